@@ -0,0 +1,671 @@
+//! Verify installed gems before skipping `bundle install`
+//!
+//! When the `Gemfile`, `Gemfile.lock`, and environment have not changed, `bundle install`
+//! is skipped. That assumes the restored `gems` layer is complete. This module performs the
+//! equivalent of `bundle check` natively, without booting Ruby: every spec the lockfile requires
+//! for the active groups must have a gemspec on disk and, if it has native extensions, a marker
+//! that they were built.
+//!
+//! Groups live in the `Gemfile` (not the lockfile) which is Ruby code. It's parsed here with
+//! line based heuristics. When a dependency's group cannot be determined it is treated as
+//! required. Erring on that side means the worst outcome is running `bundle install` when it
+//! could have been skipped.
+use bullet_stream::style;
+use commons::gemfile_lock::{GemfileLock, LockedSource, LockedSpec};
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+/// A spec required by the lockfile that is not usable from disk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct MissingGem {
+    pub(crate) name: String,
+    pub(crate) version: String,
+    pub(crate) reason: MissingReason,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum MissingReason {
+    /// No gemspec (or git checkout) found
+    NotInstalled,
+    /// Gemspec declares native extensions but they were never successfully built
+    ExtensionsNotBuilt,
+}
+
+impl Display for MissingGem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let gem = style::value(format!("{} {}", self.name, self.version));
+        match self.reason {
+            MissingReason::NotInstalled => write!(f, "{gem}"),
+            MissingReason::ExtensionsNotBuilt => write!(f, "{gem} (extensions not built)"),
+        }
+    }
+}
+
+/// Returns the gems required by the lockfile for the active groups that are
+/// not installed in any of the given gem directories
+///
+/// A gem directory is what rubygems calls a "gem home", it contains `specifications/`,
+/// `gems/`, `extensions/` and for git sources `bundler/gems/`.
+pub(crate) fn missing_gems(
+    gemfile_lock: &GemfileLock,
+    gemfile: &str,
+    without: &[String],
+    gem_dirs: &[PathBuf],
+) -> Vec<MissingGem> {
+    let extensions_re = Regex::new(r#"\.extensions = \[\s*""#).expect("clippy checked");
+    let mut missing = Vec::new();
+    for specs in required_specs(gemfile_lock, gemfile, without) {
+        let Some(first) = specs.first() else {
+            continue;
+        };
+        let result = match &first.source {
+            LockedSource::Path { .. } => None,
+            LockedSource::Git { remote, revision } => {
+                let checkout = git_checkout_name(remote, revision);
+                (!gem_dirs
+                    .iter()
+                    .any(|dir| dir.join("bundler").join("gems").join(&checkout).is_dir()))
+                .then_some(MissingReason::NotInstalled)
+            }
+            LockedSource::Rubygems { .. } => installed_state(&specs, gem_dirs, &extensions_re),
+        };
+
+        if let Some(reason) = result {
+            missing.push(MissingGem {
+                name: first.name.clone(),
+                version: first.version.clone(),
+                reason,
+            });
+        }
+    }
+
+    missing
+}
+
+/// True when `vendor/cache` (or another cache dir) holds packaged `.gem` files
+pub(crate) fn has_cached_gems(cache_dir: &Path) -> bool {
+    fs_err::read_dir(cache_dir).is_ok_and(|read_dir| {
+        read_dir
+            .filter_map(Result::ok)
+            .any(|entry| entry.path().extension().is_some_and(|ext| ext == "gem"))
+    })
+}
+
+/// Returns the gems required by the lockfile for the active groups that are not
+/// packaged in the cache dir, as written by `bundle cache`
+///
+/// Without them `bundle install --local` fails. Gems from `path:` sources are not cached.
+pub(crate) fn missing_from_cache(
+    gemfile_lock: &GemfileLock,
+    gemfile: &str,
+    without: &[String],
+    cache_dir: &Path,
+) -> Vec<MissingGem> {
+    required_specs(gemfile_lock, gemfile, without)
+        .into_iter()
+        .filter(|specs| match &specs[0].source {
+            LockedSource::Path { .. } => false,
+            LockedSource::Git { remote, revision } => {
+                !cache_dir.join(git_checkout_name(remote, revision)).is_dir()
+            }
+            LockedSource::Rubygems { .. } => !specs.iter().any(|spec| {
+                cache_dir
+                    .join(format!("{}.gem", spec.full_name()))
+                    .is_file()
+            }),
+        })
+        .map(|specs| MissingGem {
+            name: specs[0].name.clone(),
+            version: specs[0].version.clone(),
+            reason: MissingReason::NotInstalled,
+        })
+        .collect()
+}
+
+/// Names of gems that are only required through the `build_only` groups
+///
+/// Gems that are also needed by another active group are not included.
+pub(crate) fn build_only_gems(
+    gemfile_lock: &GemfileLock,
+    gemfile: &str,
+    without: &[String],
+    build_only: &[String],
+) -> Vec<String> {
+    let runtime_without = [without, build_only].concat();
+    let runtime = required_specs(gemfile_lock, gemfile, &runtime_without)
+        .into_iter()
+        .map(|specs| specs[0].name.as_str())
+        .collect::<HashSet<&str>>();
+
+    required_specs(gemfile_lock, gemfile, without)
+        .into_iter()
+        .map(|specs| specs[0].name.as_str())
+        .filter(|name| !runtime.contains(name))
+        .map(String::from)
+        .collect()
+}
+
+/// Gem directories directly inside a parent such as `<layer>/ruby/` which holds `<layer>/ruby/3.2.0/`
+pub(crate) fn gem_dirs_in(parent: &Path) -> Vec<PathBuf> {
+    fs_err::read_dir(parent)
+        .map(|read_dir| {
+            read_dir
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .filter(|path| path.is_dir())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Any platform variant being installed satisfies the lockfile
+fn installed_state(
+    specs: &[&LockedSpec],
+    gem_dirs: &[PathBuf],
+    extensions_re: &Regex,
+) -> Option<MissingReason> {
+    let mut reason = MissingReason::NotInstalled;
+    for spec in specs {
+        let full_name = spec.full_name();
+        for dir in gem_dirs {
+            let specifications = dir.join("specifications");
+            if specifications
+                .join("default")
+                .join(format!("{full_name}.gemspec"))
+                .is_file()
+            {
+                return None;
+            }
+
+            // An interrupted install can leave the gemspec without the gem's files
+            let gemspec = specifications.join(format!("{full_name}.gemspec"));
+            if gemspec.is_file() && dir.join("gems").join(&full_name).is_dir() {
+                if !has_extensions(&gemspec, extensions_re) || extensions_built(dir, &full_name) {
+                    return None;
+                }
+                reason = MissingReason::ExtensionsNotBuilt;
+            }
+        }
+    }
+    Some(reason)
+}
+
+fn has_extensions(gemspec: &Path, extensions_re: &Regex) -> bool {
+    fs_err::read_to_string(gemspec).map_or(true, |contents| extensions_re.is_match(&contents))
+}
+
+/// Rubygems writes `gem.build_complete` to `extensions/<platform>/<abi>/<full_name>/`
+/// after successfully compiling
+fn extensions_built(gem_dir: &Path, full_name: &str) -> bool {
+    let pattern = format!(
+        "{}/extensions/*/*/{}/gem.build_complete",
+        glob::Pattern::escape(&gem_dir.to_string_lossy()),
+        glob::Pattern::escape(full_name)
+    );
+    glob::glob(&pattern).is_ok_and(|mut paths| paths.any(|path| path.is_ok()))
+}
+
+/// Mirrors bundler's `Bundler::Source::Git#base_name` and `shortref_for_path`
+pub(crate) fn git_checkout_name(remote: &str, revision: &str) -> String {
+    let base = remote
+        .trim_end_matches('/')
+        .rsplit(['/', ':'])
+        .next()
+        .unwrap_or(remote);
+    let base = base.strip_suffix(".git").unwrap_or(base);
+    let short: String = revision.chars().take(12).collect();
+
+    format!("{base}-{short}")
+}
+
+/// Lockfile specs (grouped by name) reachable from the `DEPENDENCIES` that are not excluded
+fn required_specs<'a>(
+    gemfile_lock: &'a GemfileLock,
+    gemfile: &str,
+    without: &[String],
+) -> Vec<Vec<&'a LockedSpec>> {
+    let groups = GemfileGroups::from_gemfile(gemfile);
+    let mut by_name: HashMap<&str, Vec<&LockedSpec>> = HashMap::new();
+    for spec in &gemfile_lock.specs {
+        by_name.entry(spec.name.as_str()).or_default().push(spec);
+    }
+
+    let is_path = |name: &str| {
+        by_name.get(name).is_some_and(|specs| {
+            specs
+                .iter()
+                .any(|spec| matches!(spec.source, LockedSource::Path { .. }))
+        })
+    };
+
+    let mut queue = gemfile_lock
+        .dependencies
+        .iter()
+        .map(String::as_str)
+        .filter(|name| is_path(name) || !groups.excluded(name, without))
+        .collect::<Vec<&str>>();
+    let mut seen = HashSet::new();
+    let mut out = Vec::new();
+    while let Some(name) = queue.pop() {
+        if !seen.insert(name) {
+            continue;
+        }
+        if let Some(specs) = by_name.get(name) {
+            queue.extend(
+                specs
+                    .iter()
+                    .flat_map(|spec| spec.dependencies.iter().map(String::as_str)),
+            );
+            out.push(specs.clone());
+        }
+    }
+    out.sort_by(|a, b| a[0].name.cmp(&b[0].name));
+    out
+}
+
+/// Groups each gem was declared in, parsed from a `Gemfile`
+#[derive(Debug, Default, PartialEq, Eq)]
+struct GemfileGroups {
+    gems: HashMap<String, Vec<String>>,
+    /// When the Gemfile calls `gemspec`, development dependencies from the gemspec
+    /// are listed in the lockfile, but not the Gemfile. They go to this group.
+    gemspec_group: Option<String>,
+}
+
+impl GemfileGroups {
+    fn from_gemfile(gemfile: &str) -> Self {
+        let group_re = Regex::new(r"^group\b\s*\(?(.*?)\)?\s*do\b").expect("clippy checked");
+        let gem_re = Regex::new(r#"^gem\b\s*\(?\s*["']([^"']+)["'](.*)$"#).expect("clippy checked");
+        let inline_re =
+            Regex::new(r#"(?:\bgroups?:|:groups?\s*=>)\s*(\[[^\]]*\]|:\w+|["']\w+["'])"#)
+                .expect("clippy checked");
+        let gemspec_re = Regex::new(r"^gemspec\b(.*)$").expect("clippy checked");
+        let gemspec_group_re =
+            Regex::new(r#"development_group:\s*:?["']?(\w+)"#).expect("clippy checked");
+        let opens_block_re = Regex::new(
+            r"(^(if|unless|case|while|until|begin|def|class|module)\b)|\bdo(\s*\|[^|]*\|)?$",
+        )
+        .expect("clippy checked");
+        let symbols = Symbols::new();
+
+        let mut out = GemfileGroups::default();
+        let mut stack: Vec<Vec<String>> = Vec::new();
+        for line in gemfile.lines() {
+            let line = strip_comment(line).trim();
+
+            if let Some(captures) = group_re.captures(line) {
+                stack.push(symbols.parse(&captures[1]));
+            } else if line == "end" || line.starts_with("end ") || line.starts_with("end.") {
+                stack.pop();
+            } else if let Some(captures) = gem_re.captures(line) {
+                let mut groups = stack.concat();
+                if let Some(inline) = inline_re.captures(&captures[2]) {
+                    groups.extend(symbols.parse(&inline[1]));
+                }
+                if groups.is_empty() {
+                    groups.push(String::from("default"));
+                }
+                out.gems
+                    .entry(captures[1].to_string())
+                    .or_default()
+                    .extend(groups);
+            } else if let Some(captures) = gemspec_re.captures(line) {
+                out.gemspec_group = Some(
+                    gemspec_group_re
+                        .captures(&captures[1])
+                        .map_or_else(|| String::from("development"), |c| c[1].to_string()),
+                );
+            } else if opens_block_re.is_match(line) {
+                stack.push(Vec::new());
+            }
+        }
+
+        out
+    }
+
+    /// Bundler only excludes a gem when every group it belongs to is excluded
+    fn excluded(&self, name: &str, without: &[String]) -> bool {
+        let groups = match (self.gems.get(name), &self.gemspec_group) {
+            (Some(groups), _) => groups.clone(),
+            (None, Some(group)) => vec![group.clone()],
+            (None, None) => return false,
+        };
+
+        groups.iter().all(|group| without.contains(group))
+    }
+}
+
+/// Extracts group names from `:a, :b`, `["a", "b"]`, or `%i[a b]`
+///
+/// Keyword options such as `optional: true` are ignored.
+struct Symbols {
+    option_re: Regex,
+    symbol_re: Regex,
+}
+
+impl Symbols {
+    fn new() -> Self {
+        Self {
+            option_re: Regex::new(r"\b\w+:\s+[^,]+").expect("clippy checked"),
+            symbol_re: Regex::new(r#":(\w+)|["'](\w+)["']|\b(\w+)\b"#).expect("clippy checked"),
+        }
+    }
+
+    fn parse(&self, input: &str) -> Vec<String> {
+        let input = self.option_re.replace_all(input.trim(), "");
+        let input = input.trim_start_matches("%i").trim_start_matches("%w");
+        self.symbol_re
+            .captures_iter(input)
+            .filter_map(|c| c.get(1).or_else(|| c.get(2)).or_else(|| c.get(3)))
+            .map(|m| m.as_str().to_string())
+            .collect()
+    }
+}
+
+/// Removes a trailing `# comment` that is not inside of a string
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    for (index, char) in line.char_indices() {
+        match (char, quote) {
+            ('"' | '\'', None) => quote = Some(char),
+            (c, Some(q)) if c == q => quote = None,
+            ('#', None) => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::str::FromStr;
+
+    const LOCKFILE: &str = r"
+GIT
+  remote: https://github.com/heroku/my_git_gem.git
+  revision: 0123456789abcdef0123456789abcdef01234567
+  specs:
+    my_git_gem (0.1.0)
+
+GEM
+  remote: https://rubygems.org/
+  specs:
+    json (2.7.1)
+    nokogiri (1.16.0)
+      racc (~> 1.4)
+    nokogiri (1.16.0-x86_64-linux)
+      racc (~> 1.4)
+    pry (0.14.2)
+    racc (1.7.3)
+    rack (3.0.8)
+
+PLATFORMS
+  ruby
+  x86_64-linux
+
+DEPENDENCIES
+  json
+  my_git_gem!
+  nokogiri
+  pry
+  rack
+";
+
+    const GEMFILE: &str = r#"
+source "https://rubygems.org"
+
+gem "rack"
+gem 'nokogiri' # parses "html"
+gem "json"
+gem "my_git_gem", git: "https://github.com/heroku/my_git_gem.git"
+
+group :development, :test do
+  gem "pry"
+end
+"#;
+
+    fn without() -> Vec<String> {
+        vec![String::from("development"), String::from("test")]
+    }
+
+    fn write_spec(gem_dir: &Path, full_name: &str, contents: &str) {
+        fs_err::create_dir_all(gem_dir.join("gems").join(full_name)).unwrap();
+        let specifications = gem_dir.join("specifications");
+        fs_err::create_dir_all(&specifications).unwrap();
+        fs_err::write(
+            specifications.join(format!("{full_name}.gemspec")),
+            contents,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_gemfile_groups() {
+        let groups = GemfileGroups::from_gemfile(
+            r#"
+source "https://rubygems.org"
+gemspec development_group: :dev
+
+gem "rails"
+gem "rspec", group: :test
+gem "debug", groups: [:development, :test]
+gem "rubocop", :group => "lint"
+
+group :development do
+  if ENV["CI"]
+    gem "ci_reporter"
+  end
+
+  platforms :mri do
+    gem "byebug"
+  end
+end
+
+group(:assets) do
+  gem "sassc"
+end
+
+group :docs, optional: true do
+  gem "yard"
+end
+"#,
+        );
+
+        assert_eq!(groups.gemspec_group, Some(String::from("dev")));
+        assert_eq!(groups.gems["rails"], vec!["default"]);
+        assert_eq!(groups.gems["rspec"], vec!["test"]);
+        assert_eq!(groups.gems["debug"], vec!["development", "test"]);
+        assert_eq!(groups.gems["rubocop"], vec!["lint"]);
+        assert_eq!(groups.gems["ci_reporter"], vec!["development"]);
+        assert_eq!(groups.gems["byebug"], vec!["development"]);
+        assert_eq!(groups.gems["sassc"], vec!["assets"]);
+        assert_eq!(groups.gems["yard"], vec!["docs"]);
+
+        assert!(groups.excluded("debug", &without()));
+        assert!(!groups.excluded("rails", &without()));
+        assert!(!groups.excluded("rubocop", &without()));
+        assert!(groups.excluded("only_in_gemspec", &[String::from("dev")]));
+    }
+
+    #[test]
+    fn test_strip_comment() {
+        assert_eq!(strip_comment(r#"gem "a" # comment"#), r#"gem "a" "#);
+        assert_eq!(strip_comment(r#"gem "a#b""#), r#"gem "a#b""#);
+    }
+
+    #[test]
+    fn test_git_checkout_name() {
+        assert_eq!(
+            git_checkout_name(
+                "https://github.com/heroku/my_git_gem.git",
+                "0123456789abcdef0123456789abcdef01234567"
+            ),
+            "my_git_gem-0123456789ab"
+        );
+        assert_eq!(
+            git_checkout_name("git@github.com:heroku/other", "abc"),
+            "other-abc"
+        );
+    }
+
+    #[test]
+    fn test_missing_gems() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let layer_gems = tmpdir.path().join("gems").join("ruby").join("3.2.0");
+        let ruby_gems = tmpdir.path().join("ruby").join("3.2.0");
+        let gem_dirs = vec![layer_gems.clone(), ruby_gems.clone()];
+        let gemfile_lock = GemfileLock::from_str(LOCKFILE).unwrap();
+
+        let missing = missing_gems(&gemfile_lock, GEMFILE, &without(), &gem_dirs);
+        assert_eq!(
+            missing
+                .iter()
+                .map(|gem| gem.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["json", "my_git_gem", "nokogiri", "racc", "rack"]
+        );
+
+        // Default gems that ship with Ruby count as installed
+        fs_err::create_dir_all(ruby_gems.join("specifications").join("default")).unwrap();
+        fs_err::write(
+            ruby_gems
+                .join("specifications")
+                .join("default")
+                .join("json-2.7.1.gemspec"),
+            "",
+        )
+        .unwrap();
+        fs_err::create_dir_all(
+            layer_gems
+                .join("bundler")
+                .join("gems")
+                .join("my_git_gem-0123456789ab"),
+        )
+        .unwrap();
+        write_spec(&layer_gems, "rack-3.0.8", "");
+        // Only one platform variant needs to be installed
+        write_spec(&layer_gems, "nokogiri-1.16.0-x86_64-linux", "");
+        write_spec(
+            &layer_gems,
+            "racc-1.7.3",
+            r#"s.extensions = ["ext/racc/cparse/extconf.rb".freeze]"#,
+        );
+
+        let missing = missing_gems(&gemfile_lock, GEMFILE, &without(), &gem_dirs);
+        assert_eq!(
+            missing,
+            vec![MissingGem {
+                name: String::from("racc"),
+                version: String::from("1.7.3"),
+                reason: MissingReason::ExtensionsNotBuilt,
+            }]
+        );
+
+        let build_complete = layer_gems
+            .join("extensions")
+            .join("x86_64-linux")
+            .join("3.2.0")
+            .join("racc-1.7.3");
+        fs_err::create_dir_all(&build_complete).unwrap();
+        fs_err::write(build_complete.join("gem.build_complete"), "").unwrap();
+
+        assert_eq!(
+            missing_gems(&gemfile_lock, GEMFILE, &without(), &gem_dirs),
+            Vec::new()
+        );
+
+        // Excluded groups are required when no longer excluded
+        assert_eq!(
+            missing_gems(&gemfile_lock, GEMFILE, &[], &gem_dirs)
+                .iter()
+                .map(|gem| gem.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["pry"]
+        );
+    }
+
+    #[test]
+    fn test_missing_gem_files() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let layer_gems = tmpdir.path().join("gems").join("ruby").join("3.2.0");
+        let gem_dirs = vec![layer_gems.clone()];
+        let gemfile_lock = GemfileLock::from_str(LOCKFILE).unwrap();
+        let rack = |missing: Vec<MissingGem>| {
+            missing
+                .into_iter()
+                .find(|gem| gem.name == "rack")
+                .map(|gem| gem.reason)
+        };
+
+        write_spec(&layer_gems, "rack-3.0.8", "");
+        assert_eq!(
+            rack(missing_gems(&gemfile_lock, GEMFILE, &without(), &gem_dirs)),
+            None
+        );
+
+        // Gemspec without the gem's files, e.g. an interrupted install
+        fs_err::remove_dir_all(layer_gems.join("gems").join("rack-3.0.8")).unwrap();
+        assert_eq!(
+            rack(missing_gems(&gemfile_lock, GEMFILE, &without(), &gem_dirs)),
+            Some(MissingReason::NotInstalled)
+        );
+    }
+
+    #[test]
+    fn test_missing_from_cache() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let cache_dir = tmpdir.path().join("vendor").join("cache");
+        let gemfile_lock = GemfileLock::from_str(LOCKFILE).unwrap();
+        assert!(!has_cached_gems(&cache_dir));
+
+        fs_err::create_dir_all(cache_dir.join("my_git_gem-0123456789ab")).unwrap();
+        for gem in [
+            "json-2.7.1.gem",
+            "nokogiri-1.16.0-x86_64-linux.gem",
+            "rack-3.0.8.gem",
+        ] {
+            fs_err::write(cache_dir.join(gem), "").unwrap();
+        }
+        assert!(has_cached_gems(&cache_dir));
+
+        // Excluded groups (`pry`) are not required, any platform variant satisfies the lockfile
+        assert_eq!(
+            missing_from_cache(&gemfile_lock, GEMFILE, &without(), &cache_dir),
+            vec![MissingGem {
+                name: String::from("racc"),
+                version: String::from("1.7.3"),
+                reason: MissingReason::NotInstalled,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_build_only_gems() {
+        let gemfile_lock = GemfileLock::from_str(LOCKFILE).unwrap();
+        let gemfile = r#"
+gem "rack"
+gem "json"
+gem "my_git_gem", git: "https://github.com/heroku/my_git_gem.git"
+
+group :assets do
+  gem "nokogiri"
+  gem "json"
+end
+
+group :development, :test do
+  gem "pry"
+end
+"#;
+        let assets = vec![String::from("assets")];
+
+        // Dependencies of build-only gems are build-only, json is also needed at runtime
+        assert_eq!(
+            build_only_gems(&gemfile_lock, gemfile, &without(), &assets),
+            vec!["nokogiri", "racc"]
+        );
+        assert!(build_only_gems(&gemfile_lock, GEMFILE, &without(), &assets).is_empty());
+    }
+}
@@ -0,0 +1,137 @@
+//! Generate a `Gemfile.lock` for apps that only have a `Gemfile`
+//!
+//! Detect passes with only a `Gemfile`. Without a lockfile the versions of gems are resolved
+//! during the build with `bundle lock`, so two builds of the same commit can install different
+//! gems. The target platform is added so gems with precompiled native extensions resolve for
+//! the image rather than only the generic `ruby` platform.
+//!
+//! The lockfile is generated after Ruby and bundler are installed. Since the Ruby version
+//! normally comes from the lockfile, a `ruby "x.y.z"` line in the `Gemfile` is used instead.
+//!
+//! Set `HEROKU_BUNDLE_REQUIRE_LOCKFILE=1` to fail the build when the lockfile is missing.
+use crate::redact::RedactedStdout;
+use crate::RubyBuildpackError;
+use bullet_stream::state::SubBullet;
+use bullet_stream::{style, Print};
+use fun_run::{self, CommandWithName};
+use libcnb::Env;
+use regex::Regex;
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+
+/// Fail the build instead of generating a `Gemfile.lock`
+pub(crate) const REQUIRE_LOCKFILE_ENV_KEY: &str = "HEROKU_BUNDLE_REQUIRE_LOCKFILE";
+
+pub(crate) fn lockfile_required(env: &Env) -> bool {
+    env.get(REQUIRE_LOCKFILE_ENV_KEY).is_some_and(|value| {
+        matches!(
+            value.to_string_lossy().trim().to_lowercase().as_str(),
+            "1" | "true"
+        )
+    })
+}
+
+/// An exact version from `ruby "3.3.0"` in the `Gemfile`
+///
+/// Requirements such as `ruby "~> 3.3"` need to be resolved by bundler and are ignored,
+/// the default Ruby version is used instead.
+pub(crate) fn gemfile_ruby_version(gemfile: &str) -> Option<String> {
+    let ruby_re =
+        Regex::new(r#"(?m)^\s*ruby\s*\(?\s*["'](\d+\.\d+\.\d+)["']"#).expect("clippy checked");
+    ruby_re
+        .captures(gemfile)
+        .map(|captures| captures[1].to_string())
+}
+
+/// Rubygems platform for a CNB target architecture
+fn target_platform(arch: &str) -> Option<&'static str> {
+    match arch {
+        "amd64" => Some("x86_64-linux"),
+        "arm64" => Some("aarch64-linux"),
+        _ => None,
+    }
+}
+
+/// Runs `bundle lock` and returns the contents of the generated `Gemfile.lock`
+pub(crate) fn bundle_lock(
+    mut bullet: Print<SubBullet<RedactedStdout>>,
+    env: &Env,
+    app_dir: &Path,
+    arch: &str,
+) -> Result<(Print<SubBullet<RedactedStdout>>, String), RubyBuildpackError> {
+    bullet = bullet
+        .sub_bullet(format!(
+            "{warning} No {lockfile} found, gem versions are resolved during the build",
+            warning = style::important("WARNING"),
+            lockfile = style::value("Gemfile.lock"),
+        ))
+        .sub_bullet(format!(
+            "Builds are not reproducible without a committed {lockfile}, run {bundle_lock} locally and commit the result",
+            lockfile = style::value("Gemfile.lock"),
+            bundle_lock = style::command("bundle lock"),
+        ))
+        .sub_bullet(format!(
+            "Set {key} to fail the build instead",
+            key = style::value(format!("{REQUIRE_LOCKFILE_ENV_KEY}=1"))
+        ));
+
+    let mut cmd = Command::new("bundle");
+    cmd.arg("lock");
+    if let Some(platform) = target_platform(arch) {
+        cmd.args(["--add-platform", platform]);
+    }
+    cmd.current_dir(app_dir).env_clear().envs(env);
+
+    bullet
+        .stream_with(
+            format!("Running {}", style::command(cmd.name())),
+            |stdout, stderr| cmd.stream_output(stdout, stderr),
+        )
+        .map_err(|error| fun_run::map_which_problem(error, &mut cmd, env.get("PATH").cloned()))
+        .map_err(RubyBuildpackError::BundleLockCommandError)?;
+
+    let lockfile = app_dir.join("Gemfile.lock");
+    let contents = fs_err::read_to_string(&lockfile)
+        .map_err(|error| RubyBuildpackError::MissingGemfileLock(lockfile, error))?;
+
+    // Only shown for debugging, the lockfile is already written
+    let _ = bullet.stream_with(
+        format!("Generated {}", style::value("Gemfile.lock")),
+        |mut stdout, _| stdout.write_all(contents.as_bytes()),
+    );
+
+    Ok((bullet, contents))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_gemfile_ruby_version() {
+        assert_eq!(
+            gemfile_ruby_version(
+                "source \"https://rubygems.org\"\n\nruby \"3.3.1\"\ngem \"rack\"\n"
+            ),
+            Some(String::from("3.3.1"))
+        );
+        assert_eq!(
+            gemfile_ruby_version("ruby('3.2.4')\n"),
+            Some(String::from("3.2.4"))
+        );
+        assert_eq!(gemfile_ruby_version("ruby \"~> 3.3\"\n"), None);
+        assert_eq!(gemfile_ruby_version("gem \"ruby-progressbar\"\n"), None);
+        assert_eq!(gemfile_ruby_version("# ruby \"3.3.1\"\n"), None);
+    }
+
+    #[test]
+    fn test_lockfile_required() {
+        let mut env = Env::new();
+        assert!(!lockfile_required(&env));
+        env.insert(REQUIRE_LOCKFILE_ENV_KEY, "true");
+        assert!(lockfile_required(&env));
+        env.insert(REQUIRE_LOCKFILE_ENV_KEY, "0");
+        assert!(!lockfile_required(&env));
+    }
+}
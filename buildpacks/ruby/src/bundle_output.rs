@@ -0,0 +1,191 @@
+//! Condense the output of `bundle install`
+//!
+//! Every build prints a `Using <gem> <version>` line for each gem that is already installed. For
+//! large applications these lines bury the output that matters, such as gems being compiled,
+//! post-install messages, and warnings. The [`CondensedWriter`] hides them and prints a single
+//! count instead. All other lines, and stderr, are passed through unchanged.
+//!
+//! Full output is still available on failure because the command output is captured, see
+//! [`full_output_on_failure`].
+use fun_run::CmdError;
+use libcnb::Env;
+use std::io::Write;
+
+/// When `1` or `true`, `bundle install` output is streamed without condensing
+pub(crate) const VERBOSE_ENV_KEY: &str = "HEROKU_BUNDLE_INSTALL_VERBOSE";
+
+pub(crate) fn verbose(env: &Env) -> bool {
+    env.get(VERBOSE_ENV_KEY)
+        .is_some_and(|value| matches!(value.to_str(), Some("1" | "true")))
+}
+
+/// Wraps a writer, replacing `Using` lines with a count
+///
+/// Output is line buffered. The count is written before bundler's closing
+/// `Bundle complete!` line or, if that is never seen, when the writer is dropped.
+pub(crate) struct CondensedWriter<W: Write> {
+    write: W,
+    line: Vec<u8>,
+    using: usize,
+    summarized: bool,
+}
+
+impl<W: Write> CondensedWriter<W> {
+    pub(crate) fn new(write: W) -> Self {
+        Self {
+            write,
+            line: Vec::new(),
+            using: 0,
+            summarized: false,
+        }
+    }
+
+    fn write_line(&mut self) -> std::io::Result<()> {
+        let line = std::mem::take(&mut self.line);
+        if line.starts_with(b"Using ") {
+            self.using += 1;
+            return Ok(());
+        }
+        if line.starts_with(b"Bundle complete!") {
+            self.summarize()?;
+        }
+        self.write.write_all(&line)
+    }
+
+    fn summarize(&mut self) -> std::io::Result<()> {
+        if self.summarized || self.using == 0 {
+            return Ok(());
+        }
+        self.summarized = true;
+        writeln!(
+            self.write,
+            "Using {count} already installed {gems} (set {VERBOSE_ENV_KEY}=1 to list)",
+            count = self.using,
+            gems = if self.using == 1 { "gem" } else { "gems" }
+        )
+    }
+}
+
+impl<W: Write> Write for CondensedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        for byte in buf {
+            self.line.push(*byte);
+            if *byte == b'\n' {
+                self.write_line()?;
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.write.flush()
+    }
+}
+
+impl<W: Write> Drop for CondensedWriter<W> {
+    fn drop(&mut self) {
+        if !self.line.is_empty() {
+            let _ = self.write_line();
+        }
+        let _ = self.summarize();
+        let _ = self.write.flush();
+    }
+}
+
+/// Output was condensed while streaming, show all of it when the command fails
+pub(crate) fn full_output_on_failure(error: CmdError) -> CmdError {
+    match error {
+        CmdError::NonZeroExitAlreadyStreamed(output) => match output.clone().nonzero_captured() {
+            Err(error) => error,
+            Ok(_) => CmdError::NonZeroExitAlreadyStreamed(output),
+        },
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn condense(chunks: &[&str]) -> String {
+        let mut out = Vec::new();
+        {
+            let mut writer = CondensedWriter::new(&mut out);
+            for chunk in chunks {
+                writer.write_all(chunk.as_bytes()).unwrap();
+            }
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_verbose() {
+        let mut env = Env::new();
+        assert!(!verbose(&env));
+        env.insert(VERBOSE_ENV_KEY, "1");
+        assert!(verbose(&env));
+        env.insert(VERBOSE_ENV_KEY, "true");
+        assert!(verbose(&env));
+        env.insert(VERBOSE_ENV_KEY, "0");
+        assert!(!verbose(&env));
+        env.insert(VERBOSE_ENV_KEY, "false");
+        assert!(!verbose(&env));
+    }
+
+    #[test]
+    fn test_condenses_using_lines() {
+        let actual = condense(&[
+            "Fetching gem metadata from https://rubygems.org/.........\n",
+            "Using rake 13.2.1\nUsing ra",
+            "ck 3.1.8\n",
+            "Fetching nokogiri 1.16.0\n",
+            "Installing nokogiri 1.16.0 with native extensions\n",
+            "Using puma 6.5.0\n",
+            "Post-install message from rubyzip:\n",
+            "RubyZip 3.0 is coming!\n",
+            "Bundle complete! 4 Gemfile dependencies, 5 gems now installed.\n",
+            "Bundled gems are installed into `/layers/heroku_ruby/gems`",
+        ]);
+
+        assert_eq!(
+            actual,
+            r"Fetching gem metadata from https://rubygems.org/.........
+Fetching nokogiri 1.16.0
+Installing nokogiri 1.16.0 with native extensions
+Post-install message from rubyzip:
+RubyZip 3.0 is coming!
+Using 3 already installed gems (set HEROKU_BUNDLE_INSTALL_VERBOSE=1 to list)
+Bundle complete! 4 Gemfile dependencies, 5 gems now installed.
+Bundled gems are installed into `/layers/heroku_ruby/gems`"
+        );
+    }
+
+    #[test]
+    fn test_summarizes_on_drop() {
+        assert_eq!(
+            condense(&["Using rake 13.2.1\n"]),
+            "Using 1 already installed gem (set HEROKU_BUNDLE_INSTALL_VERBOSE=1 to list)\n"
+        );
+        assert_eq!(
+            condense(&["Installing rake 13.2.1\n"]),
+            "Installing rake 13.2.1\n"
+        );
+    }
+
+    #[test]
+    fn test_full_output_on_failure() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let output = std::process::Output {
+            status: std::process::ExitStatus::from_raw(256),
+            stdout: b"Using rake 13.2.1\n".to_vec(),
+            stderr: Vec::new(),
+        };
+        let error = fun_run::nonzero_streamed(String::from("bundle install"), output).unwrap_err();
+        assert!(!error.to_string().contains("Using rake"));
+
+        let error = full_output_on_failure(error);
+        assert!(matches!(error, CmdError::NonZeroExitNotStreamed(_)));
+        assert!(error.to_string().contains("Using rake 13.2.1"));
+    }
+}
@@ -0,0 +1,222 @@
+//! Tune `bundle install` concurrency to the build container
+//!
+//! Bundler installs gems serially unless `BUNDLE_JOBS` is set, and `make` compiles native
+//! extensions serially unless `MAKEFLAGS` is set. Running too many jobs in a container with a small
+//! memory limit gets compiles OOM-killed, so the job count is bounded by both the CPU quota and
+//! the memory limit of the cgroup (v1 or v2) the build runs in.
+//!
+//! Values set by the user take precedence.
+use libcnb::Env;
+use std::path::Path;
+
+/// Native extension compiles (e.g. `nokogiri`, `grpc`) can use most of this at peak
+const MEMORY_PER_JOB: u64 = 1024 * 1024 * 1024;
+/// Bundler retries network requests this many times
+const BUNDLE_RETRY: &str = "3";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ContainerLimits {
+    pub(crate) cpus: usize,
+    pub(crate) memory: Option<u64>,
+}
+
+impl ContainerLimits {
+    pub(crate) fn detect() -> Self {
+        Self::from_root(
+            Path::new("/"),
+            std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get),
+        )
+    }
+
+    /// Reads limits from `sys/fs/cgroup` and `proc/meminfo` relative to `root`
+    fn from_root(root: &Path, available_cpus: usize) -> Self {
+        let cgroup = root.join("sys").join("fs").join("cgroup");
+        let read = |path: &Path| fs_err::read_to_string(path).ok();
+
+        let quota = read(&cgroup.join("cpu.max"))
+            .and_then(|contents| {
+                let (quota, period) = contents.trim().split_once(' ')?;
+                cpu_quota(quota.parse().ok()?, period.parse().ok()?)
+            })
+            .or_else(|| {
+                cpu_quota(
+                    read(&cgroup.join("cpu").join("cpu.cfs_quota_us"))?
+                        .trim()
+                        .parse()
+                        .ok()?,
+                    read(&cgroup.join("cpu").join("cpu.cfs_period_us"))?
+                        .trim()
+                        .parse()
+                        .ok()?,
+                )
+            });
+
+        let total = read(&root.join("proc").join("meminfo")).and_then(|contents| {
+            contents.lines().find_map(|line| {
+                line.strip_prefix("MemTotal:")?
+                    .trim()
+                    .strip_suffix("kB")?
+                    .trim()
+                    .parse::<u64>()
+                    .ok()
+                    .map(|kb| kb * 1024)
+            })
+        });
+        // Unlimited is "max" in v2 and a number larger than the host memory in v1
+        let limit = read(&cgroup.join("memory.max"))
+            .or_else(|| read(&cgroup.join("memory").join("memory.limit_in_bytes")))
+            .and_then(|contents| contents.trim().parse::<u64>().ok());
+
+        Self {
+            cpus: quota.map_or(available_cpus, |quota| quota.min(available_cpus)),
+            memory: match (limit, total) {
+                (Some(limit), Some(total)) => Some(limit.min(total)),
+                (limit, total) => limit.or(total),
+            },
+        }
+    }
+
+    /// Parallel jobs that fit within both the CPU and memory limits
+    pub(crate) fn jobs(&self) -> usize {
+        let memory_jobs = self.memory.map_or(usize::MAX, |memory| {
+            usize::try_from(memory / MEMORY_PER_JOB).unwrap_or(usize::MAX)
+        });
+
+        self.cpus.min(memory_jobs).max(1)
+    }
+
+    /// Adds `BUNDLE_JOBS`, `BUNDLE_RETRY`, and `MAKEFLAGS` unless already set
+    pub(crate) fn install_env(&self, env: &Env) -> Env {
+        let jobs = self.jobs().to_string();
+        let mut env = env.clone();
+        for (key, value) in [
+            ("BUNDLE_JOBS", jobs.clone()),
+            ("BUNDLE_RETRY", BUNDLE_RETRY.to_string()),
+            ("MAKEFLAGS", format!("-j{jobs}")),
+        ] {
+            if env.get(key).is_none() {
+                env.insert(key, value);
+            }
+        }
+        env
+    }
+}
+
+/// CPUs allowed by a CFS quota, a negative quota (v1) means unlimited
+fn cpu_quota(quota: i64, period: i64) -> Option<usize> {
+    if quota <= 0 || period <= 0 {
+        return None;
+    }
+    usize::try_from((quota + period - 1) / period).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GIB: u64 = 1024 * 1024 * 1024;
+
+    fn write(root: &Path, path: &str, contents: &str) {
+        let path = root.join(path);
+        fs_err::create_dir_all(path.parent().unwrap()).unwrap();
+        fs_err::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn test_cgroup_v2() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let root = tmpdir.path();
+        write(
+            root,
+            "proc/meminfo",
+            "MemTotal:       16777216 kB\nMemFree: 1 kB\n",
+        );
+        write(root, "sys/fs/cgroup/cpu.max", "250000 100000\n");
+        write(root, "sys/fs/cgroup/memory.max", &format!("{}\n", 2 * GIB));
+
+        let limits = ContainerLimits::from_root(root, 8);
+        assert_eq!(
+            limits,
+            ContainerLimits {
+                cpus: 3,
+                memory: Some(2 * GIB)
+            }
+        );
+        // Memory bound
+        assert_eq!(limits.jobs(), 2);
+
+        write(root, "sys/fs/cgroup/cpu.max", "max 100000\n");
+        write(root, "sys/fs/cgroup/memory.max", "max\n");
+        let limits = ContainerLimits::from_root(root, 8);
+        assert_eq!(
+            limits,
+            ContainerLimits {
+                cpus: 8,
+                memory: Some(16 * GIB)
+            }
+        );
+        assert_eq!(limits.jobs(), 8);
+    }
+
+    #[test]
+    fn test_cgroup_v1() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let root = tmpdir.path();
+        write(root, "proc/meminfo", "MemTotal:       4194304 kB\n");
+        write(root, "sys/fs/cgroup/cpu/cpu.cfs_quota_us", "-1\n");
+        write(root, "sys/fs/cgroup/cpu/cpu.cfs_period_us", "100000\n");
+        write(
+            root,
+            "sys/fs/cgroup/memory/memory.limit_in_bytes",
+            "9223372036854771712\n",
+        );
+
+        let limits = ContainerLimits::from_root(root, 2);
+        assert_eq!(
+            limits,
+            ContainerLimits {
+                cpus: 2,
+                memory: Some(4 * GIB)
+            }
+        );
+        assert_eq!(limits.jobs(), 2);
+    }
+
+    #[test]
+    fn test_no_limits_found() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let limits = ContainerLimits::from_root(tmpdir.path(), 4);
+
+        assert_eq!(
+            limits,
+            ContainerLimits {
+                cpus: 4,
+                memory: None
+            }
+        );
+        assert_eq!(limits.jobs(), 4);
+        assert_eq!(
+            ContainerLimits {
+                cpus: 4,
+                memory: Some(GIB / 2)
+            }
+            .jobs(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_install_env_respects_user_values() {
+        let limits = ContainerLimits {
+            cpus: 4,
+            memory: None,
+        };
+        let mut env = Env::new();
+        env.insert("BUNDLE_JOBS", "1");
+
+        let env = limits.install_env(&env);
+        assert_eq!(env.get("BUNDLE_JOBS").unwrap(), "1");
+        assert_eq!(env.get("BUNDLE_RETRY").unwrap(), "3");
+        assert_eq!(env.get("MAKEFLAGS").unwrap(), "-j4");
+    }
+}
@@ -0,0 +1,495 @@
+//! Check resolved gems and the Ruby version against known vulnerabilities
+//!
+//! Advisories are read from a directory in the format of
+//! [ruby-advisory-db](https://github.com/rubysec/ruby-advisory-db): `gems/<name>/*.yml` for gems
+//! and `rubies/ruby/*.yml` for Ruby. The directory is set with `HEROKU_BUNDLE_AUDIT_DB`, it's not
+//! fetched by the buildpack so it can be shipped with the app or mounted into the build.
+//!
+//! A gem version is vulnerable when it matches neither the `patched_versions` nor the
+//! `unaffected_versions` of an advisory. Severity comes from the CVSS score, the same way as
+//! `bundler-audit`.
+use commons::gem_requirement::GemRequirement;
+use commons::gem_version::GemVersion;
+use commons::gemfile_lock::{GemfileLock, LockedSource, ResolvedRubyVersion};
+use core::str::FromStr;
+use libcnb::Env;
+use std::collections::BTreeSet;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use yaml_rust2::{Yaml, YamlLoader};
+
+/// Directory of advisories, relative to the app or absolute
+pub(crate) const DB_ENV_KEY: &str = "HEROKU_BUNDLE_AUDIT_DB";
+/// Lowest severity that fails the build, or `none` to only warn
+pub(crate) const FAIL_ON_ENV_KEY: &str = "HEROKU_BUNDLE_AUDIT_FAIL_ON";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Severity {
+    /// No CVSS score in the advisory
+    Unknown,
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl Severity {
+    fn from_advisory(doc: &Yaml) -> Self {
+        let score = |key: &str| match &doc[key] {
+            Yaml::Real(_) => doc[key].as_f64(),
+            #[allow(clippy::cast_precision_loss)]
+            Yaml::Integer(score) => Some(*score as f64),
+            _ => None,
+        };
+
+        if let Some(score) = score("cvss_v3") {
+            match score {
+                s if s >= 9.0 => Severity::Critical,
+                s if s >= 7.0 => Severity::High,
+                s if s >= 4.0 => Severity::Medium,
+                _ => Severity::Low,
+            }
+        } else if let Some(score) = score("cvss_v2") {
+            match score {
+                s if s >= 7.0 => Severity::High,
+                s if s >= 4.0 => Severity::Medium,
+                _ => Severity::Low,
+            }
+        } else {
+            Severity::Unknown
+        }
+    }
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Severity::Unknown => "unknown",
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+            Severity::Critical => "critical",
+        })
+    }
+}
+
+/// What to do with findings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Policy {
+    /// Print findings without failing
+    Warn,
+    /// Fail on findings of this severity or higher, findings of unknown severity only warn
+    FailOn(Severity),
+}
+
+impl Policy {
+    pub(crate) fn from_env(env: &Env) -> Result<Self, AuditError> {
+        let Some(value) = env.get(FAIL_ON_ENV_KEY) else {
+            return Ok(Policy::Warn);
+        };
+        match value.to_string_lossy().trim().to_lowercase().as_str() {
+            "" | "none" => Ok(Policy::Warn),
+            "low" => Ok(Policy::FailOn(Severity::Low)),
+            "medium" => Ok(Policy::FailOn(Severity::Medium)),
+            "high" => Ok(Policy::FailOn(Severity::High)),
+            "critical" => Ok(Policy::FailOn(Severity::Critical)),
+            other => Err(AuditError::InvalidPolicy(other.to_string())),
+        }
+    }
+
+    pub(crate) fn fails(self, finding: &Finding) -> bool {
+        match self {
+            Policy::Warn => false,
+            Policy::FailOn(threshold) => {
+                finding.advisory.severity != Severity::Unknown
+                    && finding.advisory.severity >= threshold
+            }
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum AuditError {
+    #[error(
+        "Invalid {FAIL_ON_ENV_KEY} value {0:?}, expected one of none, low, medium, high, critical"
+    )]
+    InvalidPolicy(String),
+
+    #[error("Cannot read advisory database {0}: {1}")]
+    CannotReadDb(PathBuf, std::io::Error),
+
+    #[error("Invalid advisory {0}: {1}")]
+    InvalidAdvisory(PathBuf, String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Advisory {
+    /// `CVE-*`, `GHSA-*`, or the advisory file name
+    pub(crate) id: String,
+    pub(crate) title: String,
+    pub(crate) url: Option<String>,
+    pub(crate) severity: Severity,
+    pub(crate) patched_versions: Vec<String>,
+    unaffected_versions: Vec<String>,
+}
+
+impl Advisory {
+    fn from_file(path: &Path) -> Result<Self, AuditError> {
+        let invalid = |message: String| AuditError::InvalidAdvisory(path.to_path_buf(), message);
+        let contents = fs_err::read_to_string(path)
+            .map_err(|error| AuditError::CannotReadDb(path.to_path_buf(), error))?;
+        let docs =
+            YamlLoader::load_from_str(&contents).map_err(|error| invalid(error.to_string()))?;
+        let doc = docs
+            .first()
+            .ok_or_else(|| invalid(String::from("empty file")))?;
+
+        let string = |key: &str| match &doc[key] {
+            Yaml::String(value) => Some(value.clone()),
+            Yaml::Integer(value) => Some(value.to_string()),
+            _ => None,
+        };
+        let requirements = |key: &str| -> Result<Vec<String>, AuditError> {
+            doc[key].as_vec().map_or(Ok(Vec::new()), |values| {
+                values
+                    .iter()
+                    .map(|value| {
+                        value
+                            .as_str()
+                            .map(String::from)
+                            .ok_or_else(|| invalid(format!("{key} must be a list of strings")))
+                    })
+                    .collect()
+            })
+        };
+        let patched_versions = requirements("patched_versions")?;
+        let unaffected_versions = requirements("unaffected_versions")?;
+        for requirement in patched_versions.iter().chain(&unaffected_versions) {
+            GemRequirement::from_str(requirement)
+                .map_err(|error| invalid(format!("{requirement:?} {error:?}")))?;
+        }
+
+        Ok(Advisory {
+            id: string("cve")
+                .map(|cve| format!("CVE-{cve}"))
+                .or_else(|| string("ghsa").map(|ghsa| format!("GHSA-{ghsa}")))
+                .or_else(|| {
+                    path.file_stem()
+                        .map(|stem| stem.to_string_lossy().into_owned())
+                })
+                .unwrap_or_default(),
+            title: string("title").unwrap_or_default().trim().to_string(),
+            url: string("url"),
+            severity: Severity::from_advisory(doc),
+            patched_versions,
+            unaffected_versions,
+        })
+    }
+
+    fn is_vulnerable(&self, version: &GemVersion) -> bool {
+        let matches_any = |requirements: &[String]| {
+            requirements.iter().any(|requirement| {
+                GemRequirement::from_str(requirement)
+                    .is_ok_and(|requirement| requirement.is_satisfied_by(version))
+            })
+        };
+
+        !matches_any(&self.patched_versions) && !matches_any(&self.unaffected_versions)
+    }
+}
+
+/// A gem (or Ruby) version matched by an advisory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Finding {
+    pub(crate) name: String,
+    pub(crate) version: String,
+    pub(crate) advisory: Advisory,
+}
+
+#[derive(Debug)]
+pub(crate) struct AuditReport {
+    /// Number of gems and Ruby versions checked
+    pub(crate) checked: usize,
+    pub(crate) findings: Vec<Finding>,
+    /// Advisories that could not be parsed, for example from a newer schema
+    pub(crate) skipped: Vec<AuditError>,
+}
+
+/// Advisory database configured by the user, if any
+pub(crate) fn db_from_env(env: &Env, app_dir: &Path) -> Option<PathBuf> {
+    env.get(DB_ENV_KEY)
+        .filter(|value| !value.is_empty())
+        .map(|value| app_dir.join(value))
+}
+
+/// Checks gems from rubygems and git sources, and the Ruby version, against the database
+///
+/// Gems from `path:` sources are part of the app and not checked. Invalid advisories are
+/// skipped and returned in [`AuditReport::skipped`] so one bad file doesn't fail every build.
+pub(crate) fn audit(
+    db: &Path,
+    gemfile_lock: &GemfileLock,
+    ruby_version: &ResolvedRubyVersion,
+) -> Result<AuditReport, AuditError> {
+    if !db.is_dir() {
+        return Err(AuditError::CannotReadDb(
+            db.to_path_buf(),
+            std::io::Error::new(std::io::ErrorKind::NotFound, "directory not found"),
+        ));
+    }
+
+    let gems = gemfile_lock
+        .specs
+        .iter()
+        .filter(|spec| !matches!(spec.source, LockedSource::Path { .. }))
+        .map(|spec| (spec.name.as_str(), spec.version.as_str()))
+        .collect::<BTreeSet<(&str, &str)>>();
+
+    let mut findings = Vec::new();
+    let mut skipped = Vec::new();
+    for (name, version) in &gems {
+        findings.extend(check(
+            &db.join("gems").join(name),
+            name,
+            version,
+            &mut skipped,
+        )?);
+    }
+    findings.extend(check(
+        &db.join("rubies").join("ruby"),
+        "ruby",
+        &ruby_version.0,
+        &mut skipped,
+    )?);
+
+    Ok(AuditReport {
+        checked: gems.len() + 1,
+        findings,
+        skipped,
+    })
+}
+
+/// Checks a version against every advisory in a directory, a missing directory has no advisories
+///
+/// Advisories that cannot be read are added to `skipped`.
+fn check(
+    dir: &Path,
+    name: &str,
+    version: &str,
+    skipped: &mut Vec<AuditError>,
+) -> Result<Vec<Finding>, AuditError> {
+    let Ok(parsed) = GemVersion::from_str(version) else {
+        return Ok(Vec::new());
+    };
+    let mut paths = match fs_err::read_dir(dir) {
+        Ok(read_dir) => read_dir
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "yml"))
+            .collect::<Vec<PathBuf>>(),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(AuditError::CannotReadDb(dir.to_path_buf(), error)),
+    };
+    paths.sort();
+
+    let mut findings = Vec::new();
+    for path in &paths {
+        match Advisory::from_file(path) {
+            Ok(advisory) if advisory.is_vulnerable(&parsed) => findings.push(Finding {
+                name: name.to_string(),
+                version: version.to_string(),
+                advisory,
+            }),
+            Ok(_) => {}
+            Err(error) => skipped.push(error),
+        }
+    }
+    Ok(findings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_advisory(db: &Path, dir: &str, file: &str, contents: &str) {
+        let dir = db.join(dir);
+        fs_err::create_dir_all(&dir).unwrap();
+        fs_err::write(dir.join(file), contents).unwrap();
+    }
+
+    #[test]
+    fn test_audit() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let db = tmpdir.path();
+        write_advisory(
+            db,
+            "gems/rack",
+            "CVE-2024-26146.yml",
+            r#"
+---
+gem: rack
+cve: 2024-26146
+url: https://github.com/rack/rack/security/advisories
+title: Possible Denial of Service Vulnerability in Rack Header Parsing
+cvss_v3: 5.3
+patched_versions:
+  - "~> 2.0.9, >= 2.0.9.4"
+  - "~> 2.2.8, >= 2.2.8.1"
+  - ">= 3.0.9.1"
+"#,
+        );
+        write_advisory(
+            db,
+            "gems/rack",
+            "OSVDB-1.yml",
+            r#"
+gem: rack
+title: Only affects old versions
+unaffected_versions:
+  - ">= 2.0"
+"#,
+        );
+        write_advisory(
+            db,
+            "rubies/ruby",
+            "CVE-2024-27282.yml",
+            r#"
+engine: ruby
+cve: 2024-27282
+title: Arbitrary memory address read vulnerability with Regex search
+cvss_v3: 9.8
+patched_versions:
+  - "~> 3.2.4"
+  - ">= 3.3.1"
+"#,
+        );
+        let gemfile_lock = GemfileLock::from_str(
+            r"
+GEM
+  remote: https://rubygems.org/
+  specs:
+    rack (3.0.8)
+    rake (13.1.0)
+
+PATH
+  remote: engines/rack
+  specs:
+    rack (0.1.0)
+",
+        )
+        .unwrap();
+
+        let report = audit(
+            db,
+            &gemfile_lock,
+            &ResolvedRubyVersion(String::from("3.2.2")),
+        )
+        .unwrap();
+        assert_eq!(report.checked, 3);
+        assert_eq!(
+            report
+                .findings
+                .iter()
+                .map(|finding| (
+                    finding.name.as_str(),
+                    finding.version.as_str(),
+                    finding.advisory.id.as_str(),
+                    finding.advisory.severity
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                ("rack", "3.0.8", "CVE-2024-26146", Severity::Medium),
+                ("ruby", "3.2.2", "CVE-2024-27282", Severity::Critical),
+            ]
+        );
+
+        let report = audit(
+            db,
+            &gemfile_lock,
+            &ResolvedRubyVersion(String::from("3.2.4")),
+        )
+        .unwrap();
+        assert_eq!(report.findings.len(), 1);
+
+        assert!(audit(
+            &db.join("missing"),
+            &gemfile_lock,
+            &ResolvedRubyVersion(String::from("3.2.4"))
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_audit_skips_invalid_advisories() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let db = tmpdir.path();
+        write_advisory(
+            db,
+            "gems/rack",
+            "NEWER-SCHEMA.yml",
+            r"
+gem: rack
+patched_versions:
+  - min: 3.1.0
+",
+        );
+        let gemfile_lock = GemfileLock::from_str(
+            r"
+GEM
+  remote: https://rubygems.org/
+  specs:
+    rack (3.0.8)
+",
+        )
+        .unwrap();
+
+        let report = audit(
+            db,
+            &gemfile_lock,
+            &ResolvedRubyVersion(String::from("3.2.2")),
+        )
+        .unwrap();
+        assert!(report.findings.is_empty());
+        assert_eq!(
+            report
+                .skipped
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec![format!(
+                "Invalid advisory {}: patched_versions must be a list of strings",
+                db.join("gems/rack/NEWER-SCHEMA.yml").display()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_policy() {
+        let mut env = Env::new();
+        assert_eq!(Policy::from_env(&env).unwrap(), Policy::Warn);
+        env.insert(FAIL_ON_ENV_KEY, "High");
+        let policy = Policy::from_env(&env).unwrap();
+        assert_eq!(policy, Policy::FailOn(Severity::High));
+        env.insert(FAIL_ON_ENV_KEY, "severe");
+        assert!(Policy::from_env(&env).is_err());
+
+        let finding = |severity| Finding {
+            name: String::from("rack"),
+            version: String::from("3.0.8"),
+            advisory: Advisory {
+                id: String::from("CVE-2024-26146"),
+                title: String::new(),
+                url: None,
+                severity,
+                patched_versions: Vec::new(),
+                unaffected_versions: Vec::new(),
+            },
+        };
+        assert!(!policy.fails(&finding(Severity::Medium)));
+        assert!(policy.fails(&finding(Severity::High)));
+        assert!(policy.fails(&finding(Severity::Critical)));
+        assert!(!policy.fails(&finding(Severity::Unknown)));
+        assert!(!Policy::FailOn(Severity::Low).fails(&finding(Severity::Unknown)));
+        assert!(!Policy::Warn.fails(&finding(Severity::Critical)));
+    }
+}
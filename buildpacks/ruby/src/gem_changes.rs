@@ -0,0 +1,195 @@
+//! Summarize changes to the resolved gem set between builds
+//!
+//! A snapshot of gem names and versions is stored in the `gems` layer metadata. After
+//! `bundle install` runs, the prior snapshot (even when the cache was cleared) is compared to the
+//! current one so lockfile churn is visible in the build output.
+use bullet_stream::style;
+use commons::display::SentenceList;
+use commons::gem_version::GemVersion;
+use commons::gemfile_lock::GemfileLock;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+/// Gem names mapped to their resolved version
+#[derive(Deserialize, Serialize, Debug, Clone, Eq, PartialEq, Default)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct GemSnapshot(pub(crate) BTreeMap<String, String>);
+
+impl GemSnapshot {
+    /// Platform variants of a gem share a version, they're recorded once
+    pub(crate) fn from_lockfile(gemfile_lock: &GemfileLock) -> Self {
+        Self(
+            gemfile_lock
+                .specs
+                .iter()
+                .map(|spec| (spec.name.clone(), spec.version.clone()))
+                .collect(),
+        )
+    }
+}
+
+/// A single gem's version before and after
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct VersionChange {
+    pub(crate) name: String,
+    pub(crate) old: String,
+    pub(crate) now: String,
+}
+
+/// The difference between two [`GemSnapshot`]s
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub(crate) struct GemChanges {
+    pub(crate) added: Vec<(String, String)>,
+    pub(crate) removed: Vec<(String, String)>,
+    pub(crate) upgraded: Vec<VersionChange>,
+    pub(crate) downgraded: Vec<VersionChange>,
+}
+
+impl GemChanges {
+    pub(crate) fn new(old: &GemSnapshot, now: &GemSnapshot) -> Self {
+        let mut changes = GemChanges::default();
+        for (name, version) in &now.0 {
+            match old.0.get(name) {
+                None => changes.added.push((name.clone(), version.clone())),
+                Some(old_version) if old_version != version => {
+                    let change = VersionChange {
+                        name: name.clone(),
+                        old: old_version.clone(),
+                        now: version.clone(),
+                    };
+                    if compare(old_version, version) == Ordering::Greater {
+                        changes.downgraded.push(change);
+                    } else {
+                        changes.upgraded.push(change);
+                    }
+                }
+                Some(_) => {}
+            }
+        }
+        for (name, version) in &old.0 {
+            if !now.0.contains_key(name) {
+                changes.removed.push((name.clone(), version.clone()));
+            }
+        }
+
+        changes
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.upgraded.is_empty()
+            && self.downgraded.is_empty()
+    }
+
+    /// One line per kind of change, empty kinds are omitted
+    pub(crate) fn lines(&self) -> Vec<String> {
+        let named = |list: &[(String, String)]| {
+            list.iter()
+                .map(|(name, version)| style::value(format!("{name} {version}")))
+                .collect::<Vec<String>>()
+        };
+        let versioned = |list: &[VersionChange]| {
+            list.iter()
+                .map(|change| {
+                    format!(
+                        "{} ({} to {})",
+                        style::value(&change.name),
+                        change.old,
+                        change.now
+                    )
+                })
+                .collect::<Vec<String>>()
+        };
+
+        [
+            ("Added", named(&self.added)),
+            ("Removed", named(&self.removed)),
+            ("Upgraded", versioned(&self.upgraded)),
+            ("Downgraded", versioned(&self.downgraded)),
+        ]
+        .into_iter()
+        .filter(|(_, list)| !list.is_empty())
+        .map(|(label, list)| format!("{label} {}", SentenceList::new(&list)))
+        .collect()
+    }
+}
+
+/// Versions that cannot be parsed are treated as an upgrade
+fn compare(old: &str, now: &str) -> Ordering {
+    match (GemVersion::from_str(old), GemVersion::from_str(now)) {
+        (Ok(old), Ok(now)) => old.partial_cmp(&now).unwrap_or(Ordering::Less),
+        _ => Ordering::Less,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bullet_stream::strip_ansi;
+
+    fn snapshot(gems: &[(&str, &str)]) -> GemSnapshot {
+        GemSnapshot(
+            gems.iter()
+                .map(|(name, version)| ((*name).to_string(), (*version).to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_from_lockfile() {
+        let gemfile_lock = GemfileLock::from_str(
+            r"
+GEM
+  remote: https://rubygems.org/
+  specs:
+    nokogiri (1.16.0)
+    nokogiri (1.16.0-x86_64-linux)
+    rack (3.0.8)
+",
+        )
+        .unwrap();
+
+        assert_eq!(
+            GemSnapshot::from_lockfile(&gemfile_lock),
+            snapshot(&[("nokogiri", "1.16.0"), ("rack", "3.0.8")])
+        );
+    }
+
+    #[test]
+    fn test_gem_changes() {
+        let old = snapshot(&[
+            ("puma", "6.4.0"),
+            ("rack", "3.0.8"),
+            ("rails", "7.1.0"),
+            ("sidekiq", "7.2.0"),
+        ]);
+        let now = snapshot(&[
+            ("puma", "6.4.0"),
+            ("rack", "3.0.10"),
+            ("rails", "7.0.8"),
+            ("solid_queue", "0.3.0"),
+        ]);
+
+        let changes = GemChanges::new(&old, &now);
+        assert!(!changes.is_empty());
+        assert_eq!(
+            changes
+                .lines()
+                .iter()
+                .map(strip_ansi)
+                .collect::<Vec<String>>(),
+            vec![
+                "Added `solid_queue 0.3.0`",
+                "Removed `sidekiq 7.2.0`",
+                "Upgraded `rack` (3.0.8 to 3.0.10)",
+                "Downgraded `rails` (7.1.0 to 7.0.8)",
+            ]
+        );
+
+        assert!(GemChanges::new(&now, &now).is_empty());
+        assert!(GemChanges::new(&now, &now).lines().is_empty());
+    }
+}
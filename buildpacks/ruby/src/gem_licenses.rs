@@ -0,0 +1,280 @@
+//! License report for gems installed in the `gems` layer
+//!
+//! Licenses come from the gemspec of each installed gem (see [`crate::sbom`]). Every build prints
+//! a summary grouped by license. Apps can check the licenses against a policy in `project.toml`:
+//!
+//! ```toml
+//! [com.heroku.buildpacks.ruby.licenses]
+//! allow = ["MIT", "Apache-2.0", "BSD-3-Clause", "Ruby"]
+//! deny = ["GPL-3.0"]
+//! fail = true
+//! ```
+//!
+//! A gem that declares multiple licenses can be used under any one of them, so it complies
+//! when at least one of its licenses is allowed (or `allow` is empty) and not denied. A gem
+//! that declares no license never complies. Violations only warn unless `fail = true`.
+use crate::sbom::Component;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+/// Table in `project.toml` holding the policy
+pub(crate) const POLICY_TABLE: &str = "com.heroku.buildpacks.ruby.licenses";
+/// Group name for gems that do not declare a license
+const UNKNOWN: &str = "Unknown";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct LicensePolicy {
+    #[serde(default)]
+    pub(crate) allow: Vec<String>,
+    #[serde(default)]
+    pub(crate) deny: Vec<String>,
+    /// Fail the build on violations instead of warning
+    #[serde(default)]
+    pub(crate) fail: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Violation {
+    pub(crate) name: String,
+    pub(crate) version: String,
+    pub(crate) licenses: Vec<String>,
+    pub(crate) reason: ViolationReason,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ViolationReason {
+    /// The gemspec does not declare a license
+    Unknown,
+    /// Every declared license is denied or not allowed, and at least one is denied
+    Denied,
+    /// None of the declared licenses are in the allow list
+    NotAllowed,
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let gem = bullet_stream::style::value(format!("{} {}", self.name, self.version));
+        match self.reason {
+            ViolationReason::Unknown => write!(f, "{gem} does not declare a license"),
+            ViolationReason::Denied => {
+                write!(f, "{gem} license {} is denied", label(&self.licenses))
+            }
+            ViolationReason::NotAllowed => {
+                write!(f, "{gem} license {} is not allowed", label(&self.licenses))
+            }
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum LicenseError {
+    #[error("Cannot read {0}: {1}")]
+    CannotRead(PathBuf, std::io::Error),
+
+    #[error("Cannot parse {0}: {1}")]
+    InvalidToml(PathBuf, Box<toml::de::Error>),
+
+    #[error("Invalid [{POLICY_TABLE}] in {0}: {1}")]
+    InvalidPolicy(PathBuf, Box<toml::de::Error>),
+}
+
+impl LicensePolicy {
+    /// Reads the policy from `<app_dir>/project.toml`, returns `None` when there is no policy
+    ///
+    /// Only [`LicenseError::InvalidPolicy`] means the policy table exists, a `project.toml` that
+    /// cannot be read or parsed may not have one.
+    pub(crate) fn from_app_dir(app_dir: &Path) -> Result<Option<Self>, LicenseError> {
+        let path = app_dir.join("project.toml");
+        let contents = match fs_err::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(LicenseError::CannotRead(path, error)),
+        };
+        let table = toml::from_str::<toml::Table>(&contents)
+            .map_err(|error| LicenseError::InvalidToml(path.clone(), Box::new(error)))?;
+
+        POLICY_TABLE
+            .split('.')
+            .try_fold(&toml::Value::Table(table), |value, key| value.get(key))
+            .cloned()
+            .map(|value| {
+                value
+                    .try_into::<LicensePolicy>()
+                    .map_err(|error| LicenseError::InvalidPolicy(path.clone(), Box::new(error)))
+            })
+            .transpose()
+    }
+
+    pub(crate) fn check(&self, component: &Component) -> Option<Violation> {
+        let listed = |list: &[String], license: &str| {
+            list.iter()
+                .any(|listed| listed.eq_ignore_ascii_case(license))
+        };
+        let reason = if component.licenses.is_empty() {
+            ViolationReason::Unknown
+        } else if component.licenses.iter().any(|license| {
+            !listed(&self.deny, license) && (self.allow.is_empty() || listed(&self.allow, license))
+        }) {
+            return None;
+        } else if component
+            .licenses
+            .iter()
+            .any(|license| listed(&self.deny, license))
+        {
+            ViolationReason::Denied
+        } else {
+            ViolationReason::NotAllowed
+        };
+
+        Some(Violation {
+            name: component.name.clone(),
+            version: component.version.clone(),
+            licenses: component.licenses.clone(),
+            reason,
+        })
+    }
+}
+
+/// Gem names grouped by their declared licenses, such as `MIT OR Ruby`
+pub(crate) fn summary(components: &[Component]) -> BTreeMap<String, Vec<String>> {
+    let mut groups = BTreeMap::<String, Vec<String>>::new();
+    for component in components {
+        groups
+            .entry(label(&component.licenses))
+            .or_default()
+            .push(component.name.clone());
+    }
+    groups
+}
+
+fn label(licenses: &[String]) -> String {
+    if licenses.is_empty() {
+        String::from(UNKNOWN)
+    } else {
+        licenses.join(" OR ")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sbom::ComponentKind;
+
+    fn gem(name: &str, licenses: &[&str]) -> Component {
+        Component {
+            kind: ComponentKind::Library,
+            name: name.to_string(),
+            version: String::from("1.0.0"),
+            purl: format!("pkg:gem/{name}@1.0.0"),
+            licenses: licenses.iter().map(ToString::to_string).collect(),
+            source: None,
+        }
+    }
+
+    #[test]
+    fn test_policy_from_project_toml() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        assert_eq!(LicensePolicy::from_app_dir(tmpdir.path()).unwrap(), None);
+
+        fs_err::write(
+            tmpdir.path().join("project.toml"),
+            "[_]\nschema-version = \"0.2\"\n",
+        )
+        .unwrap();
+        assert_eq!(LicensePolicy::from_app_dir(tmpdir.path()).unwrap(), None);
+
+        fs_err::write(
+            tmpdir.path().join("project.toml"),
+            "[com.heroku.buildpacks.ruby.licenses]\nallow = [\"MIT\"]\nfail = true\n",
+        )
+        .unwrap();
+        assert_eq!(
+            LicensePolicy::from_app_dir(tmpdir.path()).unwrap(),
+            Some(LicensePolicy {
+                allow: vec![String::from("MIT")],
+                deny: vec![],
+                fail: true,
+            })
+        );
+
+        fs_err::write(
+            tmpdir.path().join("project.toml"),
+            "[com.heroku.buildpacks.ruby.licenses]\nalow = [\"MIT\"]\n",
+        )
+        .unwrap();
+        assert!(matches!(
+            LicensePolicy::from_app_dir(tmpdir.path()),
+            Err(LicenseError::InvalidPolicy(_, _))
+        ));
+
+        fs_err::write(
+            tmpdir.path().join("project.toml"),
+            "[_
+",
+        )
+        .unwrap();
+        assert!(matches!(
+            LicensePolicy::from_app_dir(tmpdir.path()),
+            Err(LicenseError::InvalidToml(_, _))
+        ));
+    }
+
+    #[test]
+    fn test_check() {
+        let policy = LicensePolicy {
+            allow: vec![String::from("MIT"), String::from("Ruby")],
+            deny: vec![String::from("GPL-3.0")],
+            fail: false,
+        };
+        assert_eq!(policy.check(&gem("rack", &["mit"])), None);
+        assert_eq!(policy.check(&gem("racc", &["Ruby", "BSD-2-Clause"])), None);
+        assert_eq!(policy.check(&gem("dual", &["GPL-3.0", "MIT"])), None);
+        assert_eq!(
+            policy.check(&gem("gpl", &["GPL-3.0"])).map(|v| v.reason),
+            Some(ViolationReason::Denied)
+        );
+        assert_eq!(
+            policy
+                .check(&gem("apache", &["Apache-2.0"]))
+                .map(|v| v.reason),
+            Some(ViolationReason::NotAllowed)
+        );
+        assert_eq!(
+            policy.check(&gem("none", &[])).map(|v| v.reason),
+            Some(ViolationReason::Unknown)
+        );
+
+        let deny_only = LicensePolicy {
+            deny: vec![String::from("GPL-3.0")],
+            ..LicensePolicy::default()
+        };
+        assert_eq!(deny_only.check(&gem("apache", &["Apache-2.0"])), None);
+    }
+
+    #[test]
+    fn test_summary() {
+        let summary = summary(&[
+            gem("rack", &["MIT"]),
+            gem("racc", &["Ruby", "BSD-2-Clause"]),
+            gem("rake", &["MIT"]),
+            gem("none", &[]),
+        ]);
+        assert_eq!(
+            summary.into_iter().collect::<Vec<_>>(),
+            vec![
+                (
+                    String::from("MIT"),
+                    vec![String::from("rack"), String::from("rake")]
+                ),
+                (
+                    String::from("Ruby OR BSD-2-Clause"),
+                    vec![String::from("racc")]
+                ),
+                (String::from("Unknown"), vec![String::from("none")]),
+            ]
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use crate::redact::RedactedStdout;
 use bullet_stream::{state::SubBullet, style, Print};
 use commons::gem_version::GemVersion;
 use core::str::FromStr;
@@ -5,7 +6,6 @@ use fun_run::{CmdError, CommandWithName};
 use regex::Regex;
 use std::collections::HashMap;
 use std::ffi::OsStr;
-use std::io::Stdout;
 use std::process::Command;
 
 /// ## Gets list of an application's dependencies
@@ -22,9 +22,9 @@ pub(crate) struct GemList {
 ///
 /// Errors if the command `bundle list` is unsuccessful.
 pub(crate) fn bundle_list<T, K, V>(
-    bullet: Print<SubBullet<Stdout>>,
+    bullet: Print<SubBullet<RedactedStdout>>,
     envs: T,
-) -> Result<(Print<SubBullet<Stdout>>, GemList), CmdError>
+) -> Result<(Print<SubBullet<RedactedStdout>>, GemList), CmdError>
 where
     T: IntoIterator<Item = (K, V)>,
     K: AsRef<OsStr>,
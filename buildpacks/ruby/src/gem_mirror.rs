@@ -0,0 +1,141 @@
+//! Fetch gems from a mirror instead of the sources named in the `Gemfile`
+//!
+//! `HEROKU_BUNDLE_MIRROR` is a list of mirrors separated by spaces or commas. An entry with just
+//! a URL mirrors `https://rubygems.org`, an entry in the form `<source>=<mirror>` mirrors any
+//! other gem source:
+//!
+//! ```text
+//! HEROKU_BUNDLE_MIRROR="https://gems.internal https://gems.example.com=https://gems.internal/example"
+//! ```
+//!
+//! Each mirror becomes bundler's `BUNDLE_MIRROR__<SOURCE>` setting. The `https://rubygems.org`
+//! mirror is also used to `gem install bundler`.
+use libcnb::Env;
+use url::Url;
+
+pub(crate) const MIRROR_ENV_KEY: &str = "HEROKU_BUNDLE_MIRROR";
+const RUBYGEMS_SOURCE: &str = "https://rubygems.org/";
+/// Bundler's mirror setting for `https://rubygems.org`, whether set by the user or from `HEROKU_BUNDLE_MIRROR`
+pub(crate) const RUBYGEMS_MIRROR_KEY: &str = "BUNDLE_MIRROR__HTTPS://RUBYGEMS__ORG/";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Mirror {
+    pub(crate) source: Url,
+    pub(crate) mirror: Url,
+}
+
+impl Mirror {
+    /// Bundler setting for this mirror, for example `BUNDLE_MIRROR__HTTPS://RUBYGEMS__ORG/`
+    pub(crate) fn bundle_key(&self) -> String {
+        format!(
+            "BUNDLE_MIRROR__{}",
+            self.source
+                .as_str()
+                .to_uppercase()
+                .replace('-', "___")
+                .replace('.', "__")
+        )
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum MirrorError {
+    #[error("Invalid URL {0:?} in {MIRROR_ENV_KEY}: {1}")]
+    InvalidUrl(String, url::ParseError),
+}
+
+/// Parses mirrors from `HEROKU_BUNDLE_MIRROR`, empty when not set
+pub(crate) fn from_env(env: &Env) -> Result<Vec<Mirror>, MirrorError> {
+    env.get(MIRROR_ENV_KEY)
+        .map(|value| parse(&value.to_string_lossy()))
+        .transpose()
+        .map(Option::unwrap_or_default)
+}
+
+/// Adds a `BUNDLE_MIRROR__*` setting for each mirror, unless the user already set it
+pub(crate) fn bundle_env(mirrors: &[Mirror], env: &Env) -> Env {
+    let mut env = env.clone();
+    for mirror in mirrors {
+        let key = mirror.bundle_key();
+        if env.get(&key).is_none() {
+            env.insert(key, mirror.mirror.as_str());
+        }
+    }
+    env
+}
+
+fn parse(value: &str) -> Result<Vec<Mirror>, MirrorError> {
+    value
+        .split([' ', ',', '\n'])
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (source, mirror) = entry.split_once('=').unwrap_or((RUBYGEMS_SOURCE, entry));
+            Ok(Mirror {
+                source: parse_url(source)?,
+                mirror: parse_url(mirror)?,
+            })
+        })
+        .collect()
+}
+
+/// Bundler compares sources with a trailing slash
+fn parse_url(value: &str) -> Result<Url, MirrorError> {
+    let with_slash = if value.ends_with('/') {
+        value.to_string()
+    } else {
+        format!("{value}/")
+    };
+    Url::parse(&with_slash).map_err(|error| MirrorError::InvalidUrl(value.to_string(), error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mirrors() {
+        let mirrors =
+            parse("https://gems.internal, https://gems.example.com=https://gems.internal/example/")
+                .unwrap();
+
+        assert_eq!(
+            mirrors
+                .iter()
+                .map(|mirror| (mirror.source.as_str(), mirror.mirror.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("https://rubygems.org/", "https://gems.internal/"),
+                (
+                    "https://gems.example.com/",
+                    "https://gems.internal/example/"
+                ),
+            ]
+        );
+        assert!(parse("not a url").is_err());
+        assert_eq!(parse("").unwrap(), Vec::new());
+    }
+
+    #[test]
+    fn test_bundle_env() {
+        let mirrors = parse(
+            "https://gems.internal https://my-gems.example.com=https://gems.internal/example",
+        )
+        .unwrap();
+        assert_eq!(mirrors[0].bundle_key(), RUBYGEMS_MIRROR_KEY);
+        assert_eq!(
+            mirrors[1].bundle_key(),
+            "BUNDLE_MIRROR__HTTPS://MY___GEMS__EXAMPLE__COM/"
+        );
+
+        let mut env = Env::new();
+        env.insert(RUBYGEMS_MIRROR_KEY, "https://user.set");
+        let env = bundle_env(&mirrors, &env);
+        assert_eq!(env.get(RUBYGEMS_MIRROR_KEY).unwrap(), "https://user.set");
+        assert_eq!(
+            env.get("BUNDLE_MIRROR__HTTPS://MY___GEMS__EXAMPLE__COM/")
+                .unwrap(),
+            "https://gems.internal/example/"
+        );
+    }
+}
@@ -1,5 +1,9 @@
+pub(crate) mod build_gems_layer;
 pub(crate) mod bundle_download_layer;
 pub(crate) mod bundle_install_layer;
+pub(crate) mod compiler_cache_layer;
+pub(crate) mod gem_generations_layer;
 pub(crate) mod metrics_agent_install;
 pub(crate) mod ruby_install_layer;
 mod shared;
+pub(crate) mod ssh_key_layer;
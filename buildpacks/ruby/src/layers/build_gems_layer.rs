@@ -0,0 +1,304 @@
+//! Installs gems from build-only groups via `bundle install`
+//!
+//! Some gems are only needed while building, for example those used by `rake assets:precompile`.
+//! They do not need to ship in the runtime image. When the `Gemfile` has gems in a build-only
+//! group (`assets` by default), they are excluded from the `gems` layer and installed into this
+//! `build: true, launch: false` layer.
+//!
+//! Bundler only supports a single `BUNDLE_PATH`, so the `gems` layer only sets it at launch.
+//! At build time bundler falls back to rubygems' `GEM_HOME` (this layer) and `GEM_PATH` (this
+//! layer and the `gems` layer). Gems already in the `gems` layer are not installed again.
+//!
+//! Build-only groups are configured with `HEROKU_BUNDLE_BUILD_ONLY_GROUPS` in the same format as
+//! `BUNDLE_WITHOUT`. Set it to an empty value to install all gems into the `gems` layer.
+use crate::bundle_check;
+use crate::layers::bundle_install_layer::{
+    self, installed_gem_dirs, layer_install_state, local_install, run_or_skip, split_groups,
+    verify_installed, InstallState, Metadata,
+};
+use crate::layers::shared::{explain_cache, remove_stale_layer};
+use crate::redact::RedactedStdout;
+use crate::{BundleWithout, RubyBuildpack, RubyBuildpackError};
+use bullet_stream::state::SubBullet;
+use bullet_stream::Print;
+use commons::gemfile_lock::GemfileLock;
+use commons::layer::diff_migrate::DiffMigrateLayer;
+use libcnb::build::BuildContext;
+use libcnb::data::layer::LayerName;
+use libcnb::data::layer_name;
+use libcnb::layer_env::{LayerEnv, ModificationBehavior, Scope};
+use libcnb::Env;
+use std::path::{Path, PathBuf};
+
+/// Groups that are installed for the build but not included at launch
+pub(crate) const BUILD_ONLY_GROUPS_ENV_KEY: &str = "HEROKU_BUNDLE_BUILD_ONLY_GROUPS";
+const DEFAULT_BUILD_ONLY_GROUPS: &str = "assets";
+
+/// `BUNDLE_` and gem environment variables shown with the `bundle install` command
+const DISPLAY_ENV_KEYS: [&str; 8] = [
+    "BUNDLE_BIN",
+    "BUNDLE_FROZEN",
+    "BUNDLE_GEMFILE",
+    "BUNDLE_JOBS",
+    "BUNDLE_RETRY",
+    "BUNDLE_WITHOUT",
+    "GEM_HOME",
+    "MAKEFLAGS",
+];
+
+/// Build-only groups that contain gems, along with the groups that are not installed at all
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct BuildOnlyGroups {
+    /// Groups installed into the build-only layer
+    pub(crate) groups: Vec<String>,
+    /// Groups not installed into any layer, from `BUNDLE_WITHOUT`
+    pub(crate) without: Vec<String>,
+    /// Gems only required by the build-only groups
+    pub(crate) gems: Vec<String>,
+}
+
+impl BuildOnlyGroups {
+    /// Returns `None` when no gems are only required by a build-only group
+    pub(crate) fn new(
+        env: &Env,
+        without_default: &BundleWithout,
+        gemfile_lock: &GemfileLock,
+        gemfile: &str,
+    ) -> Option<Self> {
+        let without = split_groups(&env.get("BUNDLE_WITHOUT").map_or_else(
+            || without_default.as_str().to_string(),
+            |value| value.to_string_lossy().into_owned(),
+        ));
+        let groups = split_groups(&env.get(BUILD_ONLY_GROUPS_ENV_KEY).map_or_else(
+            || DEFAULT_BUILD_ONLY_GROUPS.to_string(),
+            |value| value.to_string_lossy().into_owned(),
+        ))
+        .into_iter()
+        .filter(|group| !without.contains(group))
+        .collect::<Vec<String>>();
+
+        let gems = bundle_check::build_only_gems(gemfile_lock, gemfile, &without, &groups);
+        (!gems.is_empty()).then_some(Self {
+            groups,
+            without,
+            gems,
+        })
+    }
+
+    /// The `BUNDLE_WITHOUT` value for the `gems` layer
+    pub(crate) fn runtime_without(&self) -> String {
+        [self.without.as_slice(), self.groups.as_slice()]
+            .concat()
+            .join(":")
+    }
+}
+
+/// Name of the `build_gems` layer
+pub(crate) fn layer_name() -> LayerName {
+    layer_name!("build_gems")
+}
+
+pub(crate) fn handle(
+    context: &BuildContext<RubyBuildpack>,
+    env: &Env,
+    bullet: Print<SubBullet<RedactedStdout>>,
+    metadata: &Metadata,
+    build_only: &BuildOnlyGroups,
+    gemfile_lock: &GemfileLock,
+) -> libcnb::Result<(Print<SubBullet<RedactedStdout>>, LayerEnv), RubyBuildpackError> {
+    let bullet = explain_cache(bullet, context, &layer_name(), metadata)?;
+    let layer_ref = DiffMigrateLayer {
+        build: true,
+        launch: false,
+    }
+    .cached_layer(layer_name(), context, metadata)?;
+    let (mut bullet, install_state) = layer_install_state(bullet, &layer_ref.state, metadata);
+
+    let gems_layer_dirs = bundle_check::gem_dirs_in(
+        &context
+            .layers_dir
+            .join(bundle_install_layer::layer_name().as_str())
+            .join("ruby"),
+    );
+    let layer_env = layer_env(&layer_ref.path(), &gems_layer_dirs, build_only);
+    layer_ref.write_env(&layer_env)?;
+    let env = layer_env.apply(Scope::Build, env);
+
+    let gemfile = fs_err::read_to_string(context.app_dir.join("Gemfile")).unwrap_or_default();
+    let mut gem_dirs = vec![layer_ref.path()];
+    gem_dirs.extend(gems_layer_dirs);
+    let gem_dirs = installed_gem_dirs(context, &gem_dirs);
+    let install_state = match install_state {
+        InstallState::Skip(checked) => verify_installed(
+            checked,
+            &bundle_check::missing_gems(gemfile_lock, &gemfile, &build_only.without, &gem_dirs),
+        ),
+        run @ InstallState::Run(_) => run,
+    };
+    let local = matches!(install_state, InstallState::Run(_))
+        && local_install(
+            context,
+            gemfile_lock,
+            &gemfile,
+            &build_only.without,
+            &gem_dirs,
+        )?;
+    (bullet, _) = run_or_skip(bullet, install_state, &env, &DISPLAY_ENV_KEYS, local)?;
+
+    Ok((bullet, layer_ref.read_env()?))
+}
+
+/// Deletes the layer from a prior build, for when there are no longer build-only gems
+pub(crate) fn remove_stale(
+    context: &BuildContext<RubyBuildpack>,
+) -> libcnb::Result<(), RubyBuildpackError> {
+    remove_stale_layer(context, &layer_name())
+}
+
+fn layer_env(
+    layer_path: &Path,
+    gems_layer_dirs: &[PathBuf],
+    build_only: &BuildOnlyGroups,
+) -> LayerEnv {
+    let gem_path = std::iter::once(layer_path)
+        .chain(gems_layer_dirs.iter().map(PathBuf::as_path))
+        .map(|path| path.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(":");
+
+    LayerEnv::new()
+        .chainable_insert(
+            Scope::Build,
+            ModificationBehavior::Override,
+            "GEM_HOME", // Without `BUNDLE_PATH` bundler installs gems here
+            layer_path,
+        )
+        .chainable_insert(
+            Scope::Build,
+            ModificationBehavior::Delimiter,
+            "GEM_PATH",
+            ":",
+        )
+        .chainable_insert(
+            Scope::Build,
+            ModificationBehavior::Prepend,
+            "GEM_PATH", // Finds build-only gems and those installed in the `gems` layer
+            gem_path,
+        )
+        .chainable_insert(
+            Scope::Build,
+            ModificationBehavior::Override,
+            "BUNDLE_BIN", // Keeps executables of build-only gems out of the `gems` layer
+            layer_path.join("bin"),
+        )
+        .chainable_insert(
+            Scope::Build,
+            ModificationBehavior::Override,
+            "BUNDLE_WITHOUT", // Build-only groups are installed, other excluded groups are not
+            build_only.without.join(":"),
+        )
+        .chainable_insert(
+            Scope::Build,
+            ModificationBehavior::Override,
+            "BUNDLE_FROZEN", // `BUNDLE_DEPLOYMENT` would set `BUNDLE_PATH`, freeze the lockfile instead
+            "1",
+        )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use core::str::FromStr;
+
+    const LOCKFILE: &str = r"
+GEM
+  remote: https://rubygems.org/
+  specs:
+    rack (3.0.8)
+    sassc (2.4.0)
+      ffi (~> 1.9)
+    ffi (1.17.0)
+    rspec (3.13.0)
+
+PLATFORMS
+  ruby
+
+DEPENDENCIES
+  rack
+  rspec
+  sassc
+";
+
+    const GEMFILE: &str = r#"
+gem "rack"
+
+group :assets do
+  gem "sassc"
+end
+
+group :test do
+  gem "rspec"
+end
+"#;
+
+    fn build_only(env: &Env) -> Option<BuildOnlyGroups> {
+        BuildOnlyGroups::new(
+            env,
+            &BundleWithout::new("development:test"),
+            &GemfileLock::from_str(LOCKFILE).unwrap(),
+            GEMFILE,
+        )
+    }
+
+    #[test]
+    fn build_only_groups_default_to_assets() {
+        let build_only = build_only(&Env::new()).unwrap();
+
+        assert_eq!(build_only.groups, vec!["assets"]);
+        assert_eq!(build_only.gems, vec!["ffi", "sassc"]);
+        assert_eq!(build_only.runtime_without(), "development:test:assets");
+    }
+
+    #[test]
+    fn build_only_groups_configured_by_user() {
+        let mut env = Env::new();
+        env.insert(BUILD_ONLY_GROUPS_ENV_KEY, "");
+        assert_eq!(build_only(&env), None);
+
+        // Excluded groups are not installed at all
+        env.insert("BUNDLE_WITHOUT", "development:assets");
+        env.insert(BUILD_ONLY_GROUPS_ENV_KEY, "assets:test");
+        let build_only = build_only(&env).unwrap();
+        assert_eq!(build_only.groups, vec!["test"]);
+        assert_eq!(build_only.gems, vec!["rspec"]);
+        assert_eq!(build_only.runtime_without(), "development:assets:test");
+    }
+
+    /// If this test fails due to user change you may need
+    /// to rev a cache key to force 'bundle install'
+    /// to re-run otherwise it won't be picked up by
+    /// anyone that is seeing `DiffState::Same`
+    #[test]
+    fn layer_env_change_keep_guard() {
+        let layer_env = layer_env(
+            &PathBuf::from("build_gems"),
+            &[PathBuf::from("gems/ruby/3.2.0")],
+            &build_only(&Env::new()).unwrap(),
+        );
+
+        assert_eq!(
+            commons::display::env_to_sorted_string(&layer_env.apply(Scope::Launch, &Env::new())),
+            ""
+        );
+        let actual =
+            commons::display::env_to_sorted_string(&layer_env.apply(Scope::Build, &Env::new()));
+        let expected = r"
+BUNDLE_BIN=build_gems/bin
+BUNDLE_FROZEN=1
+BUNDLE_WITHOUT=development:test
+GEM_HOME=build_gems
+GEM_PATH=build_gems:gems/ruby/3.2.0
+        ";
+        assert_eq!(expected.trim(), actual.trim());
+    }
+}
@@ -4,6 +4,10 @@
 //!
 //! Installs a copy of `bundler` to the `<layer-dir>` with a bundler executable in
 //! `<layer-dir>/bin`. Must run before [`crate.steps.bundle_install`].
+use crate::gem_mirror::RUBYGEMS_MIRROR_KEY;
+use crate::layers::shared::explain_cache;
+use crate::redact::RedactedStdout;
+use crate::sbom;
 use crate::RubyBuildpack;
 use crate::RubyBuildpackError;
 use bullet_stream::state::SubBullet;
@@ -12,27 +16,33 @@ use cache_diff::CacheDiff;
 use commons::gemfile_lock::ResolvedBundlerVersion;
 use commons::layer::diff_migrate::DiffMigrateLayer;
 use fun_run::{self, CommandWithName};
+use libcnb::data::layer::LayerName;
 use libcnb::data::layer_name;
 use libcnb::layer::{EmptyLayerCause, LayerState};
 use libcnb::layer_env::{LayerEnv, ModificationBehavior, Scope};
 use libcnb::Env;
 use magic_migrate::{try_migrate_deserializer_chain, TryMigrate};
 use serde::{Deserialize, Serialize};
-use std::io::Stdout;
 use std::path::Path;
 use std::process::Command;
 
+/// Name of the `bundler` layer
+pub(crate) fn layer_name() -> LayerName {
+    layer_name!("bundler")
+}
+
 pub(crate) fn handle(
     context: &libcnb::build::BuildContext<RubyBuildpack>,
     env: &Env,
-    mut bullet: Print<SubBullet<Stdout>>,
+    mut bullet: Print<SubBullet<RedactedStdout>>,
     metadata: &Metadata,
-) -> libcnb::Result<(Print<SubBullet<Stdout>>, LayerEnv), RubyBuildpackError> {
+) -> libcnb::Result<(Print<SubBullet<RedactedStdout>>, LayerEnv), RubyBuildpackError> {
+    bullet = explain_cache(bullet, context, &layer_name(), metadata)?;
     let layer_ref = DiffMigrateLayer {
         build: true,
         launch: true,
     }
-    .cached_layer(layer_name!("bundler"), context, metadata)?;
+    .cached_layer(layer_name(), context, metadata)?;
 
     let layer_env = LayerEnv::new()
         .chainable_insert(Scope::All, ModificationBehavior::Delimiter, "PATH", ":")
@@ -66,6 +76,15 @@ pub(crate) fn handle(
             bullet = download_bundler(bullet, env, metadata, &layer_ref.path())?;
         }
     }
+    layer_ref.write_sboms(&sbom::sboms(
+        &sbom::Tool::from_context(context),
+        "bundler",
+        &[sbom::bundler(
+            &metadata.version.to_string(),
+            env.get(RUBYGEMS_MIRROR_KEY)
+                .and_then(|mirror| mirror.to_str()),
+        )],
+    ))?;
     Ok((bullet, layer_ref.read_env()?))
 }
 
@@ -89,11 +108,11 @@ pub(crate) enum MetadataError {
 }
 
 fn download_bundler(
-    bullet: Print<SubBullet<Stdout>>,
+    bullet: Print<SubBullet<RedactedStdout>>,
     env: &Env,
     metadata: &Metadata,
     gem_path: &Path,
-) -> Result<Print<SubBullet<Stdout>>, RubyBuildpackError> {
+) -> Result<Print<SubBullet<RedactedStdout>>, RubyBuildpackError> {
     let bin_dir = gem_path.join("bin");
 
     let mut cmd = Command::new("gem");
@@ -101,6 +120,10 @@ fn download_bundler(
     cmd.args(["--version", &metadata.version.to_string()]) // Specify exact version to install
         .env_clear()
         .envs(env);
+    if let Some(mirror) = env.get(RUBYGEMS_MIRROR_KEY) {
+        // Shown in the output to confirm the mirror is used
+        cmd.arg("--clear-sources").arg("--source").arg(mirror);
+    }
 
     let short_name = fun_run::display(&mut cmd); // Format `gem install --version <version>` without other content for display
 
@@ -125,6 +148,7 @@ fn download_bundler(
 mod test {
     use super::*;
     use bullet_stream::strip_ansi;
+    use commons::layer::testing::MetadataFixtures;
 
     #[test]
     fn test_metadata_diff() {
@@ -158,4 +182,17 @@ version = "2.3.6"
         .trim();
         assert_eq!(expected, actual.trim());
     }
+
+    #[test]
+    fn test_metadata_fixtures() {
+        let fixtures = MetadataFixtures::<Metadata>::new().fixture("v1", r#"version = "2.3.5""#);
+        fixtures.assert_all();
+        fixtures.assert_diff(
+            "v1",
+            &Metadata {
+                version: ResolvedBundlerVersion(String::from("2.3.6")),
+            },
+            &["Bundler version (`2.3.5` to `2.3.6`)"],
+        );
+    }
 }
@@ -4,36 +4,66 @@
 //! to execute on every build (as opposed to only when the cache is empty).
 //!
 //! As a small performance optimization, it will not run if the `Gemfile.lock`,
-//! `Gemfile`, or user provided "platform" environment variable have not changed.
+//! `Gemfile`, `.bundle/config`, `vendor/cache`, local `path:` gems, or user provided
+//! "platform" environment variable have not changed.
 //! User applications can opt out of this behavior by setting the environment
 //! variable `HEROKU_SKIP_BUNDLE_DIGEST=1`. That would be useful if the application's
 //! `Gemfile` sources logic or data from another file that is unknown to the buildpack.
 //!
+//! Before skipping, the gems required by the `Gemfile.lock` are checked against the contents
+//! of the layer (see [`crate::bundle_check`]). If any are missing or have unbuilt native
+//! extensions, `bundle install` runs anyway.
+//!
+//! When the app packages gems in `vendor/cache` (via `bundle cache`) they are installed with
+//! `bundle install --local` so no gems are fetched over the network. The build fails before
+//! installing if a required gem is missing from the cache.
+//!
+//! Past generations of the layer are kept for rollbacks (see
+//! [`crate::layers::gem_generations_layer`]). When the gems changed but match a stored
+//! generation, it is restored instead of running `bundle install`.
+//!
+//! Output from `bundle install` is condensed (see [`crate::bundle_output`]) unless
+//! `HEROKU_BUNDLE_INSTALL_VERBOSE` is set.
+//!
 //! Gems can be plain Ruby code which are OS, Architecture, and Ruby version independent.
 //! They can also be native extensions that use Ruby's C API or contain libraries that
 //! must be compiled and will then be invoked via FFI. These native extensions are
 //! OS, Architecture, and Ruby version dependent. Due to this, when one of these changes
 //! we must clear the cache and re-run `bundle install`.
+use crate::bundle_check::{self, MissingGem};
+use crate::bundle_output::{self, CondensedWriter};
+use crate::container_limits::ContainerLimits;
+use crate::gem_changes::{GemChanges, GemSnapshot};
+use crate::layers::build_gems_layer::BuildOnlyGroups;
+use crate::layers::gem_generations_layer::{self, Generations};
+use crate::layers::ruby_install_layer;
+use crate::layers::shared::explain_cache;
+use crate::redact::RedactedStdout;
+use crate::sbom;
 use crate::target_id::{OsDistribution, TargetId, TargetIdError};
 use crate::{BundleWithout, RubyBuildpack, RubyBuildpackError};
 use bullet_stream::state::SubBullet;
 use bullet_stream::{style, Print};
 use cache_diff::CacheDiff;
+use commons::layer::clear_cache::ClearCache;
 use commons::layer::diff_migrate::{DiffMigrateLayer, Meta};
 use commons::{
-    display::SentenceList, gemfile_lock::ResolvedRubyVersion, metadata_digest::MetadataDigest,
+    display::SentenceList,
+    gemfile_lock::{GemfileLock, ResolvedRubyVersion},
+    metadata_digest::{MetadataDigest, TrackedPath},
 };
 use fun_run::{self, CommandWithName};
+use libcnb::data::layer::LayerName;
 use libcnb::data::layer_name;
 use libcnb::layer::{EmptyLayerCause, LayerState};
 use libcnb::{
     layer_env::{LayerEnv, ModificationBehavior, Scope},
-    Env,
+    Env, Platform,
 };
 use magic_migrate::{try_migrate_deserializer_chain, TryMigrate};
 use serde::{Deserialize, Serialize};
-use std::io::Stdout;
-use std::{path::Path, process::Command};
+use std::path::{Path, PathBuf};
+use std::process::Command;
 
 /// When this environment variable is set, the `bundle install` command will always
 /// run regardless of whether the `Gemfile`, `Gemfile.lock`, or platform environment
@@ -42,26 +72,158 @@ const SKIP_DIGEST_ENV_KEY: &str = "HEROKU_SKIP_BUNDLE_DIGEST";
 /// A failsafe, if a programmer made a mistake in the caching logic, rev-ing this
 /// key will force a re-run of `bundle install` to ensure the cache is correct
 /// on the next build.
-pub(crate) const FORCE_BUNDLE_INSTALL_CACHE_KEY: &str = "v1";
+pub(crate) const FORCE_BUNDLE_INSTALL_CACHE_KEY: &str = "v2";
+/// `BUNDLE_` environment variables shown with the `bundle install` command
+const DISPLAY_ENV_KEYS: [&str; 9] = [
+    "BUNDLE_BIN",
+    "BUNDLE_CLEAN",
+    "BUNDLE_DEPLOYMENT",
+    "BUNDLE_GEMFILE",
+    "BUNDLE_JOBS",
+    "BUNDLE_PATH",
+    "BUNDLE_RETRY",
+    "BUNDLE_WITHOUT",
+    "MAKEFLAGS",
+];
+
+/// Name of the `gems` layer
+pub(crate) fn layer_name() -> LayerName {
+    layer_name!("gems")
+}
 
 pub(crate) fn handle(
     context: &libcnb::build::BuildContext<RubyBuildpack>,
     env: &Env,
-    mut bullet: Print<SubBullet<Stdout>>,
+    bullet: Print<SubBullet<RedactedStdout>>,
     metadata: &Metadata,
     without: &BundleWithout,
-) -> libcnb::Result<(Print<SubBullet<Stdout>>, LayerEnv), RubyBuildpackError> {
+    build_only: Option<&BuildOnlyGroups>,
+    gemfile_lock: &GemfileLock,
+) -> libcnb::Result<(Print<SubBullet<RedactedStdout>>, LayerEnv), RubyBuildpackError> {
+    let bullet = explain_cache(bullet, context, &layer_name(), metadata)?;
+    // Read before the layer is cleared, gems change the most when the cache is invalidated
+    let old_gems = previous_gems(&context.layers_dir);
     let layer_ref = DiffMigrateLayer {
         build: true,
         launch: true,
     }
-    .cached_layer(layer_name!("gems"), context, metadata)?;
-    let install_state = match &layer_ref.state {
+    .cached_layer(layer_name(), context, metadata)?;
+    let (bullet, install_state) = layer_install_state(bullet, &layer_ref.state, metadata);
+
+    let layer_env = layer_env(
+        &layer_ref.path(),
+        &context.app_dir,
+        without,
+        build_only.map(BuildOnlyGroups::runtime_without).as_deref(),
+    );
+    layer_ref.write_env(&layer_env)?;
+    // When build-only gems are split out, bundler is only pointed at this layer at launch
+    let env = layer_env.apply(
+        if build_only.is_some() {
+            Scope::Launch
+        } else {
+            Scope::Build
+        },
+        env,
+    );
+
+    let gemfile = fs_err::read_to_string(context.app_dir.join("Gemfile")).unwrap_or_default();
+    let gem_dirs = installed_gem_dirs(
+        context,
+        &bundle_check::gem_dirs_in(&layer_ref.path().join("ruby")),
+    );
+    let install_state = match install_state {
+        InstallState::Skip(checked) => verify_installed(
+            checked,
+            &bundle_check::missing_gems(
+                gemfile_lock,
+                &gemfile,
+                &bundle_without_groups(&env),
+                &gem_dirs,
+            ),
+        ),
+        run @ InstallState::Run(_) => run,
+    };
+    let (mut bullet, mut generations) =
+        load_generations(context, &env, bullet, metadata, &gemfile)?;
+    let restored = match (&install_state, &mut generations) {
+        (InstallState::Run(_), Some(generations)) => {
+            let restored;
+            (bullet, restored) = restore_generation(
+                context,
+                bullet,
+                generations,
+                &layer_ref.path(),
+                (gemfile_lock, &gemfile, &bundle_without_groups(&env)),
+            );
+            restored
+        }
+        _ => false,
+    };
+
+    let updated;
+    (bullet, updated) = if restored {
+        (bullet, true)
+    } else {
+        let local = matches!(install_state, InstallState::Run(_))
+            && local_install(
+                context,
+                gemfile_lock,
+                &gemfile,
+                &bundle_without_groups(&env),
+                &gem_dirs,
+            )?;
+        run_or_skip(bullet, install_state, &env, &DISPLAY_ENV_KEYS, local)?
+    };
+    if updated {
+        if let (Some(old), Some(now)) = (&old_gems, &metadata.gems) {
+            let changes = GemChanges::new(old, now);
+            if changes.is_empty() {
+                bullet = bullet.sub_bullet("No gem changes since the last build");
+            }
+            for line in changes.lines() {
+                bullet = bullet.sub_bullet(line);
+            }
+        }
+    }
+    if let Some(generations) = &mut generations {
+        bullet = save_generation(bullet, generations, &layer_ref.path());
+    }
+    layer_ref.write_sboms(&sbom::sboms(
+        &sbom::Tool::from_context(context),
+        "gems",
+        &sbom::gems(
+            gemfile_lock,
+            &bundle_check::gem_dirs_in(&layer_ref.path().join("ruby")),
+        ),
+    ))?;
+
+    Ok((bullet, layer_ref.read_env()?))
+}
+
+/// Gems recorded by the prior build, read from the layer TOML so they're available even when
+/// the layer is cleared
+fn previous_gems(layers_dir: &Path) -> Option<GemSnapshot> {
+    fs_err::read_to_string(layers_dir.join("gems.toml"))
+        .ok()
+        .and_then(|contents| toml::from_str::<toml::Table>(&contents).ok())
+        .and_then(|mut table| table.remove("metadata"))
+        .and_then(|metadata| metadata.get("gems").cloned())
+        .and_then(|gems| gems.try_into().ok())
+}
+
+/// Reports the state of a restored or empty layer, returning whether `bundle install` should run
+pub(crate) fn layer_install_state(
+    mut bullet: Print<SubBullet<RedactedStdout>>,
+    state: &LayerState<Meta<Metadata>, Meta<Metadata>>,
+    metadata: &Metadata,
+) -> (Print<SubBullet<RedactedStdout>>, InstallState) {
+    let install_state = match state {
         LayerState::Restored { cause } => {
             bullet = bullet.sub_bullet(cause);
-            match cause {
-                Meta::Data(old) => install_state(old, metadata),
-                Meta::Message(_) => InstallState::Run(String::new()),
+            match cause.data() {
+                Some(old) => install_state(old, metadata),
+                None => InstallState::Run(String::new()),
             }
         }
         LayerState::Empty { cause } => match cause {
@@ -73,33 +235,189 @@ pub(crate) fn handle(
             }
         },
     };
+    (bullet, install_state)
+}
 
-    let env = {
-        let layer_env = layer_env(&layer_ref.path(), &context.app_dir, without);
-        layer_ref.write_env(&layer_env)?;
-        layer_env.apply(Scope::Build, env)
-    };
+/// Loads past generations of the layer and the key of the current gems
+fn load_generations(
+    context: &libcnb::build::BuildContext<RubyBuildpack>,
+    env: &Env,
+    bullet: Print<SubBullet<RedactedStdout>>,
+    metadata: &Metadata,
+    gemfile: &str,
+) -> libcnb::Result<(Print<SubBullet<RedactedStdout>>, Option<Generations>), RubyBuildpackError> {
+    let key = gem_generations_layer::key(
+        &fs_err::read_to_string(context.app_dir.join("Gemfile.lock")).unwrap_or_default(),
+        gemfile,
+        &bundle_without_groups(env),
+    );
+    gem_generations_layer::handle(
+        context,
+        env,
+        bullet,
+        &gem_generations_layer::Metadata {
+            os_distribution: metadata.os_distribution.clone(),
+            cpu_architecture: metadata.cpu_architecture.clone(),
+            ruby_version: metadata.ruby_version.clone(),
+        },
+        key,
+    )
+}
+
+/// Restores a previous generation of the layer matching the current gems, returns true when
+/// it was restored and every required gem is installed so `bundle install` can be skipped
+///
+/// Not used when `bundle install` is forced with `HEROKU_SKIP_BUNDLE_DIGEST`, or when the
+/// `gems` layer is cleared with `HEROKU_CLEAR_CACHE`.
+fn restore_generation(
+    context: &libcnb::build::BuildContext<RubyBuildpack>,
+    mut bullet: Print<SubBullet<RedactedStdout>>,
+    generations: &mut Generations,
+    layer_path: &Path,
+    (gemfile_lock, gemfile, without): (&GemfileLock, &str, &[String]),
+) -> (Print<SubBullet<RedactedStdout>>, bool) {
+    if std::env::var_os(SKIP_DIGEST_ENV_KEY).is_some()
+        || ClearCache::from_env(context.platform.env()).includes(&layer_name())
+    {
+        return (bullet, false);
+    }
+    match generations.restore(layer_path) {
+        Ok(Some(generation)) => {
+            let gem_dirs = installed_gem_dirs(
+                context,
+                &bundle_check::gem_dirs_in(&layer_path.join("ruby")),
+            );
+            let missing = bundle_check::missing_gems(gemfile_lock, gemfile, without, &gem_dirs);
+            if missing.is_empty() {
+                bullet = bullet.sub_bullet(format!(
+                    "Restored gems from generation {short_key} of a previous build with the same {lockfile}, skipping {bundle_install}",
+                    short_key = style::value(generation.short_key()),
+                    lockfile = style::value("Gemfile.lock"),
+                    bundle_install = style::value("bundle install"),
+                ));
+                (bullet, true)
+            } else {
+                // Stored again after `bundle install`, otherwise every build restores the same
+                // incomplete copy
+                if let Err(error) = generations.forget() {
+                    bullet = gem_generations_layer::warn(bullet, &error);
+                }
+                bullet = bullet.sub_bullet(format!(
+                    "Restored generation {short_key} is missing {gems}",
+                    short_key = style::value(generation.short_key()),
+                    gems = SentenceList::new(
+                        &missing
+                            .iter()
+                            .map(ToString::to_string)
+                            .collect::<Vec<String>>()
+                    )
+                ));
+                (bullet, false)
+            }
+        }
+        Ok(None) => (bullet, false),
+        Err(error) => (gem_generations_layer::warn(bullet, &error), false),
+    }
+}
 
+/// Stores the layer as the most recent generation and reports evicted generations
+fn save_generation(
+    mut bullet: Print<SubBullet<RedactedStdout>>,
+    generations: &mut Generations,
+    layer_path: &Path,
+) -> Print<SubBullet<RedactedStdout>> {
+    let stored = generations.current().is_some();
+    match generations.save(layer_path) {
+        Ok(evicted) => {
+            if let Some(generation) = generations.current().filter(|_| !stored) {
+                bullet = bullet.sub_bullet(format!(
+                    "Stored gems as generation {short_key} for fast rollbacks ({count} kept)",
+                    short_key = style::value(generation.short_key()),
+                    count = generations.len()
+                ));
+            }
+            for generation in evicted {
+                bullet = bullet.sub_bullet(format!(
+                    "Evicted least recently used gem generation {short_key} ({size})",
+                    short_key = style::value(generation.short_key()),
+                    size = gem_generations_layer::megabytes(generation.bytes)
+                ));
+            }
+            bullet
+        }
+        Err(error) => gem_generations_layer::warn(bullet, &error),
+    }
+}
+
+/// Gem directories used to verify a skipped install, including gems that ship with Ruby
+pub(crate) fn installed_gem_dirs(
+    context: &libcnb::build::BuildContext<RubyBuildpack>,
+    layer_gem_dirs: &[PathBuf],
+) -> Vec<PathBuf> {
+    let mut gem_dirs = layer_gem_dirs.to_vec();
+    gem_dirs.extend(bundle_check::gem_dirs_in(
+        &context
+            .layers_dir
+            .join(ruby_install_layer::layer_name().as_str())
+            .join("lib")
+            .join("ruby")
+            .join("gems"),
+    ));
+    gem_dirs
+}
+
+/// Whether to install from `vendor/cache` without network access
+///
+/// Fails when a gem required by the lockfile is neither packaged in `vendor/cache` nor already
+/// installed, rather than letting bundler reach out to a gem server.
+pub(crate) fn local_install(
+    context: &libcnb::build::BuildContext<RubyBuildpack>,
+    gemfile_lock: &GemfileLock,
+    gemfile: &str,
+    without: &[String],
+    gem_dirs: &[PathBuf],
+) -> Result<bool, RubyBuildpackError> {
+    let cache_dir = context.app_dir.join("vendor").join("cache");
+    if !bundle_check::has_cached_gems(&cache_dir) {
+        return Ok(false);
+    }
+
+    let installed = bundle_check::missing_gems(gemfile_lock, gemfile, without, gem_dirs);
+    let missing = bundle_check::missing_from_cache(gemfile_lock, gemfile, without, &cache_dir)
+        .into_iter()
+        .filter(|gem| installed.iter().any(|other| other.name == gem.name))
+        .collect::<Vec<MissingGem>>();
+    if missing.is_empty() {
+        Ok(true)
+    } else {
+        Err(RubyBuildpackError::VendorCacheMissingGems(missing))
+    }
+}
+
+/// Runs `bundle install` or reports why it was skipped, returns true when it ran
+///
+/// When `local` is true gems are installed from `vendor/cache` with `bundle install --local`.
+pub(crate) fn run_or_skip(
+    mut bullet: Print<SubBullet<RedactedStdout>>,
+    install_state: InstallState,
+    env: &Env,
+    display_keys: &[&str],
+    local: bool,
+) -> Result<(Print<SubBullet<RedactedStdout>>, bool), RubyBuildpackError> {
     match install_state {
         InstallState::Run(reason) => {
             if !reason.is_empty() {
                 bullet = bullet.sub_bullet(reason);
             }
+            if local {
+                bullet = bullet.sub_bullet(format!(
+                    "Installing from {vendor_cache} without network access",
+                    vendor_cache = style::value("vendor/cache")
+                ));
+            }
 
-            let mut cmd = Command::new("bundle");
-            cmd.args(["install"])
-                .env_clear() // Current process env vars already merged into env
-                .envs(&env);
-            let mut cmd = cmd.named_fn(|cmd| display_name(cmd, &env));
-            bullet
-                .stream_with(
-                    format!("Running {}", style::command(cmd.name())),
-                    |stdout, stderr| cmd.stream_output(stdout, stderr),
-                )
-                .map_err(|error| {
-                    fun_run::map_which_problem(error, cmd.mut_cmd(), env.get("PATH").cloned())
-                })
-                .map_err(RubyBuildpackError::BundleInstallCommandError)?;
+            bullet = bundle_install(bullet, env, display_keys, local)?;
+            Ok((bullet, true))
         }
         InstallState::Skip(checked) => {
             let bundle_install = style::value("bundle install");
@@ -114,15 +432,50 @@ pub(crate) fn handle(
                     "{help} To force run {bundle_install} set {}",
                     style::value(format!("{SKIP_DIGEST_ENV_KEY}=1"))
                 ));
+            Ok((bullet, false))
         }
     }
+}
 
-    Ok((bullet, layer_ref.read_env()?))
+/// Runs `bundle install`, condensing the output unless verbose output is requested
+///
+/// Concurrency is tuned to the build container unless configured by the user.
+fn bundle_install(
+    mut bullet: Print<SubBullet<RedactedStdout>>,
+    env: &Env,
+    display_keys: &[&str],
+    local: bool,
+) -> Result<Print<SubBullet<RedactedStdout>>, RubyBuildpackError> {
+    let env = &ContainerLimits::detect().install_env(env);
+    let mut cmd = Command::new("bundle");
+    cmd.args(["install"]);
+    if local {
+        cmd.arg("--local");
+    }
+    cmd.env_clear() // Current process env vars already merged into env
+        .envs(env);
+    let mut cmd = cmd.named_fn(|cmd| display_name(cmd, env, display_keys));
+    let verbose = bundle_output::verbose(env);
+    bullet
+        .stream_with(
+            format!("Running {}", style::command(cmd.name())),
+            |stdout, stderr| {
+                if verbose {
+                    cmd.stream_output(stdout, stderr)
+                } else {
+                    cmd.stream_output(CondensedWriter::new(stdout), stderr)
+                        .map_err(bundle_output::full_output_on_failure)
+                }
+            },
+        )
+        .map_err(|error| fun_run::map_which_problem(error, cmd.mut_cmd(), env.get("PATH").cloned()))
+        .map_err(RubyBuildpackError::BundleInstallCommandError)?;
+    Ok(bullet)
 }
 
-pub(crate) type Metadata = MetadataV3;
+pub(crate) type Metadata = MetadataV4;
 try_migrate_deserializer_chain!(
-    chain: [MetadataV1, MetadataV2, MetadataV3],
+    chain: [MetadataV1, MetadataV2, MetadataV3, MetadataV4],
     error: MetadataMigrateError,
     deserializer: toml::Deserializer::new,
 );
@@ -147,9 +500,19 @@ pub(crate) struct MetadataV2 {
     pub(crate) digest: MetadataDigest, // Must be last for serde to be happy https://github.com/toml-rs/toml-rs/issues/142
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone, Eq, PartialEq, CacheDiff)]
+#[derive(Deserialize, Serialize, Debug, Clone, Eq, PartialEq)]
 #[serde(deny_unknown_fields)]
 pub(crate) struct MetadataV3 {
+    pub(crate) os_distribution: OsDistribution,
+    pub(crate) cpu_architecture: String,
+    pub(crate) ruby_version: ResolvedRubyVersion,
+    pub(crate) force_bundle_install_key: String,
+    pub(crate) digest: MetadataDigest, // Must be last for serde to be happy https://github.com/toml-rs/toml-rs/issues/142
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Eq, PartialEq, CacheDiff)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct MetadataV4 {
     #[cache_diff(rename = "OS Distribution")]
     pub(crate) os_distribution: OsDistribution,
     #[cache_diff(rename = "CPU Architecture")]
@@ -159,6 +522,13 @@ pub(crate) struct MetadataV3 {
     #[cache_diff(ignore)]
     pub(crate) force_bundle_install_key: String,
 
+    /// Gem names and versions resolved in the `Gemfile.lock`. Read back on the
+    /// next build to show which gems changed.
+    ///
+    /// `None` when migrated from older metadata that didn't record it.
+    #[cache_diff(ignore)]
+    pub(crate) gems: Option<GemSnapshot>,
+
     /// A struct that holds the cryptographic hash of components that can
     /// affect the result of `bundle install`. When these values do not
     /// change between deployments we can skip re-running `bundle install` since
@@ -200,11 +570,9 @@ impl TryFrom<MetadataV1> for MetadataV2 {
     }
 }
 
-impl TryFrom<MetadataV2> for MetadataV3 {
-    type Error = std::convert::Infallible;
-
-    fn try_from(v2: MetadataV2) -> Result<Self, Self::Error> {
-        Ok(Self {
+impl From<MetadataV2> for MetadataV3 {
+    fn from(v2: MetadataV2) -> Self {
+        Self {
             os_distribution: OsDistribution {
                 name: v2.distro_name,
                 version: v2.distro_version,
@@ -213,12 +581,25 @@ impl TryFrom<MetadataV2> for MetadataV3 {
             ruby_version: v2.ruby_version,
             force_bundle_install_key: v2.force_bundle_install_key,
             digest: v2.digest,
-        })
+        }
+    }
+}
+
+impl From<MetadataV3> for MetadataV4 {
+    fn from(v3: MetadataV3) -> Self {
+        Self {
+            os_distribution: v3.os_distribution,
+            cpu_architecture: v3.cpu_architecture,
+            ruby_version: v3.ruby_version,
+            force_bundle_install_key: v3.force_bundle_install_key,
+            gems: None,
+            digest: v3.digest,
+        }
     }
 }
 
 #[derive(Debug)]
-enum InstallState {
+pub(crate) enum InstallState {
     /// Holds message indicating the reason why we want to run 'bundle install'
     Run(String),
 
@@ -228,7 +609,7 @@ enum InstallState {
 
 /// Determines if 'bundle install' should execute on a given call to `BundleInstallLatyer::update`
 ///
-fn install_state(old: &Metadata, now: &Metadata) -> InstallState {
+pub(crate) fn install_state(old: &Metadata, now: &Metadata) -> InstallState {
     let forced_env = std::env::var_os(SKIP_DIGEST_ENV_KEY);
     let old_key = &old.force_bundle_install_key;
     let now_key = &now.force_bundle_install_key;
@@ -249,11 +630,97 @@ fn install_state(old: &Metadata, now: &Metadata) -> InstallState {
     }
 }
 
-fn layer_env(layer_path: &Path, app_dir: &Path, without_default: &BundleWithout) -> LayerEnv {
+/// Files and directories that can change the outcome of `bundle install`
+///
+/// Used to build the [`MetadataDigest`]. If a tracked input is missed, `bundle install` may
+/// be skipped when it should have run.
+pub(crate) fn digest_paths(app_dir: &Path, gemfile_lock: &GemfileLock) -> Vec<TrackedPath> {
+    let mut paths = vec![
+        TrackedPath::File(app_dir.join("Gemfile")),
+        TrackedPath::File(app_dir.join("Gemfile.lock")),
+        TrackedPath::OptionalFile(app_dir.join(".bundle").join("config")),
+        TrackedPath::Dir(app_dir.join("vendor").join("cache")),
+    ];
+
+    for remote in &gemfile_lock.path_remotes {
+        let dir = app_dir.join(remote);
+        // Tracking the whole app would re-run install on every code change, for a gem
+        // defined in the app (via `gemspec`) only the gemspec affects the install.
+        if dir.components().eq(app_dir.components()) {
+            paths.push(TrackedPath::Glob(format!(
+                "{}/*.gemspec",
+                glob::Pattern::escape(&app_dir.to_string_lossy())
+            )));
+        } else {
+            paths.push(TrackedPath::Dir(dir));
+        }
+    }
+
+    paths
+}
+
+/// Skipping is only safe if every required gem is on disk
+pub(crate) fn verify_installed(checked: Vec<String>, missing: &[MissingGem]) -> InstallState {
+    if missing.is_empty() {
+        InstallState::Skip(checked)
+    } else {
+        let shown = missing
+            .iter()
+            .take(5)
+            .map(ToString::to_string)
+            .collect::<Vec<String>>();
+        let more = missing.len().saturating_sub(shown.len());
+        let gems = if more > 0 {
+            format!("{}, and {more} more", shown.join(", "))
+        } else {
+            SentenceList::new(&shown).to_string()
+        };
+        InstallState::Run(format!(
+            "{count} required {gem_str} missing from the cache: {gems}",
+            count = missing.len(),
+            gem_str = if missing.len() > 1 { "gems" } else { "gem" }
+        ))
+    }
+}
+
+/// Groups from `BUNDLE_WITHOUT` which bundler accepts separated by `:` or spaces
+fn bundle_without_groups(env: &Env) -> Vec<String> {
+    env.get("BUNDLE_WITHOUT")
+        .map(|value| split_groups(&value.to_string_lossy()))
+        .unwrap_or_default()
+}
+
+/// Splits a list of groups in the format bundler accepts for `BUNDLE_WITHOUT`
+pub(crate) fn split_groups(value: &str) -> Vec<String> {
+    value
+        .split([':', ' ', ','])
+        .filter(|group| !group.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// When `runtime_without` is given, build-only gems are installed to another layer. This
+/// layer then excludes them via `BUNDLE_WITHOUT` and only points bundler at itself at launch,
+/// at build time bundler finds gems from both layers via `GEM_PATH`.
+fn layer_env(
+    layer_path: &Path,
+    app_dir: &Path,
+    without_default: &BundleWithout,
+    runtime_without: Option<&str>,
+) -> LayerEnv {
+    let path_scope = if runtime_without.is_some() {
+        Scope::Launch
+    } else {
+        Scope::All
+    };
+    let (without_behavior, without) = match runtime_without {
+        Some(without) => (ModificationBehavior::Override, without),
+        None => (ModificationBehavior::Default, without_default.as_str()),
+    };
     // CAREFUL: See environment variable warning below vvvvvvvvvv
     let layer_env = LayerEnv::new()
         .chainable_insert(
-            Scope::All,
+            path_scope.clone(),
             ModificationBehavior::Override,
             "BUNDLE_PATH", // Directs bundler to install gems to this path.
             layer_path,
@@ -273,9 +740,9 @@ fn layer_env(layer_path: &Path, app_dir: &Path, without_default: &BundleWithout)
         )
         .chainable_insert(
             Scope::All,
-            ModificationBehavior::Default,
+            without_behavior,
             "BUNDLE_WITHOUT", // Do not install `development` or `test` groups via bundle install. Additional groups can be specified via user config.
-            without_default.as_str(),
+            without,
         )
         .chainable_insert(
             Scope::All,
@@ -284,13 +751,13 @@ fn layer_env(layer_path: &Path, app_dir: &Path, without_default: &BundleWithout)
             app_dir.join("Gemfile"),
         )
         .chainable_insert(
-            Scope::All,
+            path_scope.clone(),
             ModificationBehavior::Override,
             "BUNDLE_CLEAN", // After successful `bundle install` bundler will automatically run `bundle clean`
             "1",
         )
         .chainable_insert(
-            Scope::All,
+            path_scope,
             ModificationBehavior::Override,
             "BUNDLE_DEPLOYMENT", // Requires the `Gemfile.lock` to be in sync with the current `Gemfile`.
             "1",
@@ -305,19 +772,8 @@ fn layer_env(layer_path: &Path, app_dir: &Path, without_default: &BundleWithout)
 
 /// Displays the `bundle install` command with `BUNDLE_` environment variables
 /// that we use to configure bundler.
-fn display_name(cmd: &mut Command, env: &Env) -> String {
-    fun_run::display_with_env_keys(
-        cmd,
-        env,
-        [
-            "BUNDLE_BIN",
-            "BUNDLE_CLEAN",
-            "BUNDLE_DEPLOYMENT",
-            "BUNDLE_GEMFILE",
-            "BUNDLE_PATH",
-            "BUNDLE_WITHOUT",
-        ],
-    )
+fn display_name(cmd: &mut Command, env: &Env, keys: &[&str]) -> String {
+    fun_run::display_with_env_keys(cmd, env, keys)
 }
 
 #[cfg(test)]
@@ -326,6 +782,7 @@ mod test {
     use bullet_stream::strip_ansi;
     use pretty_assertions::assert_eq;
     use std::path::PathBuf;
+    use std::str::FromStr;
 
     /// `CacheDiff` logic controls cache invalidation
     /// When the vec is empty the cache is kept, otherwise it is invalidated
@@ -349,6 +806,7 @@ mod test {
             },
             cpu_architecture: "amd64".to_string(),
             force_bundle_install_key: FORCE_BUNDLE_INSTALL_CACHE_KEY.to_string(),
+            gems: None,
             digest: MetadataDigest::new_env_files(
                 &context.platform,
                 &[&context.app_path.join("Gemfile")],
@@ -362,6 +820,7 @@ mod test {
             os_distribution: old.os_distribution.clone(),
             cpu_architecture: old.cpu_architecture.clone(),
             force_bundle_install_key: old.force_bundle_install_key.clone(),
+            gems: old.gems.clone(),
             digest: old.digest.clone(),
         }
         .diff(&old);
@@ -379,6 +838,7 @@ mod test {
             },
             cpu_architecture: old.cpu_architecture.clone(),
             force_bundle_install_key: old.force_bundle_install_key.clone(),
+            gems: old.gems.clone(),
             digest: old.digest.clone(),
         }
         .diff(&old);
@@ -393,6 +853,7 @@ mod test {
             os_distribution: old.os_distribution.clone(),
             cpu_architecture: "arm64".to_string(),
             force_bundle_install_key: old.force_bundle_install_key.clone(),
+            gems: old.gems.clone(),
             digest: old.digest.clone(),
         }
         .diff(&old);
@@ -436,6 +897,7 @@ mod test {
             &PathBuf::from("layer_path"),
             &PathBuf::from("app_path"),
             &BundleWithout(String::from("development:test")),
+            None,
         );
 
         let env = layer_env.apply(Scope::All, &Env::new());
@@ -453,6 +915,139 @@ GEM_PATH=layer_path
         assert_eq!(expected.trim(), actual.trim());
     }
 
+    #[test]
+    fn layer_env_with_build_only_groups() {
+        let layer_env = layer_env(
+            &PathBuf::from("layer_path"),
+            &PathBuf::from("app_path"),
+            &BundleWithout(String::from("development:test")),
+            Some("development:test:assets"),
+        );
+
+        let actual =
+            commons::display::env_to_sorted_string(&layer_env.apply(Scope::Launch, &Env::new()));
+        let expected = r"
+BUNDLE_BIN=layer_path/bin
+BUNDLE_CLEAN=1
+BUNDLE_DEPLOYMENT=1
+BUNDLE_GEMFILE=app_path/Gemfile
+BUNDLE_PATH=layer_path
+BUNDLE_WITHOUT=development:test:assets
+GEM_PATH=layer_path
+        ";
+        assert_eq!(expected.trim(), actual.trim());
+
+        // Bundler finds gems via `GEM_PATH` at build time
+        let actual =
+            commons::display::env_to_sorted_string(&layer_env.apply(Scope::Build, &Env::new()));
+        let expected = r"
+BUNDLE_BIN=layer_path/bin
+BUNDLE_GEMFILE=app_path/Gemfile
+BUNDLE_WITHOUT=development:test:assets
+GEM_PATH=layer_path
+        ";
+        assert_eq!(expected.trim(), actual.trim());
+    }
+
+    #[test]
+    fn digest_paths_tracks_bundler_inputs() {
+        let app_dir = PathBuf::from("/workspace");
+        let gemfile_lock = GemfileLock::from_str(
+            r"
+PATH
+  remote: .
+  specs:
+    my_app (0.1.0)
+
+PATH
+  remote: engines/billing
+  specs:
+    billing (1.0.0)
+",
+        )
+        .unwrap();
+
+        assert_eq!(
+            digest_paths(&app_dir, &gemfile_lock),
+            vec![
+                TrackedPath::File(app_dir.join("Gemfile")),
+                TrackedPath::File(app_dir.join("Gemfile.lock")),
+                TrackedPath::OptionalFile(app_dir.join(".bundle").join("config")),
+                TrackedPath::Dir(app_dir.join("vendor").join("cache")),
+                TrackedPath::Glob(String::from("/workspace/*.gemspec")),
+                TrackedPath::Dir(app_dir.join("engines").join("billing")),
+            ]
+        );
+    }
+
+    #[test]
+    fn verify_installed_runs_when_gems_missing() {
+        let checked = vec![String::from("Gemfile")];
+        assert!(matches!(
+            verify_installed(checked.clone(), &[]),
+            InstallState::Skip(_)
+        ));
+
+        let missing = vec![MissingGem {
+            name: String::from("rack"),
+            version: String::from("3.0.8"),
+            reason: crate::bundle_check::MissingReason::NotInstalled,
+        }];
+        let InstallState::Run(reason) = verify_installed(checked, &missing) else {
+            panic!("Expected install to run");
+        };
+        assert_eq!(
+            strip_ansi(reason),
+            "1 required gem missing from the cache: `rack 3.0.8`"
+        );
+    }
+
+    #[test]
+    fn bundle_without_groups_splits() {
+        let mut env = Env::new();
+        assert!(bundle_without_groups(&env).is_empty());
+
+        env.insert("BUNDLE_WITHOUT", "development:test assets");
+        assert_eq!(
+            bundle_without_groups(&env),
+            vec!["development", "test", "assets"]
+        );
+    }
+
+    #[test]
+    fn test_previous_gems_from_cleared_layer() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let layers_dir = tmpdir.path();
+        assert_eq!(previous_gems(layers_dir), None);
+
+        // Metadata the current build can't use, the layer is cleared but the gems are kept
+        fs_err::write(
+            layers_dir.join("gems.toml"),
+            r#"
+[types]
+build = true
+launch = true
+cache = true
+
+[metadata]
+ruby_version = "3.1.3"
+unknown_field = "from a future version"
+
+[metadata.gems]
+rack = "3.0.8"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            previous_gems(layers_dir),
+            Some(GemSnapshot(
+                [(String::from("rack"), String::from("3.0.8"))]
+                    .into_iter()
+                    .collect()
+            ))
+        );
+    }
+
     /// Guards the current metadata deserialization
     /// If this fails you need to implement a migration from the last format
     /// to the current format.
@@ -480,6 +1075,11 @@ GEM_PATH=layer_path
             cpu_architecture: target_id.cpu_architecture,
             ruby_version: ResolvedRubyVersion(String::from("3.1.3")),
             force_bundle_install_key: String::from("v1"),
+            gems: Some(GemSnapshot(
+                [(String::from("rack"), String::from("3.0.8"))]
+                    .into_iter()
+                    .collect(),
+            )),
             digest: MetadataDigest::new_env_files(
                 &context.platform,
                 &[&context.app_path.join("Gemfile")],
@@ -499,6 +1099,9 @@ force_bundle_install_key = "v1"
 name = "ubuntu"
 version = "22.04"
 
+[gems]
+rack = "3.0.8"
+
 [digest]
 platform_env = "c571543beaded525b7ee46ceb0b42c0fb7b9f6bfc3a211b3bbcfe6956b69ace3"
 
@@ -575,4 +1178,29 @@ platform_env = "c571543beaded525b7ee46ceb0b42c0fb7b9f6bfc3a211b3bbcfe6956b69ace3
         };
         assert_eq!(expected, deserialized);
     }
+
+    #[test]
+    fn metadata_migrate_v3_to_v4() {
+        let toml_string = r#"
+cpu_architecture = "amd64"
+ruby_version = "3.1.3"
+force_bundle_install_key = "v1"
+
+[os_distribution]
+name = "ubuntu"
+version = "22.04"
+
+[digest]
+platform_env = "c571543beaded525b7ee46ceb0b42c0fb7b9f6bfc3a211b3bbcfe6956b69ace3"
+"#;
+        let migrated = MetadataV4::try_from_str_migrations(toml_string)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(migrated.gems, None);
+        assert_eq!(
+            migrated.ruby_version,
+            ResolvedRubyVersion(String::from("3.1.3"))
+        );
+    }
 }
@@ -0,0 +1,315 @@
+//! Caches native extension compiles across builds
+//!
+//! Gems such as `nokogiri`, `grpc`, and `pg` compile C and C++ extensions during `bundle install`
+//! and that compile dominates the time of a cold install. When `HEROKU_BUNDLE_COMPILER_CACHE=1` and
+//! `ccache` is on the `PATH`, `CC` and `CXX` are wrapped with `ccache` for `bundle install`, and the
+//! `ccache` directory is stored in a cached `build: true, launch: false` layer.
+//!
+//! When the `gems` layer is cleared for a reason that does not change the compiler inputs, the
+//! native extension compiles become cache hits. Inputs are hashed by `ccache` (source, headers,
+//! flags, and compiler contents) so a stale result is never used.
+//!
+//! The cache is limited to `HEROKU_BUNDLE_COMPILER_CACHE_SIZE` (default `1G`, in `ccache` size
+//! format) and pruned to that limit after each install.
+use crate::layers::shared::{explain_cache, remove_stale_layer};
+use crate::redact::RedactedStdout;
+use crate::target_id::OsDistribution;
+use crate::{RubyBuildpack, RubyBuildpackError};
+use bullet_stream::state::SubBullet;
+use bullet_stream::{style, Print};
+use cache_diff::CacheDiff;
+use commons::layer::diff_migrate::DiffMigrateLayer;
+use fun_run::CommandWithName;
+use libcnb::build::BuildContext;
+use libcnb::data::layer::LayerName;
+use libcnb::data::layer_name;
+use libcnb::layer::{EmptyLayerCause, LayerState};
+use libcnb::Env;
+use magic_migrate::{try_migrate_deserializer_chain, TryMigrate};
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Set to `1` or `true` to enable the compiler cache
+pub(crate) const ENABLE_ENV_KEY: &str = "HEROKU_BUNDLE_COMPILER_CACHE";
+/// Maximum size of the compiler cache
+pub(crate) const SIZE_ENV_KEY: &str = "HEROKU_BUNDLE_COMPILER_CACHE_SIZE";
+const DEFAULT_SIZE: &str = "1G";
+
+/// Ruby's `mkmf` uses these when `CC` and `CXX` are not set
+const DEFAULT_CC: &str = "gcc";
+const DEFAULT_CXX: &str = "g++";
+
+/// Name of the `compiler_cache` layer
+pub(crate) fn layer_name() -> LayerName {
+    layer_name!("compiler_cache")
+}
+
+pub(crate) fn enabled(env: &Env) -> bool {
+    env.get(ENABLE_ENV_KEY)
+        .is_some_and(|value| matches!(value.to_str(), Some("1" | "true")))
+}
+
+/// A `ccache` directory used by `bundle install`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CompilerCache {
+    dir: PathBuf,
+    max_size: String,
+}
+
+impl CompilerCache {
+    /// Wraps `CC` and `CXX` with `ccache` and points it at the cache directory
+    pub(crate) fn install_env(&self, env: &Env) -> Env {
+        let mut env = env.clone();
+        for (key, default) in [("CC", DEFAULT_CC), ("CXX", DEFAULT_CXX)] {
+            let compiler = env
+                .get(key)
+                .map_or_else(|| OsString::from(default), Clone::clone);
+            if !compiler.to_string_lossy().starts_with("ccache ") {
+                let mut wrapped = OsString::from("ccache ");
+                wrapped.push(compiler);
+                env.insert(key, wrapped);
+            }
+        }
+        env.insert("CCACHE_DIR", &self.dir);
+        env.insert("CCACHE_MAXSIZE", &self.max_size);
+        // The default compares compiler mtimes, which change when the base image is rebuilt
+        env.insert("CCACHE_COMPILERCHECK", "content");
+        env
+    }
+
+    /// Clears statistics so they only reflect the current build
+    fn zero_stats(&self, env: &Env) -> Result<(), fun_run::CmdError> {
+        Command::new("ccache")
+            .arg("--zero-stats")
+            .env_clear()
+            .envs(&self.install_env(env))
+            .named_output()
+            .map(|_| ())
+    }
+
+    /// Prunes the cache to its size limit and reports hits and misses
+    pub(crate) fn prune(
+        &self,
+        mut bullet: Print<SubBullet<RedactedStdout>>,
+        env: &Env,
+    ) -> Print<SubBullet<RedactedStdout>> {
+        let env = self.install_env(env);
+        if let Some(stats) = Command::new("ccache")
+            .arg("--print-stats")
+            .env_clear()
+            .envs(&env)
+            .named_output()
+            .ok()
+            .and_then(|output| Stats::parse(&output.stdout_lossy()))
+        {
+            bullet = bullet.sub_bullet(stats.to_string());
+        }
+
+        let mut cmd = Command::new("ccache");
+        cmd.arg("--cleanup").env_clear().envs(&env);
+        let timer = bullet.start_timer(format!(
+            "Pruning to {size} with {command}",
+            size = style::value(&self.max_size),
+            command = style::command(cmd.name())
+        ));
+        match cmd.named_output() {
+            Ok(_) => timer.done(),
+            Err(error) => timer.done().sub_bullet(format!(
+                "{warning} Could not prune compiler cache: {error}",
+                warning = style::important("WARNING")
+            )),
+        }
+    }
+}
+
+pub(crate) fn handle(
+    context: &BuildContext<RubyBuildpack>,
+    env: &Env,
+    mut bullet: Print<SubBullet<RedactedStdout>>,
+    metadata: &Metadata,
+) -> libcnb::Result<(Print<SubBullet<RedactedStdout>>, Option<CompilerCache>), RubyBuildpackError> {
+    if !on_path(env, "ccache") {
+        bullet = bullet.sub_bullet(format!(
+            "Skipping compiler cache ({ccache} not found on the {path})",
+            ccache = style::value("ccache"),
+            path = style::value("PATH")
+        ));
+        remove_stale(context)?;
+        return Ok((bullet, None));
+    }
+
+    bullet = explain_cache(bullet, context, &layer_name(), metadata)?;
+    let layer_ref = DiffMigrateLayer {
+        build: true,
+        launch: false,
+    }
+    .cached_layer(layer_name(), context, metadata)?;
+    match &layer_ref.state {
+        LayerState::Restored { cause } => {
+            bullet = bullet.sub_bullet(cause);
+        }
+        LayerState::Empty { cause } => match cause {
+            EmptyLayerCause::NewlyCreated => {}
+            EmptyLayerCause::InvalidMetadataAction { cause }
+            | EmptyLayerCause::RestoredLayerAction { cause } => {
+                bullet = bullet.sub_bullet(cause);
+            }
+        },
+    }
+
+    let compiler_cache = CompilerCache {
+        dir: layer_ref.path().join("ccache"),
+        max_size: env.get(SIZE_ENV_KEY).map_or_else(
+            || DEFAULT_SIZE.to_string(),
+            |value| value.to_string_lossy().into_owned(),
+        ),
+    };
+    bullet = bullet.sub_bullet(format!(
+        "Wrapping {cc} and {cxx} with {ccache} (limit {size})",
+        cc = style::value("CC"),
+        cxx = style::value("CXX"),
+        ccache = style::value("ccache"),
+        size = style::value(&compiler_cache.max_size)
+    ));
+    if let Err(error) = compiler_cache.zero_stats(env) {
+        bullet = bullet.sub_bullet(format!(
+            "{warning} Could not reset compiler cache statistics: {error}",
+            warning = style::important("WARNING")
+        ));
+    }
+
+    Ok((bullet, Some(compiler_cache)))
+}
+
+/// Deletes the layer from a prior build, for when the compiler cache is no longer enabled
+pub(crate) fn remove_stale(
+    context: &BuildContext<RubyBuildpack>,
+) -> libcnb::Result<(), RubyBuildpackError> {
+    remove_stale_layer(context, &layer_name())
+}
+
+fn on_path(env: &Env, name: &str) -> bool {
+    env.get("PATH")
+        .is_some_and(|path| std::env::split_paths(path).any(|dir| dir.join(name).is_file()))
+}
+
+/// Compile results from `ccache --print-stats`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Stats {
+    hits: u64,
+    misses: u64,
+}
+
+impl Stats {
+    /// Returns `None` for versions of `ccache` without machine readable output
+    fn parse(output: &str) -> Option<Self> {
+        let value = |key: &str| {
+            output.lines().find_map(|line| {
+                let (name, value) = line.split_once('\t')?;
+                (name == key).then(|| value.trim().parse::<u64>().ok())?
+            })
+        };
+
+        Some(Self {
+            hits: value("direct_cache_hit")? + value("preprocessed_cache_hit")?,
+            misses: value("cache_miss")?,
+        })
+    }
+}
+
+impl std::fmt::Display for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.hits + self.misses == 0 {
+            write!(f, "No native extensions compiled")
+        } else {
+            write!(
+                f,
+                "Compiler cache hits: {hits}, misses: {misses}",
+                hits = self.hits,
+                misses = self.misses
+            )
+        }
+    }
+}
+
+pub(crate) type Metadata = MetadataV1;
+try_migrate_deserializer_chain!(
+    deserializer: toml::Deserializer::new,
+    error: MetadataError,
+    chain: [MetadataV1],
+);
+
+/// Compiled objects are only valid on the system they were compiled for
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, CacheDiff)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct MetadataV1 {
+    #[cache_diff(rename = "OS Distribution")]
+    pub(crate) os_distribution: OsDistribution,
+    #[cache_diff(rename = "CPU Architecture")]
+    pub(crate) cpu_architecture: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum MetadataError {
+    // Update if migrating between a metadata version can error
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_install_env_wraps_compilers() {
+        let compiler_cache = CompilerCache {
+            dir: PathBuf::from("layers/compiler_cache/ccache"),
+            max_size: String::from("500M"),
+        };
+        let mut env = Env::new();
+        env.insert("CC", "clang");
+
+        let env = compiler_cache.install_env(&env);
+        assert_eq!(env.get("CC").unwrap(), "ccache clang");
+        assert_eq!(env.get("CXX").unwrap(), "ccache g++");
+        assert_eq!(
+            env.get("CCACHE_DIR").unwrap(),
+            "layers/compiler_cache/ccache"
+        );
+        assert_eq!(env.get("CCACHE_MAXSIZE").unwrap(), "500M");
+
+        // Not wrapped twice
+        let env = compiler_cache.install_env(&env);
+        assert_eq!(env.get("CC").unwrap(), "ccache clang");
+    }
+
+    #[test]
+    fn test_stats_parse() {
+        let output = "cache_miss\t3\ndirect_cache_hit\t10\npreprocessed_cache_hit\t2\nstats_updated_timestamp\t1700000000\n";
+        let stats = Stats::parse(output).unwrap();
+        assert_eq!(
+            stats,
+            Stats {
+                hits: 12,
+                misses: 3
+            }
+        );
+        assert_eq!(stats.to_string(), "Compiler cache hits: 12, misses: 3");
+
+        assert_eq!(Stats::parse("cache hit (direct)   10"), None);
+        assert_eq!(
+            Stats { hits: 0, misses: 0 }.to_string(),
+            "No native extensions compiled"
+        );
+    }
+
+    #[test]
+    fn test_enabled() {
+        let mut env = Env::new();
+        assert!(!enabled(&env));
+        env.insert(ENABLE_ENV_KEY, "1");
+        assert!(enabled(&env));
+        env.insert(ENABLE_ENV_KEY, "0");
+        assert!(!enabled(&env));
+    }
+}
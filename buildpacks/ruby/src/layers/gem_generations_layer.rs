@@ -0,0 +1,537 @@
+//! Keeps past generations of the `gems` layer for fast rollbacks
+//!
+//! The `gems` layer only holds the result of the last `bundle install`. Rolling back a deploy to
+//! an older `Gemfile.lock` would otherwise re-install every gem that changed. After each install
+//! the contents of the `gems` layer are copied into this cache-only layer (`build: false,
+//! launch: false`) under a key derived from the `Gemfile.lock`, `Gemfile`, and the groups
+//! excluded with `BUNDLE_WITHOUT`. When a later build has a matching key, that generation is
+//! copied back instead of running `bundle install`.
+//!
+//! Disabled by default. Every generation is a full copy of the gems, so the cache grows by up
+//! to `HEROKU_BUNDLE_GENERATIONS_SIZE_MB` (default `1024`) megabytes. Set
+//! `HEROKU_BUNDLE_GENERATIONS` to the number of generations to keep, they're evicted in least
+//! recently used order.
+//!
+//! Native extensions depend on the OS, architecture, and Ruby version the same way as the
+//! `gems` layer, so the whole layer is cleared when one of those changes.
+use crate::layers::shared::{explain_cache, remove_stale_layer};
+use crate::redact::RedactedStdout;
+use crate::target_id::OsDistribution;
+use crate::{RubyBuildpack, RubyBuildpackError};
+use bullet_stream::state::SubBullet;
+use bullet_stream::{style, Print};
+use cache_diff::CacheDiff;
+use commons::gemfile_lock::ResolvedRubyVersion;
+use commons::layer::diff_migrate::DiffMigrateLayer;
+use libcnb::build::BuildContext;
+use libcnb::data::layer::LayerName;
+use libcnb::data::layer_name;
+use libcnb::layer::{EmptyLayerCause, LayerState};
+use libcnb::Env;
+use magic_migrate::{try_migrate_deserializer_chain, TryMigrate};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Number of generations to keep, `0` disables
+pub(crate) const COUNT_ENV_KEY: &str = "HEROKU_BUNDLE_GENERATIONS";
+/// Combined size limit of all generations in megabytes
+pub(crate) const SIZE_ENV_KEY: &str = "HEROKU_BUNDLE_GENERATIONS_SIZE_MB";
+const DEFAULT_COUNT: usize = 0;
+const DEFAULT_SIZE_MB: u64 = 1024;
+
+/// Directories of the `gems` layer written by `bundle install` via `BUNDLE_PATH` and `BUNDLE_BIN`
+const GEM_DIRS: [&str; 2] = ["ruby", "bin"];
+const INDEX_FILE: &str = "generations.toml";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Limits {
+    pub(crate) count: usize,
+    pub(crate) bytes: u64,
+}
+
+impl Limits {
+    pub(crate) fn from_env(env: &Env) -> Self {
+        let number = |key: &str| {
+            env.get(key)
+                .and_then(|value| value.to_string_lossy().trim().parse::<u64>().ok())
+        };
+        Self {
+            count: number(COUNT_ENV_KEY)
+                .and_then(|count| usize::try_from(count).ok())
+                .unwrap_or(DEFAULT_COUNT),
+            bytes: number(SIZE_ENV_KEY).unwrap_or(DEFAULT_SIZE_MB) * 1024 * 1024,
+        }
+    }
+}
+
+/// Name of the `gems_generations` layer
+pub(crate) fn layer_name() -> LayerName {
+    layer_name!("gems_generations")
+}
+
+/// Content address of an installed gem set
+pub(crate) fn key(gemfile_lock: &str, gemfile: &str, without: &[String]) -> String {
+    let mut hasher = Sha256::new();
+    for part in [gemfile_lock, gemfile, &without.join(":")] {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct Index {
+    #[serde(default)]
+    generation: Vec<Generation>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Generation {
+    pub(crate) key: String,
+    /// Incremented on every use, the lowest value is the least recently used
+    last_used: u64,
+    pub(crate) bytes: u64,
+}
+
+impl Generation {
+    pub(crate) fn short_key(&self) -> &str {
+        &self.key[..self.key.len().min(12)]
+    }
+}
+
+/// Generations stored in the cache-only layer
+#[derive(Debug)]
+pub(crate) struct Generations {
+    dir: PathBuf,
+    limits: Limits,
+    index: Index,
+    /// Key of the gems for the current build, see [`key`]
+    key: String,
+}
+
+impl Generations {
+    fn load(dir: &Path, limits: Limits, key: String) -> Self {
+        // An unreadable index loses track of the generations, they're evicted on the next save
+        let index = fs_err::read_to_string(dir.join(INDEX_FILE))
+            .ok()
+            .and_then(|contents| toml::from_str(&contents).ok())
+            .unwrap_or_default();
+        Self {
+            dir: dir.to_path_buf(),
+            limits,
+            index,
+            key,
+        }
+    }
+
+    fn write_index(&self) -> std::io::Result<()> {
+        fs_err::write(
+            self.dir.join(INDEX_FILE),
+            toml::to_string(&self.index).map_err(std::io::Error::other)?,
+        )
+    }
+
+    fn next_use(&self) -> u64 {
+        self.index
+            .generation
+            .iter()
+            .map(|generation| generation.last_used)
+            .max()
+            .unwrap_or(0)
+            + 1
+    }
+
+    /// Replaces the gem directories of `layer_path` with the generation matching the current
+    /// key, returns it when found
+    pub(crate) fn restore(&mut self, layer_path: &Path) -> std::io::Result<Option<Generation>> {
+        let next_use = self.next_use();
+        let Some(generation) = self
+            .index
+            .generation
+            .iter_mut()
+            .find(|generation| generation.key == self.key)
+        else {
+            return Ok(None);
+        };
+        let source = self.dir.join(&generation.key);
+        if !source.is_dir() {
+            return Ok(None);
+        }
+        for name in GEM_DIRS {
+            remove_dir_if_exists(&layer_path.join(name))?;
+        }
+        copy_gem_dirs(&source, layer_path)?;
+        generation.last_used = next_use;
+        let generation = generation.clone();
+        self.write_index()?;
+
+        Ok(Some(generation))
+    }
+
+    /// Deletes the generation matching the current key so the next [`Generations::save`] stores
+    /// a fresh copy, for a restored generation that turned out to be incomplete
+    pub(crate) fn forget(&mut self) -> std::io::Result<()> {
+        self.index
+            .generation
+            .retain(|generation| generation.key != self.key);
+        remove_dir_if_exists(&self.dir.join(&self.key))?;
+        self.write_index()
+    }
+
+    /// Stores the gem directories of `layer_path` under the current key (unless already stored)
+    /// and evicts generations beyond the limits, returns the evicted generations
+    pub(crate) fn save(&mut self, layer_path: &Path) -> std::io::Result<Vec<Generation>> {
+        let key = self.key.clone();
+        let next_use = self.next_use();
+        let target = self.dir.join(&key);
+        if let Some(generation) = self
+            .index
+            .generation
+            .iter_mut()
+            .find(|generation| generation.key == key)
+            .filter(|_| target.is_dir())
+        {
+            generation.last_used = next_use;
+        } else {
+            self.index
+                .generation
+                .retain(|generation| generation.key != key);
+            remove_dir_if_exists(&target)?;
+            fs_err::create_dir_all(&target)?;
+            copy_gem_dirs(layer_path, &target)?;
+            self.index.generation.push(Generation {
+                key,
+                last_used: next_use,
+                bytes: dir_size(&target)?,
+            });
+        }
+
+        let evicted = evict(&mut self.index.generation, self.limits);
+        for generation in &evicted {
+            remove_dir_if_exists(&self.dir.join(&generation.key))?;
+        }
+        // Directories not tracked by the index, for example from an unreadable index
+        for entry in fs_err::read_dir(&self.dir)? {
+            let path = entry?.path();
+            let tracked = path.file_name().is_some_and(|name| {
+                self.index
+                    .generation
+                    .iter()
+                    .any(|generation| name == generation.key.as_str())
+            });
+            if path.is_dir() && !tracked {
+                remove_dir_if_exists(&path)?;
+            }
+        }
+        self.write_index()?;
+
+        Ok(evicted)
+    }
+
+    /// The stored generation for the current key
+    pub(crate) fn current(&self) -> Option<&Generation> {
+        self.index
+            .generation
+            .iter()
+            .find(|generation| generation.key == self.key)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.index.generation.len()
+    }
+}
+
+/// Keeps the most recently used generations that fit within the limits, returns the rest
+fn evict(generations: &mut Vec<Generation>, limits: Limits) -> Vec<Generation> {
+    generations.sort_by_key(|generation| std::cmp::Reverse(generation.last_used));
+    let mut total = 0;
+    let (keep, evict): (Vec<_>, Vec<_>) = std::mem::take(generations)
+        .into_iter()
+        .enumerate()
+        .partition(|(index, generation)| {
+            let fits = *index < limits.count && total + generation.bytes <= limits.bytes;
+            if fits {
+                total += generation.bytes;
+            }
+            fits
+        });
+    *generations = keep.into_iter().map(|(_, generation)| generation).collect();
+    evict
+        .into_iter()
+        .map(|(_, generation)| generation)
+        .collect()
+}
+
+fn copy_gem_dirs(from: &Path, to: &Path) -> std::io::Result<()> {
+    for name in GEM_DIRS {
+        let source = from.join(name);
+        if !source.exists() {
+            continue;
+        }
+        // Preserves symlinks and permissions of installed gems
+        let output = Command::new("cp").arg("-a").arg(&source).arg(to).output()?;
+        if !output.status.success() {
+            return Err(std::io::Error::other(format!(
+                "Could not copy {} to {}: {}",
+                source.display(),
+                to.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn remove_dir_if_exists(path: &Path) -> std::io::Result<()> {
+    match fs_err::remove_dir_all(path) {
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+fn dir_size(path: &Path) -> std::io::Result<u64> {
+    let mut total = 0;
+    for entry in fs_err::read_dir(path)? {
+        let entry = entry?;
+        let metadata = fs_err::symlink_metadata(entry.path())?;
+        total += if metadata.is_dir() {
+            dir_size(&entry.path())?
+        } else {
+            metadata.len()
+        };
+    }
+    Ok(total)
+}
+
+/// Formats bytes as megabytes for output
+pub(crate) fn megabytes(bytes: u64) -> String {
+    #[allow(clippy::cast_precision_loss)]
+    let megabytes = bytes as f64 / (1024.0 * 1024.0);
+    format!("{megabytes:.1} MB")
+}
+
+/// Loads the generations layer, returns `None` when generations are disabled
+pub(crate) fn handle(
+    context: &BuildContext<RubyBuildpack>,
+    env: &Env,
+    mut bullet: Print<SubBullet<RedactedStdout>>,
+    metadata: &Metadata,
+    key: String,
+) -> libcnb::Result<(Print<SubBullet<RedactedStdout>>, Option<Generations>), RubyBuildpackError> {
+    let limits = Limits::from_env(env);
+    if limits.count == 0 {
+        remove_stale(context)?;
+        return Ok((bullet, None));
+    }
+
+    bullet = explain_cache(bullet, context, &layer_name(), metadata)?;
+    let layer_ref = DiffMigrateLayer {
+        build: false,
+        launch: false,
+    }
+    .cached_layer(layer_name(), context, metadata)?;
+    match &layer_ref.state {
+        LayerState::Restored { .. }
+        | LayerState::Empty {
+            cause: EmptyLayerCause::NewlyCreated,
+        } => {}
+        LayerState::Empty {
+            cause:
+                EmptyLayerCause::InvalidMetadataAction { cause }
+                | EmptyLayerCause::RestoredLayerAction { cause },
+        } => {
+            bullet = bullet.sub_bullet(format!("Gem generations: {cause}"));
+        }
+    }
+
+    Ok((
+        bullet,
+        Some(Generations::load(&layer_ref.path(), limits, key)),
+    ))
+}
+
+/// Deletes the layer from a prior build, for when generations are disabled
+pub(crate) fn remove_stale(
+    context: &BuildContext<RubyBuildpack>,
+) -> libcnb::Result<(), RubyBuildpackError> {
+    remove_stale_layer(context, &layer_name())
+}
+
+/// Reports a problem with the generations without failing the build, they're only an optimization
+pub(crate) fn warn(
+    bullet: Print<SubBullet<RedactedStdout>>,
+    error: &std::io::Error,
+) -> Print<SubBullet<RedactedStdout>> {
+    bullet.sub_bullet(format!(
+        "{warning} Could not use previous gem generations: {error}",
+        warning = style::important("WARNING")
+    ))
+}
+
+pub(crate) type Metadata = MetadataV1;
+try_migrate_deserializer_chain!(
+    deserializer: toml::Deserializer::new,
+    error: MetadataError,
+    chain: [MetadataV1],
+);
+
+/// Native extensions are only valid on the system and Ruby version they were compiled for
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, CacheDiff)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct MetadataV1 {
+    #[cache_diff(rename = "OS Distribution")]
+    pub(crate) os_distribution: OsDistribution,
+    #[cache_diff(rename = "CPU Architecture")]
+    pub(crate) cpu_architecture: String,
+    #[cache_diff(rename = "Ruby version")]
+    pub(crate) ruby_version: ResolvedRubyVersion,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum MetadataError {
+    // Update if migrating between a metadata version can error
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn generation(key: &str, last_used: u64, bytes: u64) -> Generation {
+        Generation {
+            key: key.to_string(),
+            last_used,
+            bytes,
+        }
+    }
+
+    #[test]
+    fn test_key() {
+        let without = vec![String::from("development"), String::from("test")];
+        assert_eq!(
+            key("lock", "gemfile", &without),
+            key("lock", "gemfile", &without)
+        );
+        assert_ne!(
+            key("lock", "gemfile", &without),
+            key("lock2", "gemfile", &without)
+        );
+        assert_ne!(
+            key("lock", "gemfile", &without),
+            key("lock", "gemfile", &[])
+        );
+        assert_eq!(key("lock", "gemfile", &without).len(), 64);
+    }
+
+    #[test]
+    fn test_evict_lru() {
+        let mut generations = vec![
+            generation("a", 1, 10),
+            generation("b", 3, 10),
+            generation("c", 2, 10),
+        ];
+        let evicted = evict(
+            &mut generations,
+            Limits {
+                count: 2,
+                bytes: 100,
+            },
+        );
+        assert_eq!(evicted, vec![generation("a", 1, 10)]);
+        assert_eq!(
+            generations,
+            vec![generation("b", 3, 10), generation("c", 2, 10)]
+        );
+
+        let evicted = evict(
+            &mut generations,
+            Limits {
+                count: 2,
+                bytes: 15,
+            },
+        );
+        assert_eq!(evicted, vec![generation("c", 2, 10)]);
+        assert_eq!(generations, vec![generation("b", 3, 10)]);
+    }
+
+    #[test]
+    fn test_save_and_restore() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let layer = tmpdir.path().join("gems");
+        let store = tmpdir.path().join("gems_generations");
+        fs_err::create_dir_all(&store).unwrap();
+        let limits = Limits {
+            count: 2,
+            bytes: 1024,
+        };
+
+        let install = |version: &str| {
+            let _ = fs_err::remove_dir_all(&layer);
+            fs_err::create_dir_all(layer.join("ruby").join("3.2.0")).unwrap();
+            fs_err::write(layer.join("ruby").join("3.2.0").join("VERSION"), version).unwrap();
+        };
+
+        let build = |key: &str| Generations::load(&store, limits, key.to_string());
+        install("one");
+        assert!(build("one").save(&layer).unwrap().is_empty());
+        install("two");
+        assert!(build("two").save(&layer).unwrap().is_empty());
+
+        let mut generations = build("one");
+        assert_eq!(generations.len(), 2);
+        let restored = generations.restore(&layer).unwrap().unwrap();
+        assert_eq!(restored.key, "one");
+        assert_eq!(
+            fs_err::read_to_string(layer.join("ruby").join("3.2.0").join("VERSION")).unwrap(),
+            "one"
+        );
+        assert!(build("missing").restore(&layer).unwrap().is_none());
+        assert!(build("missing").current().is_none());
+
+        // "two" is now the least recently used
+        install("three");
+        let evicted = build("three").save(&layer).unwrap();
+        assert_eq!(
+            evicted.iter().map(|g| g.key.as_str()).collect::<Vec<_>>(),
+            vec!["two"]
+        );
+        assert!(!store.join("two").exists());
+        assert!(store.join("one").is_dir());
+        assert!(store.join("three").is_dir());
+    }
+
+    #[test]
+    fn test_forget_replaces_incomplete_generation() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let layer = tmpdir.path().join("gems");
+        let store = tmpdir.path().join("gems_generations");
+        fs_err::create_dir_all(&store).unwrap();
+        let limits = Limits {
+            count: 2,
+            bytes: 1024,
+        };
+        let version_file = layer.join("ruby").join("3.2.0").join("VERSION");
+        fs_err::create_dir_all(version_file.parent().unwrap()).unwrap();
+
+        fs_err::write(&version_file, "incomplete").unwrap();
+        let _ = Generations::load(&store, limits, "one".to_string())
+            .save(&layer)
+            .unwrap();
+
+        // Restored generation was missing gems, `bundle install` runs before saving again
+        let mut generations = Generations::load(&store, limits, "one".to_string());
+        assert!(generations.restore(&layer).unwrap().is_some());
+        generations.forget().unwrap();
+        assert!(generations.current().is_none());
+        fs_err::write(&version_file, "complete").unwrap();
+        let _ = generations.save(&layer).unwrap();
+
+        fs_err::write(&version_file, "other").unwrap();
+        let mut generations = Generations::load(&store, limits, "one".to_string());
+        assert!(generations.restore(&layer).unwrap().is_some());
+        assert_eq!(fs_err::read_to_string(&version_file).unwrap(), "complete");
+    }
+}
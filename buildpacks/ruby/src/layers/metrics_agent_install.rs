@@ -1,15 +1,18 @@
+use crate::redact::RedactedStdout;
 use crate::{RubyBuildpack, RubyBuildpackError};
 use bullet_stream::state::SubBullet;
 use bullet_stream::{style, Print};
+use commons::layer::clear_cache::ClearCache;
 use flate2::read::GzDecoder;
 use libcnb::additional_buildpack_binary_path;
+use libcnb::data::layer::LayerName;
 use libcnb::data::layer_name;
 use libcnb::layer::{
     CachedLayerDefinition, EmptyLayerCause, InvalidMetadataAction, LayerState, RestoredLayerAction,
 };
+use libcnb::Platform;
 use libherokubuildpack::digest::sha256;
 use serde::{Deserialize, Serialize};
-use std::io::Stdout;
 use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
 use tar::Archive;
@@ -60,22 +63,30 @@ pub(crate) enum MetricsAgentInstallError {
     ChecksumFailed(String),
 }
 
+/// Name of the `metrics_agent` layer
+pub(crate) fn layer_name() -> LayerName {
+    layer_name!("metrics_agent")
+}
+
 pub(crate) fn handle_metrics_agent_layer(
     context: &libcnb::build::BuildContext<RubyBuildpack>,
-    mut bullet: Print<SubBullet<Stdout>>,
-) -> libcnb::Result<Print<SubBullet<Stdout>>, RubyBuildpackError> {
+    mut bullet: Print<SubBullet<RedactedStdout>>,
+) -> libcnb::Result<Print<SubBullet<RedactedStdout>>, RubyBuildpackError> {
     let metadata = Metadata {
         download_url: DOWNLOAD_URL.to_string(),
     };
 
+    let clear = ClearCache::from_env(context.platform.env()).message(&layer_name());
     let layer_ref = context.cached_layer(
-        layer_name!("metrics_agent"),
+        layer_name(),
         CachedLayerDefinition {
             build: true,
             launch: true,
             invalid_metadata_action: &|_| InvalidMetadataAction::DeleteLayer,
             restored_layer_action: &|old: &Metadata, _| {
-                if old == &metadata {
+                if let Some(message) = &clear {
+                    (RestoredLayerAction::DeleteLayer, message.clone())
+                } else if old == &metadata {
                     (
                         RestoredLayerAction::KeepLayer,
                         style::url(old.download_url.clone()),
@@ -100,6 +111,9 @@ pub(crate) fn handle_metrics_agent_layer(
                 EmptyLayerCause::InvalidMetadataAction { .. } => {
                     bullet = bullet.sub_bullet("Clearing cache (invalid metadata)");
                 }
+                EmptyLayerCause::RestoredLayerAction { cause } if clear.is_some() => {
+                    bullet = bullet.sub_bullet(cause);
+                }
                 EmptyLayerCause::RestoredLayerAction { cause: url } => {
                     bullet = bullet.sub_bullet(format!("Deleting cached metrics agent ({url})"));
                 }
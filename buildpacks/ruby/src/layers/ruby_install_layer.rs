@@ -11,6 +11,9 @@
 //!
 //! When the Ruby version changes, invalidate and re-run.
 //!
+use crate::layers::shared::explain_cache;
+use crate::redact::RedactedStdout;
+use crate::sbom;
 use crate::target_id::OsDistribution;
 use crate::{
     target_id::{TargetId, TargetIdError},
@@ -22,29 +25,36 @@ use cache_diff::CacheDiff;
 use commons::gemfile_lock::ResolvedRubyVersion;
 use commons::layer::diff_migrate::{DiffMigrateLayer, LayerRename};
 use flate2::read::GzDecoder;
+use libcnb::data::layer::LayerName;
 use libcnb::data::layer_name;
 use libcnb::layer::{EmptyLayerCause, LayerState};
 use libcnb::layer_env::LayerEnv;
 use magic_migrate::{try_migrate_deserializer_chain, TryMigrate};
 use serde::{Deserialize, Serialize};
-use std::io::{self, Stdout};
+use std::io;
 use std::path::Path;
 use tar::Archive;
 use tempfile::NamedTempFile;
 use url::Url;
 
+/// Name of the `binruby` layer
+pub(crate) fn layer_name() -> LayerName {
+    layer_name!("binruby")
+}
+
 pub(crate) fn handle(
     context: &libcnb::build::BuildContext<RubyBuildpack>,
-    mut bullet: Print<SubBullet<Stdout>>,
+    mut bullet: Print<SubBullet<RedactedStdout>>,
     metadata: &Metadata,
-) -> libcnb::Result<(Print<SubBullet<Stdout>>, LayerEnv), RubyBuildpackError> {
+) -> libcnb::Result<(Print<SubBullet<RedactedStdout>>, LayerEnv), RubyBuildpackError> {
+    bullet = explain_cache(bullet, context, &layer_name(), metadata)?;
     let layer_ref = DiffMigrateLayer {
         build: true,
         launch: true,
     }
     .cached_layer_rename(
         LayerRename {
-            to: layer_name!("binruby"),
+            to: layer_name(),
             from: vec![layer_name!("ruby")],
         },
         context,
@@ -67,6 +77,13 @@ pub(crate) fn handle(
             bullet = timer.done();
         }
     }
+    let url = download_url(&metadata.target_id(), &metadata.ruby_version)
+        .map_err(RubyBuildpackError::RubyInstallError)?;
+    layer_ref.write_sboms(&sbom::sboms(
+        &sbom::Tool::from_context(context),
+        "binruby",
+        &[sbom::ruby(&metadata.ruby_version.to_string(), url.as_str())],
+    ))?;
     Ok((bullet, layer_ref.read_env()?))
 }
 
@@ -1,3 +1,56 @@
+use crate::redact::RedactedStdout;
+use crate::{RubyBuildpack, RubyBuildpackError};
+use bullet_stream::state::SubBullet;
+use bullet_stream::Print;
+use cache_diff::CacheDiff;
+use commons::layer::diff_migrate;
+use fs_err::PathExt;
+use libcnb::build::BuildContext;
+use libcnb::data::layer::LayerName;
+use libcnb::layer::LayerError;
+use magic_migrate::TryMigrate;
+use serde::Serialize;
+use std::fmt::Debug;
+
+/// Deletes a layer left over from a prior build, for when a layer is no longer used
+pub(crate) fn remove_stale_layer<B: libcnb::Buildpack>(
+    context: &BuildContext<B>,
+    name: &LayerName,
+) -> libcnb::Result<(), B::Error> {
+    let layer_dir = context.layers_dir.join(name.as_str());
+    if layer_dir.fs_err_try_exists().map_err(LayerError::IoError)? {
+        fs_err::remove_dir_all(&layer_dir).map_err(LayerError::IoError)?;
+        let layer_toml = layer_dir.with_extension("toml");
+        if layer_toml
+            .fs_err_try_exists()
+            .map_err(LayerError::IoError)?
+        {
+            fs_err::remove_file(layer_toml).map_err(LayerError::IoError)?;
+        }
+    }
+    Ok(())
+}
+
+/// Prints why a layer's cache is kept or cleared when `HEROKU_CACHE_EXPLAIN` is set
+///
+/// Call before `DiffMigrateLayer::cached_layer`, which replaces the metadata of the prior build.
+pub(crate) fn explain_cache<M>(
+    bullet: Print<SubBullet<RedactedStdout>>,
+    context: &BuildContext<RubyBuildpack>,
+    layer_name: &LayerName,
+    metadata: &M,
+) -> libcnb::Result<Print<SubBullet<RedactedStdout>>, RubyBuildpackError>
+where
+    M: CacheDiff + TryMigrate + Serialize + Debug + Clone,
+{
+    Ok(
+        match diff_migrate::explain(layer_name, context, metadata)? {
+            Some(explanation) => bullet.sub_bullet(explanation.to_string()),
+            None => bullet,
+        },
+    )
+}
+
 /// Takes in a directory and returns a minimal build context for use in testing shared caching behavior
 ///
 /// Intented only for use with this buildpack, but meant to be used by multiple layers to assert caching behavior.
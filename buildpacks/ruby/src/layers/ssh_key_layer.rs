@@ -0,0 +1,135 @@
+//! Writes an SSH setup for fetching private git sources
+//!
+//! When `HEROKU_BUNDLE_SSH_KEY` is set, the key is written to a build-only layer along with an
+//! SSH config that uses it. Git is pointed at the config via `GIT_SSH_COMMAND`. The layer is not
+//! cached and not available at launch so the key never leaves the build.
+use crate::credentials::{SSH_KEY_ENV_KEY, SSH_KNOWN_HOSTS_ENV_KEY};
+use crate::redact::RedactedStdout;
+use crate::{RubyBuildpack, RubyBuildpackError};
+use bullet_stream::state::SubBullet;
+use bullet_stream::{style, Print};
+use fs_err::os::unix::fs::OpenOptionsExt;
+use libcnb::data::layer::LayerName;
+use libcnb::data::layer_name;
+use libcnb::layer::UncachedLayerDefinition;
+use libcnb::layer_env::{LayerEnv, ModificationBehavior, Scope};
+use std::io::Write;
+use std::path::Path;
+
+/// Name of the `ssh` layer
+pub(crate) fn layer_name() -> LayerName {
+    layer_name!("ssh")
+}
+
+pub(crate) fn handle(
+    context: &libcnb::build::BuildContext<RubyBuildpack>,
+    bullet: Print<SubBullet<RedactedStdout>>,
+    key: &str,
+    known_hosts: Option<&str>,
+) -> libcnb::Result<(Print<SubBullet<RedactedStdout>>, LayerEnv), RubyBuildpackError> {
+    let layer_ref = context.uncached_layer(
+        layer_name(),
+        UncachedLayerDefinition {
+            build: true,
+            launch: false,
+        },
+    )?;
+    write_ssh_dir(&layer_ref.path(), key, known_hosts)
+        .map_err(RubyBuildpackError::SshKeyWriteError)?;
+    layer_ref.write_env(LayerEnv::new().chainable_insert(
+        Scope::Build,
+        ModificationBehavior::Override,
+        "GIT_SSH_COMMAND",
+        format!("ssh -F {}", layer_ref.path().join("config").display()),
+    ))?;
+
+    let mut bullet = bullet.sub_bullet(format!(
+        "Using SSH key from {} for git sources",
+        style::value(SSH_KEY_ENV_KEY)
+    ));
+    if known_hosts.is_none() {
+        bullet = bullet.sub_bullet(format!(
+            "Trusting git hosts on first use, set {} to verify them",
+            style::value(SSH_KNOWN_HOSTS_ENV_KEY)
+        ));
+    }
+
+    Ok((bullet, layer_ref.read_env()?))
+}
+
+/// Writes the equivalent of `~/.ssh` to the given directory
+fn write_ssh_dir(dir: &Path, key: &str, known_hosts: Option<&str>) -> Result<(), std::io::Error> {
+    let key_path = dir.join("id_key");
+    let known_hosts_path = dir.join("known_hosts");
+
+    // Created without group or world access, SSH refuses to use the key otherwise
+    fs_err::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&key_path)?
+        .write_all(with_trailing_newline(key).as_bytes())?;
+    fs_err::write(
+        &known_hosts_path,
+        known_hosts.map(with_trailing_newline).unwrap_or_default(),
+    )?;
+    fs_err::write(
+        dir.join("config"),
+        format!(
+            "Host *\n  IdentityFile {key}\n  IdentitiesOnly yes\n  UserKnownHostsFile {known_hosts}\n  StrictHostKeyChecking {checking}\n",
+            key = key_path.display(),
+            known_hosts = known_hosts_path.display(),
+            checking = if known_hosts.is_some() {
+                "yes"
+            } else {
+                "accept-new"
+            }
+        ),
+    )?;
+
+    Ok(())
+}
+
+/// SSH rejects keys without a trailing newline
+fn with_trailing_newline(contents: &str) -> String {
+    format!("{}\n", contents.trim_end())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_write_ssh_dir() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let dir = tmpdir.path();
+
+        write_ssh_dir(dir, "-----BEGIN KEY-----\nabc\n-----END KEY-----", None).unwrap();
+        assert_eq!(
+            fs_err::read_to_string(dir.join("id_key")).unwrap(),
+            "-----BEGIN KEY-----\nabc\n-----END KEY-----\n"
+        );
+        assert_eq!(
+            fs_err::metadata(dir.join("id_key"))
+                .unwrap()
+                .permissions()
+                .mode()
+                & 0o777,
+            0o600
+        );
+        let config = fs_err::read_to_string(dir.join("config")).unwrap();
+        assert!(config.contains(&format!("IdentityFile {}", dir.join("id_key").display())));
+        assert!(config.contains("StrictHostKeyChecking accept-new"));
+
+        write_ssh_dir(dir, "key", Some("github.com ssh-ed25519 AAAA")).unwrap();
+        assert_eq!(
+            fs_err::read_to_string(dir.join("known_hosts")).unwrap(),
+            "github.com ssh-ed25519 AAAA\n"
+        );
+        assert!(fs_err::read_to_string(dir.join("config"))
+            .unwrap()
+            .contains("StrictHostKeyChecking yes"));
+    }
+}
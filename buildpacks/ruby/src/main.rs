@@ -1,7 +1,8 @@
 use bullet_stream::{style, Print};
 use commons::cache::CacheError;
-use commons::gemfile_lock::GemfileLock;
-use commons::metadata_digest::MetadataDigest;
+use commons::display::SentenceList;
+use commons::gemfile_lock::{GemfileLock, RubyVersion};
+use commons::metadata_digest::{DigestError, MetadataDigest};
 use core::str::FromStr;
 use fs_err::PathExt;
 use fun_run::CmdError;
@@ -11,6 +12,7 @@ use layers::{
 use libcnb::build::{BuildContext, BuildResult, BuildResultBuilder};
 use libcnb::data::build_plan::BuildPlanBuilder;
 use libcnb::data::launch::LaunchBuilder;
+use libcnb::data::layer::LayerName;
 use libcnb::data::layer_name;
 use libcnb::detect::{DetectContext, DetectResult, DetectResultBuilder};
 use libcnb::generic::{GenericMetadata, GenericPlatform};
@@ -20,10 +22,21 @@ use libcnb::Platform;
 use libcnb::{buildpack_main, Buildpack};
 use std::io::stdout;
 
+mod bundle_check;
+mod bundle_lock;
+mod bundle_output;
+mod container_limits;
+mod credentials;
+mod gem_audit;
+mod gem_changes;
+mod gem_licenses;
 mod gem_list;
+mod gem_mirror;
 mod layers;
 mod rake_status;
 mod rake_task_detect;
+mod redact;
+mod sbom;
 mod steps;
 mod target_id;
 mod user_errors;
@@ -115,24 +128,50 @@ impl Buildpack for RubyBuildpack {
 
     #[allow(clippy::too_many_lines)]
     fn build(&self, context: BuildContext<Self>) -> libcnb::Result<BuildResult, Self::Error> {
-        let mut build_output = Print::new(stdout()).h2("Heroku Ruby Buildpack");
+        let credentials = credentials::Credentials::from_env(context.platform.env());
+        redact::init(&credentials.secrets());
+        let mut build_output =
+            Print::new(redact::RedactedStdout::new(stdout())).h2("Heroku Ruby Buildpack");
 
         // ## Set default environment
         let (mut env, store) =
             crate::steps::default_env(&context, &context.platform.env().clone())?;
 
         // Gather static information about project
+        let gemfile = fs_err::read_to_string(context.app_dir.join("Gemfile")).ok();
         let lockfile = context.app_dir.join("Gemfile.lock");
-        let lockfile_contents = fs_err::read_to_string(&lockfile)
-            .map_err(|error| RubyBuildpackError::MissingGemfileLock(lockfile, error))?;
-        let gemfile_lock = GemfileLock::from_str(&lockfile_contents).expect("Infallible");
+        let (lockfile_contents, generate_lockfile) = match fs_err::read_to_string(&lockfile) {
+            Ok(contents) => (contents, false),
+            Err(error)
+                if error.kind() == std::io::ErrorKind::NotFound
+                    && gemfile.is_some()
+                    && !bundle_lock::lockfile_required(&env) =>
+            {
+                (String::new(), true)
+            }
+            Err(error) => Err(RubyBuildpackError::MissingGemfileLock(lockfile, error))?,
+        };
+        let mut gemfile_lock = GemfileLock::from_str(&lockfile_contents).expect("Infallible");
+        let mut ruby_source = gemfile_lock.ruby_source();
+        if let Some(version) = gemfile
+            .as_deref()
+            .filter(|_| generate_lockfile)
+            .and_then(bundle_lock::gemfile_ruby_version)
+        {
+            gemfile_lock.ruby_version = RubyVersion::Explicit(version);
+            ruby_source = String::from("Gemfile");
+        }
         let bundler_version = gemfile_lock.resolve_bundler("2.5.6");
         let ruby_version = gemfile_lock.resolve_ruby("3.2.6");
+        let mirrors = gem_mirror::from_env(&env).map_err(RubyBuildpackError::GemMirrorError)?;
 
         // ## Install metrics agent
         build_output = {
             let bullet = build_output.bullet("Metrics agent");
-            if lockfile_contents.contains("barnes") {
+            // Without a lockfile the gem can only be declared in the `Gemfile`
+            if lockfile_contents.contains("barnes")
+                || (generate_lockfile && gemfile.as_deref().is_some_and(|g| g.contains("barnes")))
+            {
                 layers::metrics_agent_install::handle_metrics_agent_layer(&context, bullet)?.done()
             } else {
                 bullet
@@ -149,7 +188,7 @@ impl Buildpack for RubyBuildpack {
             let bullet = build_output.bullet(format!(
                 "Ruby version {} from {}",
                 style::value(ruby_version.to_string()),
-                style::value(gemfile_lock.ruby_source())
+                style::value(ruby_source)
             ));
             let (bullet, layer_env) = layers::ruby_install_layer::handle(
                 &context,
@@ -167,6 +206,29 @@ impl Buildpack for RubyBuildpack {
             (bullet.done(), layer_env.apply(Scope::Build, &env))
         };
 
+        // ## Gem mirrors
+        (build_output, env) = if mirrors.is_empty() {
+            (build_output, env)
+        } else {
+            let env = gem_mirror::bundle_env(&mirrors, &env);
+            let mut bullet = build_output.bullet("Gem mirrors");
+            for mirror in &mirrors {
+                let key = mirror.bundle_key();
+                // A value the user set for bundler directly takes precedence
+                let url = env.get(&key).map_or_else(
+                    || mirror.mirror.to_string(),
+                    |value| value.to_string_lossy().into_owned(),
+                );
+                bullet = bullet.sub_bullet(format!(
+                    "Using mirror {url} for {source} via {key}",
+                    url = style::url(url),
+                    source = style::url(mirror.source.as_str()),
+                    key = style::value(key)
+                ));
+            }
+            (bullet.done(), env)
+        };
+
         // ## Setup bundler
         (build_output, env) = {
             let bullet = build_output.bullet(format!(
@@ -186,45 +248,206 @@ impl Buildpack for RubyBuildpack {
             (bullet.done(), layer_env.apply(Scope::Build, &env))
         };
 
+        // ## Private gem sources
+        (build_output, env) = if credentials.is_empty() {
+            (build_output, env)
+        } else {
+            let mut bullet = build_output.bullet("Private gem sources");
+            for credential in &credentials.hosts {
+                bullet = bullet.sub_bullet(format!(
+                    "Using credentials for {host} from {key}",
+                    host = style::value(&credential.host),
+                    key = style::value(&credential.key)
+                ));
+            }
+            if let Some(key) = &credentials.ssh_key {
+                let layer_env;
+                (bullet, layer_env) = layers::ssh_key_layer::handle(
+                    &context,
+                    bullet,
+                    key,
+                    credentials.ssh_known_hosts.as_deref(),
+                )?;
+                env = layer_env.apply(Scope::Build, &env);
+            }
+            (bullet.done(), env)
+        };
+
+        // ## Generate Gemfile.lock
+        let (mut build_output, gemfile_lock) = if generate_lockfile {
+            let (bullet, contents) = bundle_lock::bundle_lock(
+                build_output.bullet("Generate Gemfile.lock"),
+                &env,
+                &context.app_dir,
+                &context.target.arch,
+            )?;
+            (
+                bullet.done(),
+                GemfileLock::from_str(&contents).expect("Infallible"),
+            )
+        } else {
+            (build_output, gemfile_lock)
+        };
+
+        // Other apps may use `project.toml` for other tools, a file that can't be read or parsed
+        // is warned about in the license report
+        let license_policy = match gem_licenses::LicensePolicy::from_app_dir(&context.app_dir) {
+            Err(error @ gem_licenses::LicenseError::InvalidPolicy(..)) => {
+                return Err(RubyBuildpackError::GemLicensePolicyError(error).into());
+            }
+            other => other,
+        };
+
+        // ## Vulnerability audit
+        build_output = if let Some(db) = gem_audit::db_from_env(&env, &context.app_dir) {
+            let policy =
+                gem_audit::Policy::from_env(&env).map_err(RubyBuildpackError::GemAuditError)?;
+            crate::steps::audit_gems(
+                build_output.bullet("Vulnerability audit"),
+                &db,
+                policy,
+                &gemfile_lock,
+                &ruby_version,
+            )?
+            .done()
+        } else {
+            build_output
+        };
+
         // ## Bundle install
-        (build_output, env) = {
-            let bullet = build_output.bullet("Bundle install gems");
-            let (bullet, layer_env) = layers::bundle_install_layer::handle(
+        let bundle_without = BundleWithout::new("development:test");
+        let build_only = layers::build_gems_layer::BuildOnlyGroups::new(
+            &env,
+            &bundle_without,
+            &gemfile_lock,
+            &fs_err::read_to_string(context.app_dir.join("Gemfile")).unwrap_or_default(),
+        );
+        let bundle_metadata = layers::bundle_install_layer::Metadata {
+            os_distribution: OsDistribution {
+                name: context.target.distro_name.clone(),
+                version: context.target.distro_version.clone(),
+            },
+            cpu_architecture: context.target.arch.clone(),
+            ruby_version: ruby_version.clone(),
+            force_bundle_install_key: String::from(
+                crate::layers::bundle_install_layer::FORCE_BUNDLE_INSTALL_CACHE_KEY,
+            ),
+            gems: Some(gem_changes::GemSnapshot::from_lockfile(&gemfile_lock)),
+            digest: MetadataDigest::new_env_paths(
+                &context.platform,
+                &layers::bundle_install_layer::digest_paths(&context.app_dir, &gemfile_lock),
+            )
+            .map_err(RubyBuildpackError::BundleInstallDigestError)?,
+        };
+        let (mut build_output, compiler_cache) = if layers::compiler_cache_layer::enabled(&env) {
+            let (bullet, compiler_cache) = layers::compiler_cache_layer::handle(
                 &context,
                 &env,
-                bullet,
-                &layers::bundle_install_layer::Metadata {
+                build_output.bullet("Compiler cache"),
+                &layers::compiler_cache_layer::Metadata {
                     os_distribution: OsDistribution {
                         name: context.target.distro_name.clone(),
                         version: context.target.distro_version.clone(),
                     },
                     cpu_architecture: context.target.arch.clone(),
-                    ruby_version: ruby_version.clone(),
-                    force_bundle_install_key: String::from(
-                        crate::layers::bundle_install_layer::FORCE_BUNDLE_INSTALL_CACHE_KEY,
-                    ),
-                    digest: MetadataDigest::new_env_files(
-                        &context.platform,
-                        &[
-                            &context.app_dir.join("Gemfile"),
-                            &context.app_dir.join("Gemfile.lock"),
-                        ],
-                    )
-                    .map_err(|error| match error {
-                        commons::metadata_digest::DigestError::CannotReadFile(path, error) => {
-                            RubyBuildpackError::BundleInstallDigestError(path, error)
-                        }
-                    })?,
                 },
-                &BundleWithout::new("development:test"),
+            )?;
+            (bullet.done(), compiler_cache)
+        } else {
+            layers::compiler_cache_layer::remove_stale(&context)?;
+            (build_output, None)
+        };
+        // Compilers are only wrapped for `bundle install`
+        let install_env = |env: &libcnb::Env| {
+            compiler_cache
+                .as_ref()
+                .map_or_else(|| env.clone(), |cache| cache.install_env(env))
+        };
+        (build_output, env) = {
+            let bullet = build_output.bullet("Bundle install gems");
+            let (bullet, layer_env) = layers::bundle_install_layer::handle(
+                &context,
+                &install_env(&env),
+                bullet,
+                &bundle_metadata,
+                &bundle_without,
+                build_only.as_ref(),
+                &gemfile_lock,
+            )?;
+
+            (bullet.done(), layer_env.apply(Scope::Build, &env))
+        };
+
+        // ## Bundle install build-only gems
+        (build_output, env) = if let Some(build_only) = &build_only {
+            let bullet = build_output
+                .bullet(format!(
+                    "Bundle install build-only groups {}",
+                    SentenceList::new(
+                        &build_only
+                            .groups
+                            .iter()
+                            .map(style::value)
+                            .collect::<Vec<String>>()
+                    )
+                ))
+                .sub_bullet(format!(
+                    "Not included at launch: {}",
+                    SentenceList::new(
+                        &build_only
+                            .gems
+                            .iter()
+                            .map(style::value)
+                            .collect::<Vec<String>>()
+                    )
+                ));
+            let (bullet, layer_env) = layers::build_gems_layer::handle(
+                &context,
+                &install_env(&env),
+                bullet,
+                &bundle_metadata,
+                build_only,
+                &gemfile_lock,
             )?;
 
             (bullet.done(), layer_env.apply(Scope::Build, &env))
+        } else {
+            layers::build_gems_layer::remove_stale(&context)?;
+            (build_output, env)
+        };
+
+        // ## License report
+        let mut license_gem_dirs = bundle_check::gem_dirs_in(
+            &context
+                .layers_dir
+                .join(layers::bundle_install_layer::layer_name().as_str())
+                .join("ruby"),
+        );
+        // Build-only gems are checked against the policy too
+        let build_gems_dir = context
+            .layers_dir
+            .join(layers::build_gems_layer::layer_name().as_str());
+        if build_gems_dir.is_dir() {
+            license_gem_dirs.push(build_gems_dir);
+        }
+        build_output = crate::steps::license_report(
+            build_output.bullet("License report"),
+            &sbom::gems(&gemfile_lock, &license_gem_dirs),
+            license_policy.as_ref().map(Option::as_ref),
+        )?
+        .done();
+
+        build_output = if let Some(compiler_cache) = &compiler_cache {
+            compiler_cache
+                .prune(build_output.bullet("Compiler cache results"), &env)
+                .done()
+        } else {
+            build_output
         };
 
         env = {
             let user_binstubs = context.uncached_layer(
-                layer_name!("user_binstubs"),
+                user_binstubs_layer_name(),
                 UncachedLayerDefinition {
                     build: true,
                     launch: true,
@@ -271,6 +494,11 @@ impl Buildpack for RubyBuildpack {
             }
             .done()
         };
+
+        // ## Remove unused layers
+        build_output =
+            crate::steps::remove_unused_layers(build_output.bullet("Unused layers"), &context)?
+                .done();
         build_output.done();
 
         if let Some(default_process) = default_process {
@@ -288,6 +516,11 @@ impl Buildpack for RubyBuildpack {
     }
 }
 
+/// Name of the `user_binstubs` layer that puts the app's `bin/` on the `PATH`
+pub(crate) fn user_binstubs_layer_name() -> LayerName {
+    layer_name!("user_binstubs")
+}
+
 fn needs_java(gemfile_lock: impl AsRef<str>) -> bool {
     let java_regex = regex::Regex::new(r"\(jruby ").expect("clippy");
     java_regex.is_match(gemfile_lock.as_ref())
@@ -302,10 +535,18 @@ pub(crate) enum RubyBuildpackError {
     MetricsAgentError(MetricsAgentInstallError),
     MissingGemfileLock(std::path::PathBuf, std::io::Error),
     InAppDirCacheError(CacheError),
-    BundleInstallDigestError(std::path::PathBuf, std::io::Error),
+    BundleInstallDigestError(DigestError),
     BundleInstallCommandError(CmdError),
+    BundleLockCommandError(CmdError),
     RakeAssetsPrecompileFailed(CmdError),
     GemInstallBundlerCommandError(CmdError),
+    SshKeyWriteError(std::io::Error),
+    GemMirrorError(gem_mirror::MirrorError),
+    GemAuditError(gem_audit::AuditError),
+    GemAuditFailed(Vec<gem_audit::Finding>),
+    GemLicensePolicyError(gem_licenses::LicenseError),
+    GemLicensePolicyFailed(Vec<gem_licenses::Violation>),
+    VendorCacheMissingGems(Vec<bundle_check::MissingGem>),
 }
 
 impl From<RubyBuildpackError> for libcnb::Error<RubyBuildpackError> {
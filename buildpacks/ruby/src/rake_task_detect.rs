@@ -1,10 +1,10 @@
+use crate::redact::RedactedStdout;
 use bullet_stream::{
     state::SubBullet,
     {style, Print},
 };
 use core::str::FromStr;
 use fun_run::{CmdError, CommandWithName};
-use std::io::Stdout;
 use std::{ffi::OsStr, process::Command};
 
 /// Run `rake -P` and parse output to show what rake tasks an application has
@@ -25,10 +25,10 @@ pub(crate) struct RakeDetect {
 ///
 /// Will return `Err` if `bundle exec rake -p` command cannot be invoked by the operating system.
 pub(crate) fn call<T: IntoIterator<Item = (K, V)>, K: AsRef<OsStr>, V: AsRef<OsStr>>(
-    bullet: Print<SubBullet<Stdout>>,
+    bullet: Print<SubBullet<RedactedStdout>>,
     envs: T,
     error_on_failure: bool,
-) -> Result<(Print<SubBullet<Stdout>>, RakeDetect), CmdError> {
+) -> Result<(Print<SubBullet<RedactedStdout>>, RakeDetect), CmdError> {
     let mut cmd = Command::new("rake");
     cmd.args(["-P", "--trace"]).env_clear().envs(envs);
 
@@ -1,9 +1,15 @@
+mod audit_gems;
 mod default_env;
 mod detect_rake_tasks;
 mod get_default_process;
+mod license_report;
 mod rake_assets_install;
+mod remove_unused_layers;
 
+pub(crate) use self::audit_gems::audit_gems;
 pub(crate) use self::default_env::default_env;
 pub(crate) use self::detect_rake_tasks::detect_rake_tasks;
 pub(crate) use self::get_default_process::get_default_process;
+pub(crate) use self::license_report::license_report;
 pub(crate) use self::rake_assets_install::rake_assets_install;
+pub(crate) use self::remove_unused_layers::remove_unused_layers;
@@ -0,0 +1,88 @@
+use crate::gem_audit::{self, Policy, FAIL_ON_ENV_KEY};
+use crate::redact::RedactedStdout;
+use crate::RubyBuildpackError;
+use bullet_stream::state::SubBullet;
+use bullet_stream::{style, Print};
+use commons::display::SentenceList;
+use commons::gemfile_lock::{GemfileLock, ResolvedRubyVersion};
+use std::path::Path;
+
+pub(crate) fn audit_gems(
+    mut bullet: Print<SubBullet<RedactedStdout>>,
+    db: &Path,
+    policy: Policy,
+    gemfile_lock: &GemfileLock,
+    ruby_version: &ResolvedRubyVersion,
+) -> Result<Print<SubBullet<RedactedStdout>>, RubyBuildpackError> {
+    let report = gem_audit::audit(db, gemfile_lock, ruby_version)
+        .map_err(RubyBuildpackError::GemAuditError)?;
+
+    bullet = bullet.sub_bullet(format!(
+        "Checked {count} gems and Ruby against {db}",
+        count = report.checked - 1,
+        db = style::value(db.to_string_lossy())
+    ));
+    for error in &report.skipped {
+        bullet = bullet.sub_bullet(format!(
+            "{warning} Skipped {error}",
+            warning = style::important("WARNING")
+        ));
+    }
+    if report.findings.is_empty() {
+        return Ok(bullet.sub_bullet("No vulnerabilities found"));
+    }
+
+    for finding in &report.findings {
+        let patched = if finding.advisory.patched_versions.is_empty() {
+            String::from("no patched version")
+        } else {
+            format!(
+                "patched in {}",
+                SentenceList::new(
+                    &finding
+                        .advisory
+                        .patched_versions
+                        .iter()
+                        .map(style::value)
+                        .collect::<Vec<String>>()
+                )
+                .join_str("or")
+            )
+        };
+        bullet = bullet.sub_bullet(format!(
+            "{gem} {id} ({severity}) {title}, {patched}",
+            gem = style::value(format!("{} {}", finding.name, finding.version)),
+            id = finding.advisory.id,
+            severity = finding.advisory.severity,
+            title = finding.advisory.title,
+        ));
+    }
+
+    let failing = report
+        .findings
+        .iter()
+        .filter(|finding| policy.fails(finding))
+        .cloned()
+        .collect::<Vec<_>>();
+    if !failing.is_empty() {
+        return Err(RubyBuildpackError::GemAuditFailed(failing));
+    }
+
+    let found = format!(
+        "{warning} Found {count} {vulnerabilities}",
+        warning = style::important("WARNING"),
+        count = report.findings.len(),
+        vulnerabilities = if report.findings.len() > 1 {
+            "vulnerabilities"
+        } else {
+            "vulnerability"
+        },
+    );
+    let fail_on = style::value(FAIL_ON_ENV_KEY);
+    Ok(bullet.sub_bullet(match policy {
+        Policy::Warn => format!("{found}, set {fail_on} to fail the build"),
+        Policy::FailOn(threshold) => {
+            format!("{found} below the {threshold} severity set by {fail_on}")
+        }
+    }))
+}
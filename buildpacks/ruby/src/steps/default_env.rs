@@ -3,13 +3,18 @@ use libcnb::layer::UncachedLayerDefinition;
 use libcnb::layer_env::{LayerEnv, ModificationBehavior};
 use libcnb::{
     build::BuildContext,
-    data::{layer_name, store::Store},
+    data::{layer::LayerName, layer_name, store::Store},
     layer_env::Scope,
     Env,
 };
 use rand::Rng;
 
 // Set default environment values
+/// Name of the `env_defaults` layer
+pub(crate) fn layer_name() -> LayerName {
+    layer_name!("env_defaults")
+}
+
 pub(crate) fn default_env(
     context: &BuildContext<RubyBuildpack>,
     platform_env: &Env,
@@ -38,7 +43,7 @@ pub(crate) fn default_env(
         .to_string();
 
     let layer_ref = context.uncached_layer(
-        layer_name!("env_defaults"),
+        layer_name(),
         UncachedLayerDefinition {
             build: true,
             launch: true,
@@ -1,20 +1,20 @@
 use crate::gem_list::GemList;
 use crate::rake_status::{check_rake_ready, RakeStatus};
 use crate::rake_task_detect::{self, RakeDetect};
+use crate::redact::RedactedStdout;
 use crate::RubyBuildpack;
 use crate::RubyBuildpackError;
 use bullet_stream::state::SubBullet;
 use bullet_stream::{style, Print};
 use libcnb::build::BuildContext;
 use libcnb::Env;
-use std::io::Stdout;
 
 pub(crate) fn detect_rake_tasks(
-    bullet: Print<SubBullet<Stdout>>,
+    bullet: Print<SubBullet<RedactedStdout>>,
     gem_list: &GemList,
     context: &BuildContext<RubyBuildpack>,
     env: &Env,
-) -> Result<(Print<SubBullet<Stdout>>, Option<RakeDetect>), RubyBuildpackError> {
+) -> Result<(Print<SubBullet<RedactedStdout>>, Option<RakeDetect>), RubyBuildpackError> {
     let help = style::important("HELP");
     let rake = style::value("rake");
     let gemfile = style::value("Gemfile");
@@ -38,8 +38,8 @@ pub(crate) fn detect_rake_tasks(
         )),
         RakeStatus::MissingRakefile => Ok((
             bullet
-                .sub_bullet(format!("Skipping rake tasks ({rakefile} not found)",))
-                .sub_bullet(format!("{help} Add {rakefile} to your project to enable",)),
+                .sub_bullet(format!("Skipping rake tasks ({rakefile} not found)"))
+                .sub_bullet(format!("{help} Add {rakefile} to your project to enable")),
             None,
         )),
         RakeStatus::SkipManifestFound(paths) => {
@@ -1,4 +1,5 @@
 use crate::gem_list::GemList;
+use crate::redact::RedactedStdout;
 use crate::RubyBuildpack;
 use bullet_stream::style;
 use bullet_stream::{state::SubBullet, Print};
@@ -6,14 +7,13 @@ use libcnb::build::BuildContext;
 use libcnb::data::launch::Process;
 use libcnb::data::launch::ProcessBuilder;
 use libcnb::data::process_type;
-use std::io::Stdout;
 use std::path::Path;
 
 pub(crate) fn get_default_process(
-    bullet: Print<SubBullet<Stdout>>,
+    bullet: Print<SubBullet<RedactedStdout>>,
     context: &BuildContext<RubyBuildpack>,
     gem_list: &GemList,
-) -> (Print<SubBullet<Stdout>>, Option<Process>) {
+) -> (Print<SubBullet<RedactedStdout>>, Option<Process>) {
     let config_ru = style::value("config.ru");
     let rails = style::value("rails");
     let rack = style::value("rack");
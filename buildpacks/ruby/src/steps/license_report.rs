@@ -0,0 +1,57 @@
+use crate::gem_licenses::{self, LicenseError, LicensePolicy, POLICY_TABLE};
+use crate::redact::RedactedStdout;
+use crate::sbom::Component;
+use crate::RubyBuildpackError;
+use bullet_stream::state::SubBullet;
+use bullet_stream::{style, Print};
+
+pub(crate) fn license_report(
+    mut bullet: Print<SubBullet<RedactedStdout>>,
+    gems: &[Component],
+    policy: Result<Option<&LicensePolicy>, &LicenseError>,
+) -> Result<Print<SubBullet<RedactedStdout>>, RubyBuildpackError> {
+    if gems.is_empty() {
+        return Ok(bullet.sub_bullet("No installed gems"));
+    }
+    for (license, names) in gem_licenses::summary(gems) {
+        bullet = bullet.sub_bullet(format!(
+            "{license} ({count}): {names}",
+            license = style::value(license),
+            count = names.len(),
+            names = names.join(", ")
+        ));
+    }
+
+    let policy = match policy {
+        Ok(Some(policy)) => policy,
+        Ok(None) => return Ok(bullet),
+        Err(error) => {
+            return Ok(bullet.sub_bullet(format!(
+                "{warning} Skipping the license policy, {error}",
+                warning = style::important("WARNING"),
+            )))
+        }
+    };
+    let violations = gems
+        .iter()
+        .filter_map(|gem| policy.check(gem))
+        .collect::<Vec<_>>();
+    if violations.is_empty() {
+        return Ok(bullet.sub_bullet("All gems comply with the license policy"));
+    }
+    if policy.fail {
+        return Err(RubyBuildpackError::GemLicensePolicyFailed(violations));
+    }
+
+    for violation in &violations {
+        bullet = bullet.sub_bullet(violation.to_string());
+    }
+    Ok(bullet.sub_bullet(format!(
+        "{warning} {count} {gems} violate the license policy, set {fail} in {table} to fail the build",
+        warning = style::important("WARNING"),
+        count = violations.len(),
+        gems = if violations.len() > 1 { "gems" } else { "gem" },
+        fail = style::value("fail = true"),
+        table = style::value(format!("[{POLICY_TABLE}]")),
+    )))
+}
@@ -1,21 +1,24 @@
 use crate::rake_task_detect::RakeDetect;
+use crate::redact::RedactedStdout;
 use crate::RubyBuildpack;
 use crate::RubyBuildpackError;
 use bullet_stream::state::SubBullet;
 use bullet_stream::{style, Print};
-use commons::cache::{mib, AppCache, CacheConfig, CacheError, CacheState, KeepPath, PathState};
+use commons::cache::{
+    mib, AppCache, CacheConfig, CacheError, CacheState, CacheStrategy, EvictionPolicy, KeepPath,
+    PathState,
+};
 use fun_run::{self, CommandWithName};
 use libcnb::build::BuildContext;
 use libcnb::Env;
-use std::io::Stdout;
 use std::process::Command;
 
 pub(crate) fn rake_assets_install(
-    mut bullet: Print<SubBullet<Stdout>>,
+    mut bullet: Print<SubBullet<RedactedStdout>>,
     context: &BuildContext<RubyBuildpack>,
     env: &Env,
     rake_detect: &RakeDetect,
-) -> Result<Print<SubBullet<Stdout>>, RubyBuildpackError> {
+) -> Result<Print<SubBullet<RedactedStdout>>, RubyBuildpackError> {
     let help = style::important("HELP");
     let cases = asset_cases(rake_detect);
     let rake_assets_precompile = style::value("rake assets:precompile");
@@ -51,20 +54,7 @@ pub(crate) fn rake_assets_install(
         AssetCases::PrecompileAndClean => {
             bullet = bullet.sub_bullet(format!("Compiling assets with cache (detected {rake_assets_precompile} and {rake_assets_clean} via {rake_detect_cmd})"));
 
-            let cache_config = [
-                CacheConfig {
-                    path: context.app_dir.join("public").join("assets"),
-                    limit: mib(100),
-                    keep_path: KeepPath::Runtime,
-                },
-                CacheConfig {
-                    path: context.app_dir.join("tmp").join("cache").join("assets"),
-                    limit: mib(100),
-                    keep_path: KeepPath::BuildOnly,
-                },
-            ];
-
-            let caches = cache_config
+            let caches = asset_cache_config(context)
                 .into_iter()
                 .map(|config| AppCache::new_and_load(context, config))
                 .collect::<Result<Vec<AppCache>, CacheError>>()
@@ -76,6 +66,7 @@ pub(crate) fn rake_assets_install(
                     CacheState::NewEmpty => format!("Creating cache for {path}"),
                     CacheState::ExistsEmpty => format!("Loading (empty) cache for {path}"),
                     CacheState::ExistsWithContents => format!("Loading cache for {path}"),
+                    CacheState::Cleared(message) => format!("{message} for {path}"),
                 });
             }
 
@@ -108,14 +99,17 @@ pub(crate) fn rake_assets_install(
                 {
                     let path = store.path().display();
                     let limit = store.limit();
-                    let removed_len = removed.files.len();
                     let removed_size = removed.adjusted_bytes();
 
-                    bullet = bullet.sub_bullet(format!("Detected cache size exceeded (over {limit} limit by {removed_size}) for {path}"))
-                    .sub_bullet(
-                        format!("Removed {removed_len} files from the cache for {path}"),
-
-                    );
+                    for (policy, files) in &removed.by_policy {
+                        let removed_len = files.len();
+                        if policy == &EvictionPolicy::Lru {
+                            bullet = bullet.sub_bullet(format!("Detected cache size exceeded (over {limit} limit by {removed_size}) for {path}"));
+                        }
+                        bullet = bullet.sub_bullet(format!(
+                            "Removed {removed_len} files from the cache for {path} ({policy})"
+                        ));
+                    }
                 }
             }
         }
@@ -124,6 +118,28 @@ pub(crate) fn rake_assets_install(
     Ok(bullet)
 }
 
+/// Directories in the app that are cached between builds when assets are compiled
+pub(crate) fn asset_cache_config(context: &BuildContext<RubyBuildpack>) -> [CacheConfig; 2] {
+    [
+        CacheConfig {
+            path: context.app_dir.join("public").join("assets"),
+            limit: mib(100),
+            keep_path: KeepPath::Runtime,
+            launch: false,
+            strategy: CacheStrategy::Hardlink,
+            eviction: vec![EvictionPolicy::KeepVersions(3), EvictionPolicy::Lru],
+        },
+        CacheConfig {
+            path: context.app_dir.join("tmp").join("cache").join("assets"),
+            limit: mib(100),
+            keep_path: KeepPath::BuildOnly,
+            launch: false,
+            strategy: CacheStrategy::Hardlink,
+            eviction: vec![EvictionPolicy::Lru],
+        },
+    ]
+}
+
 #[derive(Clone, Debug)]
 enum AssetCases {
     None,
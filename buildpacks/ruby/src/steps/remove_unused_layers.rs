@@ -0,0 +1,101 @@
+use crate::layers::{
+    build_gems_layer, bundle_download_layer, bundle_install_layer, compiler_cache_layer,
+    gem_generations_layer, metrics_agent_install, ruby_install_layer, ssh_key_layer,
+};
+use crate::redact::RedactedStdout;
+use crate::steps::default_env;
+use crate::steps::rake_assets_install::asset_cache_config;
+use crate::{user_binstubs_layer_name, RubyBuildpack, RubyBuildpackError};
+use bullet_stream::state::SubBullet;
+use bullet_stream::Print;
+use commons::cache::create_layer_name;
+use commons::layer::gc::remove_unlisted_layers;
+use libcnb::build::BuildContext;
+use libcnb::data::layer::LayerName;
+use libcnb::layer::LayerError;
+
+/// Removes layers left behind by older versions of this buildpack
+///
+/// Any layer not listed in [`layer_names`] or an asset cache is deleted, so a new layer module
+/// must define a `layer_name()` and be added there or it's removed at the end of every build.
+pub(crate) fn remove_unused_layers(
+    mut bullet: Print<SubBullet<RedactedStdout>>,
+    context: &BuildContext<RubyBuildpack>,
+) -> libcnb::Result<Print<SubBullet<RedactedStdout>>, RubyBuildpackError> {
+    let removed = remove_unlisted_layers(&context.layers_dir, &known_layers(context)?)
+        .map_err(LayerError::IoError)?;
+
+    if removed.is_empty() {
+        return Ok(bullet.sub_bullet("No unused layers"));
+    }
+    for layer in removed {
+        bullet = bullet.sub_bullet(format!("Removed {layer}"));
+    }
+    Ok(bullet)
+}
+
+/// Every layer the current buildpack version may write
+fn known_layers(
+    context: &BuildContext<RubyBuildpack>,
+) -> Result<Vec<LayerName>, RubyBuildpackError> {
+    let mut layers = layer_names();
+    for config in asset_cache_config(context) {
+        layers.push(
+            create_layer_name(&context.app_dir, &config.path)
+                .map_err(RubyBuildpackError::InAppDirCacheError)?,
+        );
+    }
+    Ok(layers)
+}
+
+/// Layers with a fixed name, each module defines its own
+fn layer_names() -> Vec<LayerName> {
+    vec![
+        build_gems_layer::layer_name(),
+        bundle_download_layer::layer_name(),
+        bundle_install_layer::layer_name(),
+        compiler_cache_layer::layer_name(),
+        default_env::layer_name(),
+        gem_generations_layer::layer_name(),
+        metrics_agent_install::layer_name(),
+        ruby_install_layer::layer_name(),
+        ssh_key_layer::layer_name(),
+        user_binstubs_layer_name(),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    /// A layer written with `layer_name!` outside of this list is deleted at the end of every build
+    #[test]
+    fn test_layer_names_include_every_layer() {
+        // Renamed to `binruby` with `LayerRename`
+        let renamed = ["ruby"];
+        let layer_name_re = Regex::new(r#"layer_name!\("(\w+)"\)"#).unwrap();
+        let known = layer_names()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<String>>();
+
+        let pattern = format!("{}/src/**/*.rs", env!("CARGO_MANIFEST_DIR"));
+        for path in glob::glob(&pattern).unwrap() {
+            let path = path.unwrap();
+            let contents = fs_err::read_to_string(&path).unwrap();
+            let code = contents
+                .split("\n#[cfg(test)]\nmod tests")
+                .next()
+                .unwrap_or_default();
+            for captures in layer_name_re.captures_iter(code) {
+                let name = &captures[1];
+                assert!(
+                    known.iter().any(|known| known == name) || renamed.contains(&name),
+                    "Layer `{name}` from {} is missing from `layer_names()`",
+                    path.display()
+                );
+            }
+        }
+    }
+}
@@ -1,13 +1,19 @@
+use crate::bundle_lock::REQUIRE_LOCKFILE_ENV_KEY;
+use crate::credentials::SSH_KEY_ENV_KEY;
+use crate::gem_audit::{DB_ENV_KEY, FAIL_ON_ENV_KEY};
+use crate::gem_licenses::POLICY_TABLE;
+use crate::gem_mirror::MIRROR_ENV_KEY;
+use crate::redact::RedactedStdout;
 use crate::{DetectError, RubyBuildpackError};
 use bullet_stream::{state::Bullet, state::SubBullet, style, Print};
+use commons::metadata_digest::DigestError;
 use fun_run::{CmdError, CommandWithName};
 use indoc::formatdoc;
-use std::io::Stdout;
 use std::process::Command;
 const DEBUG_INFO_STR: &str = "Debug info";
 
 pub(crate) fn on_error(err: libcnb::Error<RubyBuildpackError>) {
-    let output = Print::new(std::io::stdout()).without_header();
+    let output = Print::new(RedactedStdout::new(std::io::stdout())).without_header();
     let debug_info = style::important(DEBUG_INFO_STR);
     match cause(err) {
         Cause::OurError(error) => log_our_error(output, error),
@@ -29,11 +35,11 @@ pub(crate) fn on_error(err: libcnb::Error<RubyBuildpackError>) {
                 CLI. If you can reproduce the behavior locally and believe you've found a bug in the
                 buildpack or the framework please open an issue on the buildpack's GitHub repository.
             "}),
-    };
+    }
 }
 
 #[allow(clippy::too_many_lines)]
-fn log_our_error(mut output: Print<Bullet<Stdout>>, error: RubyBuildpackError) {
+fn log_our_error(mut output: Print<Bullet<RedactedStdout>>, error: RubyBuildpackError) {
     let git_branch_url =
         style::url("https://devcenter.heroku.com/articles/git#deploy-from-a-branch-besides-main");
     let ruby_versions_url =
@@ -121,6 +127,8 @@ fn log_our_error(mut output: Print<Bullet<Stdout>>, error: RubyBuildpackError) {
                 Error: `Gemfile.lock` not found
 
                 A `Gemfile.lock` file is required and was not found in the root of your application.
+                When only a `Gemfile` is present one is generated with `bundle lock`, unless
+                `{REQUIRE_LOCKFILE_ENV_KEY}` is set.
 
                 If you have a `Gemfile.lock` in your application, ensure it is tracked in Git and
                 that you’re pushing the correct branch.
@@ -188,13 +196,33 @@ fn log_our_error(mut output: Print<Bullet<Stdout>>, error: RubyBuildpackError) {
                     Use the information above to debug further.
                 "});
         }
-        RubyBuildpackError::BundleInstallDigestError(path, error) => {
+        RubyBuildpackError::BundleLockCommandError(error) => {
+            let local_command = local_command_debug(&error);
+            output
+                .bullet(&debug_info)
+                .sub_bullet(error.to_string())
+                .done()
+                .error(formatdoc! {"
+                    Error generating `Gemfile.lock`
+
+                    The application does not have a `Gemfile.lock` so the buildpack tried to
+                    resolve the gems in the `Gemfile` with `bundle lock`, which failed.
+
+                    {local_command}
+
+                    Commit a `Gemfile.lock` to make builds reproducible and try again.
+                "});
+        }
+        RubyBuildpackError::BundleInstallDigestError(error) => {
             output = output
                 .bullet(&debug_info)
                 .sub_bullet(error.to_string())
                 .done();
 
-            if let Some(dir) = path.parent() {
+            if let Some(dir) = match &error {
+                DigestError::CannotReadFile(path, _) => path.parent(),
+                DigestError::InvalidGlob(_, _) => None,
+            } {
                 output = debug_cmd(
                     output.bullet(format!(
                         "{debug_info} Contents of the {} directory",
@@ -208,8 +236,8 @@ fn log_our_error(mut output: Print<Bullet<Stdout>>, error: RubyBuildpackError) {
                 Error generating file digest
 
                 An error occurred while generating a file digest. To provide the fastest possible
-                install experience, the Ruby buildpack converts your `Gemfile` and `Gemfile.lock`
-                into a digest to use in cache invalidation.
+                install experience, the Ruby buildpack converts your `Gemfile`, `Gemfile.lock`,
+                and other bundler inputs into a digest to use in cache invalidation.
 
                 Ensure that the permissions on the files in your application directory are correct and that
                 all symlinks correctly resolve.
@@ -294,6 +322,132 @@ fn log_our_error(mut output: Print<Bullet<Stdout>>, error: RubyBuildpackError) {
                 Use the information above to debug further.
             "});
         }
+        RubyBuildpackError::SshKeyWriteError(error) => {
+            output
+                .bullet(debug_info)
+                .sub_bullet(error.to_string())
+                .done()
+                .error(formatdoc! {"
+                    Error writing SSH key
+
+                    An error occurred while writing the SSH key from `{SSH_KEY_ENV_KEY}` used to
+                    fetch private git sources, the buildpack cannot continue.
+
+                    Use the information above to debug further.
+                "});
+        }
+        RubyBuildpackError::VendorCacheMissingGems(missing) => {
+            let mut bullet = output.bullet(format!(
+                "Gems missing from {}",
+                style::value("vendor/cache")
+            ));
+            for gem in &missing {
+                bullet = bullet.sub_bullet(gem.to_string());
+            }
+            bullet.done().error(formatdoc! {"
+                Error: `vendor/cache` is incomplete
+
+                The application includes gems packaged in `vendor/cache` so they are installed
+                with `bundle install --local` without network access. The gems listed above are
+                required by the `Gemfile.lock` but were not found in `vendor/cache`.
+
+                Run `bundle cache --all-platforms` locally, commit the result, and try again.
+            "});
+        }
+        RubyBuildpackError::GemAuditError(error) => {
+            output
+                .bullet(debug_info)
+                .sub_bullet(error.to_string())
+                .done()
+                .error(formatdoc! {"
+                    Error: Could not audit gems for vulnerabilities
+
+                    The vulnerability audit is enabled by `{DB_ENV_KEY}` but the advisory database
+                    or the `{FAIL_ON_ENV_KEY}` policy could not be used.
+
+                    Ensure `{DB_ENV_KEY}` points to a directory in the ruby-advisory-db format
+                    and that `{FAIL_ON_ENV_KEY}` is one of `none`, `low`, `medium`, `high`,
+                    or `critical`, then try again.
+                "});
+        }
+        RubyBuildpackError::GemAuditFailed(findings) => {
+            let mut bullet = output.bullet("Vulnerabilities that fail the build");
+            for finding in &findings {
+                bullet = bullet.sub_bullet(format!(
+                    "{gem} {id} ({severity}){url}",
+                    gem = style::value(format!("{} {}", finding.name, finding.version)),
+                    id = finding.advisory.id,
+                    severity = finding.advisory.severity,
+                    url = finding
+                        .advisory
+                        .url
+                        .as_ref()
+                        .map(|url| format!(" {}", style::url(url)))
+                        .unwrap_or_default()
+                ));
+            }
+            bullet.done().error(formatdoc! {"
+                Error: Vulnerable dependencies found
+
+                The gems or Ruby version listed above have known vulnerabilities at or above
+                the severity set by `{FAIL_ON_ENV_KEY}`. Vulnerabilities with an unknown
+                severity also fail the build.
+
+                Upgrade to a patched version and try again. To deploy anyway, raise the
+                severity or set `{FAIL_ON_ENV_KEY}=none` to only warn.
+            "});
+        }
+        RubyBuildpackError::GemLicensePolicyError(error) => {
+            output
+                .bullet(debug_info)
+                .sub_bullet(error.to_string())
+                .done()
+                .error(formatdoc! {"
+                    Error: Could not read the gem license policy
+
+                    The `[{POLICY_TABLE}]` table in `project.toml` is invalid.
+                    The table supports these keys:
+
+                    - `allow`: licenses that gems may use, any license when empty
+                    - `deny`: licenses that gems may not use
+                    - `fail`: `true` to fail the build on violations instead of warning
+
+                    Fix the `project.toml` file and try again.
+                "});
+        }
+        RubyBuildpackError::GemLicensePolicyFailed(violations) => {
+            let mut bullet = output.bullet("Gems that violate the license policy");
+            for violation in &violations {
+                bullet = bullet.sub_bullet(violation.to_string());
+            }
+            bullet.done().error(formatdoc! {"
+                Error: Gem licenses do not comply with the license policy
+
+                The gems listed above declare licenses that are denied or not allowed by
+                `[{POLICY_TABLE}]` in `project.toml`. Gems that do not
+                declare a license in their gemspec also violate the policy.
+
+                Replace the gems, or update the policy and try again. To deploy anyway,
+                remove `fail = true` from the policy to only warn.
+            "});
+        }
+        RubyBuildpackError::GemMirrorError(error) => {
+            output
+                .bullet(debug_info)
+                .sub_bullet(error.to_string())
+                .done()
+                .error(formatdoc! {"
+                    Error: Invalid `{MIRROR_ENV_KEY}`
+
+                    The gem mirror setting must be a list of URLs separated by spaces or commas.
+                    A URL on its own mirrors `https://rubygems.org`, use `<source>=<mirror>` to
+                    mirror another gem source. For example:
+
+                        {MIRROR_ENV_KEY}=\"https://gems.internal https://gems.example.com=https://gems.internal/example\"
+
+                    Fix the value and try again.
+                "});
+        }
         RubyBuildpackError::MetricsAgentError(error) => {
             output
                 .bullet(debug_info)
@@ -339,7 +493,10 @@ fn replace_app_path_with_relative(contents: impl AsRef<str>) -> String {
     app_path_re.replace_all(contents.as_ref(), "./").to_string()
 }
 
-fn debug_cmd(mut log: Print<SubBullet<Stdout>>, command: &mut Command) -> Print<Bullet<Stdout>> {
+fn debug_cmd(
+    mut log: Print<SubBullet<RedactedStdout>>,
+    command: &mut Command,
+) -> Print<Bullet<RedactedStdout>> {
     let result = log.stream_with(
         format!("Running debug command {}", style::command(command.name())),
         |stdout, stderr| command.stream_output(stdout, stderr),
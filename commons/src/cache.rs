@@ -3,7 +3,7 @@ mod clean;
 mod config;
 mod error;
 
-pub use self::app_cache::{build, AppCache, CacheState, PathState};
+pub use self::app_cache::{build, create_layer_name, AppCache, CacheState, PathState};
 pub use self::clean::FilesWithSize;
-pub use self::config::{mib, CacheConfig, KeepPath};
+pub use self::config::{mib, CacheConfig, CacheStrategy, EvictionPolicy, KeepPath};
 pub use self::error::CacheError;
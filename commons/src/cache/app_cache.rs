@@ -1,10 +1,13 @@
-use crate::cache::clean::{lru_clean, FilesWithSize};
-use crate::cache::{CacheConfig, CacheError, KeepPath};
+use crate::cache::clean::{evict, FilesWithSize};
+use crate::cache::{CacheConfig, CacheError, CacheStrategy, EvictionPolicy, KeepPath};
+use crate::layer::diff_migrate::{DiffMigrateLayer, Meta};
 use byte_unit::{AdjustedByte, Byte, UnitType};
+use cache_diff::CacheDiff;
 use fs_extra::dir::CopyOptions;
 use libcnb::build::BuildContext;
 use libcnb::data::layer::LayerName;
-use libcnb::layer::{CachedLayerDefinition, InvalidMetadataAction, RestoredLayerAction};
+use libcnb::layer::{EmptyLayerCause, LayerState};
+use magic_migrate::{try_migrate_deserializer_chain, TryMigrate};
 use serde::{Deserialize, Serialize};
 use std::path::Path;
 use std::path::PathBuf;
@@ -32,7 +35,7 @@ use tempfile as _;
 ///
 ///# pub(crate) struct HelloWorldBuildpack;
 ///
-///  use commons::cache::{AppCache, CacheConfig, KeepPath, mib};
+///  use commons::cache::{AppCache, CacheConfig, CacheStrategy, EvictionPolicy, KeepPath, mib};
 ///
 ///# impl Buildpack for HelloWorldBuildpack {
 ///#     type Platform = GenericPlatform;
@@ -47,7 +50,10 @@ use tempfile as _;
 ///         let config = CacheConfig {
 ///             path: context.app_dir.join("public").join("assets"),
 ///             limit: mib(100),
-///             keep_path: KeepPath::Runtime
+///             keep_path: KeepPath::Runtime,
+///             launch: false,
+///             strategy: CacheStrategy::Copy,
+///             eviction: vec![EvictionPolicy::Lru],
 ///         };
 ///
 ///         let store = AppCache::new_and_load(&context, config).unwrap();
@@ -77,6 +83,12 @@ pub struct AppCache {
 
     /// Status of the cache directory when struct was instantiated
     cache_state: CacheState,
+
+    /// How files are moved between `path` and `cache`
+    strategy: CacheStrategy,
+
+    /// Policies applied to the cache after saving
+    eviction: Vec<EvictionPolicy>,
 }
 
 impl AppCache {
@@ -132,9 +144,11 @@ impl AppCache {
         match self.keep_path {
             KeepPath::Runtime => {}
             KeepPath::BuildOnly => {
-                fs_err::remove_dir_all(&self.path).map_err(CacheError::IoError)?;
+                if self.path.exists() {
+                    fs_err::remove_dir_all(&self.path).map_err(CacheError::IoError)?;
+                }
             }
-        };
+        }
 
         Ok(self)
     }
@@ -150,45 +164,69 @@ impl AppCache {
     /// - If files cannot be moved from the cache to the path
     ///   then an error will be raised.
     pub fn load(&self) -> Result<&Self, CacheError> {
-        fs_err::create_dir_all(&self.path).map_err(CacheError::IoError)?;
         fs_err::create_dir_all(&self.cache).map_err(CacheError::IoError)?;
 
-        fs_extra::dir::copy(
-            &self.cache,
-            &self.path,
-            &CopyOptions {
-                overwrite: false,
-                skip_exist: true,
-                copy_inside: true,
-                content_only: true,
-                ..CopyOptions::default()
-            },
-        )
-        .map_err(|error| CacheError::CopyCacheToAppError {
-            path: self.path.clone(),
-            cache: self.cache.clone(),
-            error,
-        })?;
-        copy_mtime_r(&self.cache, &self.path)?;
+        match self.strategy {
+            CacheStrategy::Copy => {
+                fs_err::create_dir_all(&self.path).map_err(CacheError::IoError)?;
+                fs_extra::dir::copy(
+                    &self.cache,
+                    &self.path,
+                    &CopyOptions {
+                        overwrite: false,
+                        skip_exist: true,
+                        copy_inside: true,
+                        content_only: true,
+                        ..CopyOptions::default()
+                    },
+                )
+                .map_err(|error| CacheError::CopyCacheToAppError {
+                    path: self.path.clone(),
+                    cache: self.cache.clone(),
+                    error,
+                })?;
+                copy_mtime_r(&self.cache, &self.path)?;
+
+                fs_err::remove_dir_all(&self.cache).map_err(CacheError::IoError)?;
+            }
+            CacheStrategy::Hardlink => {
+                fs_err::create_dir_all(&self.path).map_err(CacheError::IoError)?;
+                link_r(&self.cache, &self.path, Overwrite::No)?;
 
-        fs_err::remove_dir_all(&self.cache).map_err(CacheError::IoError)?;
+                fs_err::remove_dir_all(&self.cache).map_err(CacheError::IoError)?;
+            }
+            CacheStrategy::Symlink => {
+                if self.path.is_symlink() {
+                    fs_err::remove_file(&self.path).map_err(CacheError::IoError)?;
+                } else if self.path.exists() {
+                    // Files in the app win over cached files with the same name
+                    link_r(&self.path, &self.cache, Overwrite::Yes)?;
+                    fs_err::remove_dir_all(&self.path).map_err(CacheError::IoError)?;
+                }
+                if let Some(parent) = self.path.parent() {
+                    fs_err::create_dir_all(parent).map_err(CacheError::IoError)?;
+                }
+                fs_err::os::unix::fs::symlink(&self.cache, &self.path)
+                    .map_err(CacheError::IoError)?;
+            }
+        }
 
         Ok(self)
     }
 
-    /// Save and Clean out files in the cache above the configured limit
+    /// Save and Clean out files in the cache according to the eviction policies
     ///
     /// Files will first be moved from the path into the cache
     /// according to the configured `keep_path` policy.
     ///
-    /// Afterwards, files in the cache will be cleaned:
-    /// If the cache directory is above the given `limit` then
+    /// Afterwards, files in the cache will be cleaned by each
+    /// configured `EvictionPolicy` in order. With `EvictionPolicy::Lru`,
+    /// if the cache directory is above the given `limit` then
     /// files will be deleted in LRU order based on disk mtime.
     ///
     /// If any files were removed in this process then they will
-    /// be returned via `Some`. If no files were removed, `None`
-    /// will be returned indicating the directory is not yet
-    /// at the stated limit.
+    /// be returned via `Some`, grouped by the policy that removed them.
+    /// If no files were removed, `None` will be returned.
     ///
     /// # Errors
     ///
@@ -198,7 +236,7 @@ impl AppCache {
     /// - If metadata of a file cannot be read, an error will be raised
     pub fn save_and_clean(&self) -> Result<Option<FilesWithSize>, CacheError> {
         self.save()?;
-        lru_clean(&self.cache, self.limit)
+        evict(&self.cache, self.limit, &self.eviction)
     }
 
     /// Returns an enum representing the state
@@ -225,6 +263,9 @@ pub enum CacheState {
 
     /// Cache was previously created, it is non-empty
     ExistsWithContents,
+
+    /// Cache was previously created, but cleared, the message describes why
+    Cleared(String),
 }
 
 /// Current state of the path
@@ -250,17 +291,33 @@ pub enum PathState {
 ///  Historically, sprockets will keep 3 versions of old files on disk. This
 ///  allows for emails, that might live a long time, to reference a specific SHA of an
 ///  asset.
-#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub(crate) type Metadata = MetadataV1;
+try_migrate_deserializer_chain!(
+    deserializer: toml::Deserializer::new,
+    error: MetadataError,
+    chain: [MetadataV1],
+);
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, CacheDiff)]
 #[serde(deny_unknown_fields)]
-pub(crate) struct Metadata {
+pub(crate) struct MetadataV1 {
+    #[cache_diff(rename = "Cached path")]
     pub(crate) app_dir_path: PathBuf,
 }
 
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum MetadataError {
+    // Update if migrating between a metadata version can error
+}
+
 /// Converts a `CacheConfig` into an `AppCache`
 ///
 /// Same as `AppCache::new_and_load` without loading
 /// files from the cache into the path.
 ///
+/// The layer is cleared with a message in [`CacheState::Cleared`] when the cached path changes,
+/// the metadata cannot be migrated, or `HEROKU_CLEAR_CACHE` includes it.
+///
 /// # Errors
 ///
 /// - If the layer cannot be created
@@ -268,43 +325,48 @@ pub fn build<B: libcnb::Buildpack>(
     context: &BuildContext<B>,
     config: CacheConfig,
 ) -> Result<AppCache, CacheError> {
+    let launch = config.launch_layer();
     let CacheConfig {
         path,
         limit,
         keep_path,
+        launch: _,
+        strategy,
+        eviction,
     } = config;
 
     let layer_name = create_layer_name(&context.app_dir, &path)?;
     let create_state = layer_name_cache_state(&context.layers_dir, &layer_name);
-
     let metadata = Metadata {
         app_dir_path: path.clone(),
     };
-    let cache = context
-        .cached_layer(
-            layer_name,
-            CachedLayerDefinition {
-                build: true,
-                launch: true,
-                invalid_metadata_action: &|_| InvalidMetadataAction::DeleteLayer,
-                restored_layer_action: &|old: &Metadata, _| {
-                    if old == &metadata {
-                        RestoredLayerAction::KeepLayer
-                    } else {
-                        RestoredLayerAction::DeleteLayer
-                    }
+    let layer_ref = DiffMigrateLayer {
+        build: true,
+        launch,
+    }
+    .cached_layer(layer_name, context, &metadata)?;
+
+    let cache_state = match &layer_ref.state {
+        LayerState::Empty {
+            cause:
+                EmptyLayerCause::InvalidMetadataAction {
+                    cause: Meta::Message(message),
+                }
+                | EmptyLayerCause::RestoredLayerAction {
+                    cause: Meta::Message(message),
                 },
-            },
-        )
-        .map_err(|error| CacheError::InternalLayerError(format!("{error:?}")))
-        .map(|layer_ref| layer_ref.path())?;
+        } => CacheState::Cleared(message.clone()),
+        _ => create_state,
+    };
 
     Ok(AppCache {
         path,
         limit,
-        cache,
+        cache: layer_ref.path(),
         keep_path,
-        cache_state: create_state,
+        cache_state,
+        strategy,
+        eviction,
     })
 }
 
@@ -315,31 +377,101 @@ pub fn build<B: libcnb::Buildpack>(
 /// cannonical and will overwrite files with the same name in the
 /// cache.
 ///
+/// With [`CacheStrategy::Symlink`] the files are already in the cache. The
+/// symlink is removed, and files are linked back into the application path
+/// for [`KeepPath::Runtime`].
+///
 /// # Errors
 ///
 /// - If the copy command fails an `IoExtraError` will be raised.
 fn save(store: &AppCache) -> Result<&AppCache, CacheError> {
-    fs_extra::dir::copy(
-        &store.path,
-        &store.cache,
-        &CopyOptions {
-            overwrite: true,
-            copy_inside: true,  // Recursive
-            content_only: true, // Don't copy top level directory name
-            ..CopyOptions::default()
-        },
-    )
-    .map_err(|error| CacheError::CopyAppToCacheError {
-        path: store.path.clone(),
-        cache: store.cache.clone(),
-        error,
-    })?;
+    match store.strategy {
+        CacheStrategy::Copy => {
+            fs_extra::dir::copy(
+                &store.path,
+                &store.cache,
+                &CopyOptions {
+                    overwrite: true,
+                    copy_inside: true,  // Recursive
+                    content_only: true, // Don't copy top level directory name
+                    ..CopyOptions::default()
+                },
+            )
+            .map_err(|error| CacheError::CopyAppToCacheError {
+                path: store.path.clone(),
+                cache: store.cache.clone(),
+                error,
+            })?;
 
-    copy_mtime_r(&store.path, &store.cache)?;
+            copy_mtime_r(&store.path, &store.cache)?;
+        }
+        CacheStrategy::Hardlink => {
+            fs_err::create_dir_all(&store.cache).map_err(CacheError::IoError)?;
+            link_r(&store.path, &store.cache, Overwrite::Yes)?;
+        }
+        CacheStrategy::Symlink => {
+            if store.path.is_symlink() {
+                fs_err::remove_file(&store.path).map_err(CacheError::IoError)?;
+            } else if store.path.exists() {
+                // The symlink was replaced with a directory during the build
+                link_r(&store.path, &store.cache, Overwrite::Yes)?;
+                fs_err::remove_dir_all(&store.path).map_err(CacheError::IoError)?;
+            }
+            if store.keep_path == KeepPath::Runtime {
+                fs_err::create_dir_all(&store.path).map_err(CacheError::IoError)?;
+                link_r(&store.cache, &store.path, Overwrite::No)?;
+            }
+        }
+    }
 
     Ok(store)
 }
 
+/// Whether [`link_r`] replaces files that already exist in the destination
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Overwrite {
+    Yes,
+    No,
+}
+
+/// Hardlinks every file in a path into another path, copying when a link cannot be made
+///
+/// Hardlinks cannot cross filesystems, for example when the layers and app directories are
+/// different mounts. Copied files get the mtime of the original, linked files share it.
+fn link_r(from: &Path, to_path: &Path, overwrite: Overwrite) -> Result<(), CacheError> {
+    for entry in WalkDir::new(from).into_iter().filter_map(Result::ok) {
+        let relative = entry
+            .path()
+            .strip_prefix(from)
+            .expect("Walkdir path should return path with prefix of called root");
+        let target = to_path.join(relative);
+
+        if entry.file_type().is_dir() {
+            fs_err::create_dir_all(&target).map_err(CacheError::IoError)?;
+            continue;
+        }
+        if target.symlink_metadata().is_ok() {
+            if overwrite == Overwrite::No {
+                continue;
+            }
+            if target.is_dir() && !target.is_symlink() {
+                fs_err::remove_dir_all(&target).map_err(CacheError::IoError)?;
+            } else {
+                fs_err::remove_file(&target).map_err(CacheError::IoError)?;
+            }
+        }
+
+        if entry.file_type().is_symlink() {
+            let link = fs_err::read_link(entry.path()).map_err(CacheError::IoError)?;
+            fs_err::os::unix::fs::symlink(link, &target).map_err(CacheError::IoError)?;
+        } else if fs_err::hard_link(entry.path(), &target).is_err() {
+            fs_err::copy(entry.path(), &target).map_err(CacheError::IoError)?;
+            copy_mtime(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
 /// Copies the mtime information from a path to another path
 ///
 /// This is information used for the LRU cleaner so that older files are removed first.
@@ -350,29 +482,35 @@ fn copy_mtime_r(from: &Path, to_path: &Path) -> Result<(), CacheError> {
             .strip_prefix(from)
             .expect("Walkdir path should return path with prefix of called root");
 
-        let mtime = entry
-            .metadata()
-            .map_err(|error| std::io::Error::new(std::io::ErrorKind::Other, error))
-            .map(|metadata| filetime::FileTime::from_last_modification_time(&metadata))
-            .map_err(|error| CacheError::Mtime {
-                from: entry.path().to_path_buf(),
-                to_path: to_path.join(relative),
-                error,
-            })?;
-
-        filetime::set_file_mtime(to_path.join(relative), mtime).map_err(|error| {
-            CacheError::Mtime {
-                from: entry.path().to_path_buf(),
-                to_path: to_path.join(relative),
-                error,
-            }
-        })?;
+        copy_mtime(entry.path(), &to_path.join(relative))?;
     }
     Ok(())
 }
 
+/// Copies the mtime of a single file or directory
+fn copy_mtime(from: &Path, to_path: &Path) -> Result<(), CacheError> {
+    let mtime = fs_err::metadata(from)
+        .map(|metadata| filetime::FileTime::from_last_modification_time(&metadata))
+        .map_err(|error| CacheError::Mtime {
+            from: from.to_path_buf(),
+            to_path: to_path.to_path_buf(),
+            error,
+        })?;
+
+    filetime::set_file_mtime(to_path, mtime).map_err(|error| CacheError::Mtime {
+        from: from.to_path_buf(),
+        to_path: to_path.to_path_buf(),
+        error,
+    })
+}
+
 /// Converts a path inside of an app to a valid layer name for libcnb.
-fn create_layer_name(app_root: &Path, path: &Path) -> Result<LayerName, CacheError> {
+///
+/// # Errors
+///
+/// - If the path is not inside of the app directory
+/// - If the result is not a valid layer name
+pub fn create_layer_name(app_root: &Path, path: &Path) -> Result<LayerName, CacheError> {
     let name = path
         .strip_prefix(app_root)
         .map_err(|_| {
@@ -421,6 +559,10 @@ fn is_empty_dir(path: &Path) -> bool {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::layer::clear_cache::CLEAR_CACHE_ENV_KEY;
+    use crate::layer::testing::{
+        platform_env, temp_build_context, FakeBuildpack, MetadataFixtures,
+    };
     use filetime::FileTime;
     use libcnb::data::layer_name;
     use std::str::FromStr;
@@ -453,6 +595,147 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_clear_cache() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let context = temp_build_context::<FakeBuildpack>(
+            tmpdir.path(),
+            include_str!("../../../buildpacks/ruby/buildpack.toml"),
+        );
+        let config = CacheConfig {
+            path: context.app_dir.join("public").join("assets"),
+            limit: Byte::from_u64(512),
+            keep_path: KeepPath::Runtime,
+            launch: false,
+            strategy: CacheStrategy::Copy,
+            eviction: vec![EvictionPolicy::Lru],
+        };
+        let store = AppCache::new_and_load(&context, config.clone()).unwrap();
+        fs_err::write(store.path().join("application.css"), "body {}").unwrap();
+        store.save().unwrap();
+        let store = AppCache::new_and_load(&context, config.clone()).unwrap();
+        assert_eq!(store.cache_state(), &CacheState::ExistsWithContents);
+        store.save().unwrap();
+
+        platform_env(tmpdir.path(), CLEAR_CACHE_ENV_KEY, "cache_public_assets");
+        let context = temp_build_context::<FakeBuildpack>(
+            tmpdir.path(),
+            include_str!("../../../buildpacks/ruby/buildpack.toml"),
+        );
+        fs_err::remove_dir_all(context.app_dir.join("public")).unwrap();
+        let store = AppCache::new_and_load(&context, config).unwrap();
+        assert_eq!(
+            store.cache_state(),
+            &CacheState::Cleared(String::from(
+                "Clearing cache due to HEROKU_CLEAR_CACHE=cache_public_assets (includes `cache_public_assets`)"
+            ))
+        );
+        assert!(!store.path().join("application.css").exists());
+    }
+
+    #[test]
+    fn test_layer_launch_follows_config() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let context = temp_build_context::<FakeBuildpack>(
+            tmpdir.path(),
+            include_str!("../../../buildpacks/ruby/buildpack.toml"),
+        );
+        let layer_types = |config: CacheConfig| {
+            AppCache::new_and_load(&context, config).unwrap();
+            let layer_toml: toml::Table = toml::from_str(
+                &fs_err::read_to_string(context.layers_dir.join("cache_public_assets.toml"))
+                    .unwrap(),
+            )
+            .unwrap();
+            layer_toml["types"].clone()
+        };
+        let config = CacheConfig {
+            path: context.app_dir.join("public").join("assets"),
+            limit: Byte::from_u64(512),
+            keep_path: KeepPath::Runtime,
+            launch: false,
+            strategy: CacheStrategy::Copy,
+            eviction: vec![EvictionPolicy::Lru],
+        };
+
+        let types = layer_types(config.clone());
+        assert_eq!(Some(false), types["launch"].as_bool());
+        assert_eq!(Some(true), types["cache"].as_bool());
+
+        // Runtime files are already in the app dir, a launch layer would copy them into the image
+        let types = layer_types(CacheConfig {
+            launch: true,
+            ..config.clone()
+        });
+        assert_eq!(Some(false), types["launch"].as_bool());
+
+        let types = layer_types(CacheConfig {
+            keep_path: KeepPath::BuildOnly,
+            ..config.clone()
+        });
+        assert_eq!(Some(false), types["launch"].as_bool());
+
+        let types = layer_types(CacheConfig {
+            keep_path: KeepPath::BuildOnly,
+            launch: true,
+            ..config
+        });
+        assert_eq!(Some(true), types["launch"].as_bool());
+    }
+
+    #[test]
+    fn test_invalid_metadata_is_cleared() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let context = temp_build_context::<FakeBuildpack>(
+            tmpdir.path(),
+            include_str!("../../../buildpacks/ruby/buildpack.toml"),
+        );
+        let config = CacheConfig {
+            path: context.app_dir.join("public").join("assets"),
+            limit: Byte::from_u64(512),
+            keep_path: KeepPath::Runtime,
+            launch: false,
+            strategy: CacheStrategy::Copy,
+            eviction: vec![EvictionPolicy::Lru],
+        };
+        let store = AppCache::new_and_load(&context, config.clone()).unwrap();
+        fs_err::write(store.path().join("application.css"), "body {}").unwrap();
+        store.save().unwrap();
+
+        fs_err::write(
+            context.layers_dir.join("cache_public_assets.toml"),
+            "[metadata]\napp_dir_path = 1\n",
+        )
+        .unwrap();
+        fs_err::remove_dir_all(context.app_dir.join("public")).unwrap();
+        let store = AppCache::new_and_load(&context, config).unwrap();
+        let CacheState::Cleared(message) = store.cache_state() else {
+            panic!(
+                "Expected cache to be cleared, got {:?}",
+                store.cache_state()
+            );
+        };
+        assert!(
+            message.starts_with("Clearing cache due to invalid metadata"),
+            "{message}"
+        );
+        assert!(!store.path().join("application.css").exists());
+    }
+
+    #[test]
+    fn test_metadata_fixtures() {
+        let fixtures = MetadataFixtures::<Metadata>::new()
+            .fixture("v1", r#"app_dir_path = "/workspace/public/assets""#);
+        fixtures.assert_all();
+        fixtures.assert_diff(
+            "v1",
+            &Metadata {
+                app_dir_path: PathBuf::from("/app/public/assets"),
+            },
+            &["Cached path (`/workspace/public/assets` to `/app/public/assets`)"],
+        );
+    }
+
     #[test]
     fn test_load_does_not_clobber_files() {
         let tmpdir = tempfile::tempdir().unwrap();
@@ -470,6 +753,8 @@ mod tests {
             limit: Byte::from_u64(512),
             keep_path: KeepPath::Runtime,
             cache_state: CacheState::NewEmpty,
+            strategy: CacheStrategy::Copy,
+            eviction: vec![EvictionPolicy::Lru],
         };
 
         store.load().unwrap();
@@ -489,6 +774,8 @@ mod tests {
             limit: Byte::from_u64(512),
             keep_path: KeepPath::Runtime,
             cache_state: CacheState::NewEmpty,
+            strategy: CacheStrategy::Copy,
+            eviction: vec![EvictionPolicy::Lru],
         };
 
         assert!(is_empty_dir(&app_path)); // Assert empty dir
@@ -520,6 +807,8 @@ mod tests {
             limit: Byte::from_u64(512),
             keep_path: KeepPath::BuildOnly,
             cache_state: CacheState::NewEmpty,
+            strategy: CacheStrategy::Copy,
+            eviction: vec![EvictionPolicy::Lru],
         };
 
         assert!(is_empty_dir(&app_path));
@@ -540,6 +829,113 @@ mod tests {
         assert!(!store.path.join("lol.txt").exists());
     }
 
+    #[test]
+    fn test_hardlink_strategy() {
+        use std::os::unix::fs::MetadataExt;
+
+        let mtime = FileTime::from_unix_time(1000, 0);
+        let tmpdir = tempfile::tempdir().unwrap();
+        let app_path = tmpdir.path().join("app");
+        let cache_path = tmpdir.path().join("cache");
+        fs_err::create_dir_all(cache_path.join("nested")).unwrap();
+        fs_err::create_dir_all(&app_path).unwrap();
+        fs_err::write(cache_path.join("a.txt"), "cache").unwrap();
+        fs_err::write(cache_path.join("nested").join("b.txt"), "cache").unwrap();
+        filetime::set_file_mtime(cache_path.join("nested").join("b.txt"), mtime).unwrap();
+        fs_err::write(app_path.join("a.txt"), "app").unwrap();
+
+        let store = AppCache {
+            path: app_path.clone(),
+            cache: cache_path.clone(),
+            limit: Byte::from_u64(512),
+            keep_path: KeepPath::Runtime,
+            cache_state: CacheState::NewEmpty,
+            strategy: CacheStrategy::Hardlink,
+            eviction: vec![EvictionPolicy::Lru],
+        };
+        store.load().unwrap();
+
+        assert_eq!(
+            "app",
+            fs_err::read_to_string(app_path.join("a.txt")).unwrap()
+        );
+        let b = fs_err::metadata(app_path.join("nested").join("b.txt")).unwrap();
+        assert_eq!(mtime, FileTime::from_last_modification_time(&b));
+
+        store.save().unwrap();
+
+        assert_eq!(
+            "app",
+            fs_err::read_to_string(cache_path.join("a.txt")).unwrap()
+        );
+        assert_eq!(
+            fs_err::metadata(app_path.join("a.txt")).unwrap().ino(),
+            fs_err::metadata(cache_path.join("a.txt")).unwrap().ino()
+        );
+        let b = fs_err::metadata(cache_path.join("nested").join("b.txt")).unwrap();
+        assert_eq!(mtime, FileTime::from_last_modification_time(&b));
+    }
+
+    #[test]
+    fn test_symlink_strategy() {
+        let mtime = FileTime::from_unix_time(1000, 0);
+        let tmpdir = tempfile::tempdir().unwrap();
+        let app_path = tmpdir.path().join("app").join("assets");
+        let cache_path = tmpdir.path().join("cache");
+        fs_err::create_dir_all(&cache_path).unwrap();
+        fs_err::create_dir_all(&app_path).unwrap();
+        fs_err::write(cache_path.join("a.txt"), "cache").unwrap();
+        fs_err::write(cache_path.join("b.txt"), "cache").unwrap();
+        filetime::set_file_mtime(cache_path.join("b.txt"), mtime).unwrap();
+        fs_err::write(app_path.join("a.txt"), "app").unwrap();
+
+        let store = AppCache {
+            path: app_path.clone(),
+            cache: cache_path.clone(),
+            limit: Byte::from_u64(512),
+            keep_path: KeepPath::Runtime,
+            cache_state: CacheState::NewEmpty,
+            strategy: CacheStrategy::Symlink,
+            eviction: vec![EvictionPolicy::Lru],
+        };
+        store.load().unwrap();
+
+        assert!(app_path.is_symlink());
+        assert_eq!(
+            "app",
+            fs_err::read_to_string(app_path.join("a.txt")).unwrap()
+        );
+        fs_err::write(app_path.join("c.txt"), "build").unwrap();
+
+        store.save().unwrap();
+
+        assert!(!app_path.is_symlink());
+        assert_eq!(
+            "build",
+            fs_err::read_to_string(cache_path.join("c.txt")).unwrap()
+        );
+        assert_eq!(
+            "app",
+            fs_err::read_to_string(app_path.join("a.txt")).unwrap()
+        );
+        let b = fs_err::metadata(app_path.join("b.txt")).unwrap();
+        assert_eq!(mtime, FileTime::from_last_modification_time(&b));
+
+        let store = AppCache {
+            keep_path: KeepPath::BuildOnly,
+            ..store
+        };
+        store.load().unwrap();
+        store.save().unwrap();
+
+        assert!(!app_path.exists());
+        assert!(!app_path.is_symlink());
+        assert_eq!(
+            "app",
+            fs_err::read_to_string(cache_path.join("a.txt")).unwrap()
+        );
+    }
+
     #[test]
     fn mtime_preserved_keep_path_build_only() {
         let mtime = FileTime::from_unix_time(1000, 0);
@@ -557,6 +953,8 @@ mod tests {
             limit: Byte::from_u64(512),
             keep_path: KeepPath::BuildOnly,
             cache_state: CacheState::NewEmpty,
+            strategy: CacheStrategy::Copy,
+            eviction: vec![EvictionPolicy::Lru],
         };
 
         fs_err::write(app_path.join(filename), "catbus").unwrap();
@@ -599,6 +997,8 @@ mod tests {
             limit: Byte::from_u64(512),
             keep_path: KeepPath::Runtime,
             cache_state: CacheState::NewEmpty,
+            strategy: CacheStrategy::Copy,
+            eviction: vec![EvictionPolicy::Lru],
         };
 
         fs_err::write(app_path.join(filename), "catbus").unwrap();
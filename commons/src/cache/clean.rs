@@ -1,35 +1,58 @@
-use crate::cache::CacheError;
+use crate::cache::{CacheError, EvictionPolicy};
 use byte_unit::{AdjustedByte, Byte, UnitType};
+use regex::Regex;
+use std::collections::HashMap;
 use std::os::unix::fs::MetadataExt;
 use std::path::{Path, PathBuf};
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
-/// Remove Least Recently Used (LRU) files in cache above a byte limit
+/// Remove files from the cache according to each policy in order
 ///
-/// The cache directory may grow unbounded. This function will limit
-/// the size of the directory to the given input. When the directory
-/// grows larger than the limit, then files will be deleted to
-/// bring the directory size under the given limit.
+/// Returns `None` when no files were removed, otherwise the removed files
+/// grouped by the policy that removed them.
 ///
 /// # Errors
 ///
-/// - The provided ``cache_path`` is not valid UTF-8 (`OsStringErr`).
 /// - Metadata from a file in the ``cache_path`` cannot be retrieved from the OS (`IoError`).
 ///   this is needed for mtime retrieval to calculate which file is least recently used.
 /// - If there's an OS error while deleting a file.
-/// - If an internal glob pattern is incorrect
 /// - If the OS does not support mtime operation on files.
-pub(crate) fn lru_clean(path: &Path, limit: Byte) -> Result<Option<FilesWithSize>, CacheError> {
-    let overage = lru_files_above_limit(path, limit)?;
-
-    if overage.files.is_empty() {
-        Ok(None)
-    } else {
-        for file in &overage.files {
+pub(crate) fn evict(
+    path: &Path,
+    limit: Byte,
+    policies: &[EvictionPolicy],
+) -> Result<Option<FilesWithSize>, CacheError> {
+    let bytes = files(path)?
+        .iter()
+        .map(|file| u128::from(file.size))
+        .sum::<u128>();
+    let mut by_policy = Vec::new();
+    for policy in policies {
+        let removed = match policy {
+            EvictionPolicy::Lru => lru_files_above_limit(path, limit)?.files,
+            EvictionPolicy::KeepVersions(count) => versions_beyond(path, *count)?,
+            EvictionPolicy::MaxAge(age) => files_older_than(path, *age, SystemTime::now())?,
+            EvictionPolicy::MaxFiles(count) => files_beyond_count(path, *count)?,
+        };
+        for file in &removed {
             fs_err::remove_file(file).map_err(CacheError::IoError)?;
         }
+        if !removed.is_empty() {
+            by_policy.push((policy.clone(), removed));
+        }
+    }
 
-        Ok(Some(overage))
+    if by_policy.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(FilesWithSize {
+            bytes,
+            files: by_policy
+                .iter()
+                .flat_map(|(_, files)| files.clone())
+                .collect(),
+            by_policy,
+        }))
     }
 }
 
@@ -56,7 +79,7 @@ fn lru_files_above_limit(cache_path: &Path, limit: Byte) -> Result<FilesWithSize
 
     if bytes >= max_bytes {
         let mut current_bytes = bytes;
-        files.sort_by(|a, b| a.modified.cmp(&b.modified));
+        files.sort_by_key(|a| a.modified);
 
         Ok(FilesWithSize {
             bytes,
@@ -68,12 +91,71 @@ fn lru_files_above_limit(cache_path: &Path, limit: Byte) -> Result<FilesWithSize
                 })
                 .map(|p| p.path.clone())
                 .collect::<Vec<PathBuf>>(),
+            by_policy: Vec::new(),
         })
     } else {
         Ok(FilesWithSize::default())
     }
 }
 
+/// Files with a digest in their name beyond the `keep` most recently modified versions
+/// of the same logical file, ordered oldest first
+fn versions_beyond(cache_path: &Path, keep: usize) -> Result<Vec<PathBuf>, CacheError> {
+    // Such as `application-<digest>.js`, the name and extension identify the logical file
+    let fingerprint = Regex::new(r"^(?P<name>.+)-(?P<digest>[0-9a-f]{7,128})(?P<extension>\..+)?$")
+        .expect("Clippy checked");
+    let mut versions = HashMap::<PathBuf, Vec<MiniPathModSize>>::new();
+    for file in files(cache_path)? {
+        let Some(name) = file.path.file_name().map(|name| name.to_string_lossy()) else {
+            continue;
+        };
+        let Some(captures) = fingerprint.captures(&name) else {
+            continue;
+        };
+        let logical = file.path.with_file_name(format!(
+            "{}{}",
+            &captures["name"],
+            captures
+                .name("extension")
+                .map_or("", |extension| extension.as_str())
+        ));
+        versions.entry(logical).or_default().push(file);
+    }
+
+    let mut removed = versions
+        .into_values()
+        .flat_map(|mut files| {
+            files.sort_by_key(|file| std::cmp::Reverse(file.modified));
+            files.into_iter().skip(keep)
+        })
+        .collect::<Vec<_>>();
+    removed.sort_by_key(|file| file.modified);
+    Ok(removed.into_iter().map(|file| file.path).collect())
+}
+
+/// Files last modified more than `age` before `now`, ordered oldest first
+fn files_older_than(
+    cache_path: &Path,
+    age: Duration,
+    now: SystemTime,
+) -> Result<Vec<PathBuf>, CacheError> {
+    let cutoff = now.checked_sub(age).unwrap_or(SystemTime::UNIX_EPOCH);
+    let mut files = files(cache_path)?
+        .into_iter()
+        .filter(|file| file.modified < cutoff)
+        .collect::<Vec<_>>();
+    files.sort_by_key(|file| file.modified);
+    Ok(files.into_iter().map(|file| file.path).collect())
+}
+
+/// Least recently modified files beyond the `keep` most recent, ordered oldest first
+fn files_beyond_count(cache_path: &Path, keep: usize) -> Result<Vec<PathBuf>, CacheError> {
+    let mut files = files(cache_path)?;
+    files.sort_by_key(|file| file.modified);
+    let over = files.len().saturating_sub(keep);
+    Ok(files.into_iter().take(over).map(|file| file.path).collect())
+}
+
 /// A list of files and their associated size on disk in bytes
 #[derive(Debug, Eq, PartialEq, Default)]
 pub struct FilesWithSize {
@@ -82,6 +164,9 @@ pub struct FilesWithSize {
 
     /// Paths to files
     pub files: Vec<PathBuf>,
+
+    /// The files in `files` grouped by the policy that removed them, in the order the policies ran
+    pub by_policy: Vec<(EvictionPolicy, Vec<PathBuf>)>,
 }
 
 impl FilesWithSize {
@@ -199,4 +284,106 @@ mod tests {
         let overage = lru_files_above_limit(&dir, mib(0)).unwrap();
         assert_eq!(overage.files, Vec::<PathBuf>::new());
     }
+
+    fn write_with_mtime(path: &Path, seconds: i64) {
+        if let Some(parent) = path.parent() {
+            fs_err::create_dir_all(parent).unwrap();
+        }
+        fs_err::write(path, "").unwrap();
+        filetime::set_file_mtime(path, filetime::FileTime::from_unix_time(seconds, 0)).unwrap();
+    }
+
+    #[test]
+    fn test_versions_beyond() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let dir = tmpdir.path();
+        for (seconds, name) in [
+            (1, "application-0000000aaaa.js"),
+            (2, "application-1111111bbbb.js"),
+            (3, "application-2222222cccc.js"),
+            (1, "application-0000000aaaa.js.gz"),
+            (1, "admin/application-0000000aaaa.js"),
+            (1, "robots.txt"),
+        ] {
+            write_with_mtime(&dir.join(name), seconds);
+        }
+
+        assert_eq!(
+            versions_beyond(dir, 2).unwrap(),
+            vec![dir.join("application-0000000aaaa.js")]
+        );
+        assert!(versions_beyond(dir, 3).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_files_older_than_and_beyond_count() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let dir = tmpdir.path();
+        write_with_mtime(&dir.join("old"), 100);
+        write_with_mtime(&dir.join("older"), 50);
+        write_with_mtime(&dir.join("new"), 1_000);
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_100);
+
+        assert_eq!(
+            files_older_than(dir, Duration::from_secs(500), now).unwrap(),
+            vec![dir.join("older"), dir.join("old")]
+        );
+        assert_eq!(files_beyond_count(dir, 2).unwrap(), vec![dir.join("older")]);
+        assert!(files_beyond_count(dir, 3).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_evict_reports_policies() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let dir = tmpdir.path();
+        write_with_mtime(&dir.join("app-0000000aaaa.css"), 1);
+        write_with_mtime(&dir.join("app-1111111bbbb.css"), 2);
+        write_with_mtime(&dir.join("a.txt"), 3);
+        write_with_mtime(&dir.join("b.txt"), 4);
+
+        assert_eq!(None, evict(dir, mib(1), &[EvictionPolicy::Lru]).unwrap());
+
+        let removed = evict(
+            dir,
+            mib(1),
+            &[
+                EvictionPolicy::KeepVersions(1),
+                EvictionPolicy::MaxFiles(2),
+                EvictionPolicy::Lru,
+            ],
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(
+            removed.by_policy,
+            vec![
+                (
+                    EvictionPolicy::KeepVersions(1),
+                    vec![dir.join("app-0000000aaaa.css")]
+                ),
+                (
+                    EvictionPolicy::MaxFiles(2),
+                    vec![dir.join("app-1111111bbbb.css")]
+                ),
+            ]
+        );
+        assert_eq!(
+            removed.files,
+            vec![
+                dir.join("app-0000000aaaa.css"),
+                dir.join("app-1111111bbbb.css")
+            ]
+        );
+        assert!(!dir.join("app-1111111bbbb.css").exists());
+        assert!(dir.join("a.txt").exists());
+        assert_eq!(
+            "keep 1 version per asset",
+            EvictionPolicy::KeepVersions(1).to_string()
+        );
+        assert_eq!(
+            "older than 7 days",
+            EvictionPolicy::MaxAge(Duration::from_secs(7 * 86_400)).to_string()
+        );
+    }
 }
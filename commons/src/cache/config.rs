@@ -1,5 +1,7 @@
 use byte_unit::Byte;
+use std::fmt::Display;
 use std::path::PathBuf;
+use std::time::Duration;
 
 /// Configure behavior of a cached path
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -13,9 +15,33 @@ pub struct CacheConfig {
 
     /// Specify what happens to the application path while it's being stored
     pub keep_path: KeepPath,
+
+    /// Make the cache layer available at launch, only used with [`KeepPath::BuildOnly`]
+    ///
+    /// With [`KeepPath::Runtime`] the files are already in the application directory,
+    /// a launch layer would put them in the image twice, so the layer is never launched.
+    /// See [`CacheConfig::launch_layer`].
+    pub launch: bool,
+
+    /// How files are moved between the application path and the cache
+    pub strategy: CacheStrategy,
+
+    /// Policies that remove files from the cache after it's saved, applied in order
+    ///
+    /// `vec![EvictionPolicy::Lru]` removes files above `limit`. An empty list never
+    /// removes files.
+    pub eviction: Vec<EvictionPolicy>,
+}
+
+impl CacheConfig {
+    /// Whether the cache layer is available at launch, follows [`CacheConfig::keep_path`]
+    #[must_use]
+    pub fn launch_layer(&self) -> bool {
+        self.launch && self.keep_path == KeepPath::BuildOnly
+    }
 }
 
-/// Indicates whether we want the cache to be available at runtime or not.
+/// Indicates whether the cached files stay in the application directory at runtime or not.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum KeepPath {
     /// Keep the application directory where it is, copy files to the cache
@@ -25,6 +51,70 @@ pub enum KeepPath {
     BuildOnly,
 }
 
+/// How files are moved between the application path and the cache
+///
+/// Files in the application path always take precedence over cached files
+/// with the same name, and the cache keeps each file's mtime for cleaning.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum CacheStrategy {
+    /// Copy every file, the cache and application path hold separate copies
+    #[default]
+    Copy,
+
+    /// Hardlink every file, falling back to a copy when the filesystem cannot
+    /// link between the two directories
+    Hardlink,
+
+    /// Replace the application path with a symlink to the cache for the build
+    ///
+    /// The symlink is removed on save. With [`KeepPath::Runtime`] the files are
+    /// hardlinked (or copied) back into the application path.
+    Symlink,
+}
+
+/// Decides which files are removed from the cache
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    /// Remove least recently modified files until the cache is under [`CacheConfig::limit`]
+    #[default]
+    Lru,
+
+    /// Keep the given number of most recently modified versions of each fingerprinted file
+    ///
+    /// Files such as `application-<digest>.js` written by Sprockets or Propshaft are
+    /// versions of the same logical asset, similar to what `rake assets:clean` keeps.
+    /// Files without a digest in their name are not removed.
+    KeepVersions(usize),
+
+    /// Remove files that have not been modified for longer than the duration
+    MaxAge(Duration),
+
+    /// Keep at most the given number of files, removing the least recently modified first
+    MaxFiles(usize),
+}
+
+impl Display for EvictionPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EvictionPolicy::Lru => write!(f, "least recently used over the size limit"),
+            EvictionPolicy::KeepVersions(count) => write!(
+                f,
+                "keep {count} version{s} per asset",
+                s = if *count == 1 { "" } else { "s" }
+            ),
+            EvictionPolicy::MaxAge(age) => {
+                let seconds = age.as_secs();
+                match seconds {
+                    0..3_600 => write!(f, "older than {seconds} seconds"),
+                    3_600..86_400 => write!(f, "older than {} hours", seconds / 3_600),
+                    _ => write!(f, "older than {} days", seconds / 86_400),
+                }
+            }
+            EvictionPolicy::MaxFiles(count) => write!(f, "more than {count} files"),
+        }
+    }
+}
+
 /// Returns a `Byte` value containing the number
 /// of mebibytes given.
 #[must_use]
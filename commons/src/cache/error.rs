@@ -1,3 +1,4 @@
+use std::fmt::Debug;
 use std::path::PathBuf;
 
 #[derive(thiserror::Error, Debug)]
@@ -44,9 +45,23 @@ pub enum CacheError {
     #[error("An internal error occured while creating a dir glob pattern: {0}")]
     InternalBadGlobError(glob::PatternError),
 
-    #[error("An internal error occured while constructing the layer: {0}")]
-    InternalLayerError(String),
+    #[error("Could not create or restore the cache layer: {0}")]
+    LayerError(libcnb::layer::LayerError),
+
+    #[error("Unexpected error while creating the cache layer: {0}")]
+    UnexpectedLayerError(String),
 
     #[error("The OS does not support the retreiving `mtime` information from files: {0}")]
     MtimeUnsupportedOS(std::io::Error),
 }
+
+/// Layer errors are kept as [`libcnb::layer::LayerError`], libcnb does not return other
+/// variants when creating a layer
+impl<E: Debug> From<libcnb::Error<E>> for CacheError {
+    fn from(error: libcnb::Error<E>) -> Self {
+        match error {
+            libcnb::Error::LayerError(error) => Self::LayerError(error),
+            other => Self::UnexpectedLayerError(other.to_string()),
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use crate::gem_version::{GemVersion, VersionError};
+use std::fmt;
+use std::str::FromStr;
+
+/// # Version constraints such as `~> 1.2, >= 1.2.3`
+///
+/// Based off of Ruby's `Gem::Requirement` logic, a version satisfies a requirement
+/// when it satisfies every comma separated constraint:
+///
+/// - <https://github.com/rubygems/rubygems/blob/ecc8e895b69063562b9bf749b353948e051e4171/lib/rubygems/requirement.rb>
+///
+/// Example:
+///
+/// ```rust
+/// use std::str::FromStr;
+/// use commons::gem_requirement::GemRequirement;
+/// use commons::gem_version::GemVersion;
+///
+/// let requirement = GemRequirement::from_str("~> 5.2.4, >= 5.2.4.3").unwrap();
+/// assert!(requirement.is_satisfied_by(&GemVersion::from_str("5.2.4.3").unwrap()));
+/// assert!(!requirement.is_satisfied_by(&GemVersion::from_str("5.2.4.2").unwrap()));
+/// assert!(!requirement.is_satisfied_by(&GemVersion::from_str("5.3.0").unwrap()));
+/// ```
+#[derive(Debug, Clone)]
+pub struct GemRequirement {
+    raw: String,
+    constraints: Vec<(Operator, GemVersion)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Equal,
+    NotEqual,
+    Greater,
+    Less,
+    GreaterOrEqual,
+    LessOrEqual,
+    Pessimistic,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum RequirementError {
+    InvalidRequirement(String),
+    InvalidVersion(VersionError),
+}
+
+impl GemRequirement {
+    #[must_use]
+    pub fn is_satisfied_by(&self, version: &GemVersion) -> bool {
+        self.constraints
+            .iter()
+            .all(|(operator, required)| match operator {
+                Operator::Equal => version == required,
+                Operator::NotEqual => version != required,
+                Operator::Greater => version > required,
+                Operator::Less => version < required,
+                Operator::GreaterOrEqual => version >= required,
+                Operator::LessOrEqual => version <= required,
+                Operator::Pessimistic => version >= required && version < &required.bump(),
+            })
+    }
+}
+
+impl fmt::Display for GemRequirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.raw)
+    }
+}
+
+impl FromStr for GemRequirement {
+    type Err = RequirementError;
+
+    fn from_str(requirement: &str) -> Result<Self, Self::Err> {
+        let constraint_regex = regex::Regex::new(r"\A\s*(=|!=|>=|<=|>|<|~>)?\s*(\S+)\s*\z")
+            .expect("Internal Error: Invalid Regular Expression!"); // Checked via clippy lint https://rust-lang.github.io/rust-clippy/master/index.html#invalid_regex
+
+        let constraints = requirement
+            .split(',')
+            .map(|constraint| {
+                let captures = constraint_regex.captures(constraint).ok_or_else(|| {
+                    RequirementError::InvalidRequirement(String::from(requirement))
+                })?;
+                let operator = match captures.get(1).map_or("=", |m| m.as_str()) {
+                    "!=" => Operator::NotEqual,
+                    ">" => Operator::Greater,
+                    "<" => Operator::Less,
+                    ">=" => Operator::GreaterOrEqual,
+                    "<=" => Operator::LessOrEqual,
+                    "~>" => Operator::Pessimistic,
+                    _ => Operator::Equal,
+                };
+                let version =
+                    GemVersion::from_str(&captures[2]).map_err(RequirementError::InvalidVersion)?;
+
+                Ok((operator, version))
+            })
+            .collect::<Result<Vec<_>, RequirementError>>()?;
+
+        Ok(GemRequirement {
+            raw: requirement.trim().to_string(),
+            constraints,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    // https://github.com/rubygems/rubygems/blob/ecc8e895b69063562b9bf749b353948e051e4171/test/rubygems/test_gem_requirement.rb#L263-L283
+    fn satisfied_by_boxed() {
+        assert!(!r("~> 1.4").is_satisfied_by(&v("1.3")));
+        assert!(r("~> 1.4").is_satisfied_by(&v("1.4")));
+        assert!(r("~> 1.4").is_satisfied_by(&v("1.5")));
+        assert!(!r("~> 1.4").is_satisfied_by(&v("2.0")));
+
+        assert!(!r("~> 1.4.4").is_satisfied_by(&v("1.3")));
+        assert!(r("~> 1.4.4").is_satisfied_by(&v("1.4.4")));
+        assert!(r("~> 1.4.4").is_satisfied_by(&v("1.4.5")));
+        assert!(!r("~> 1.4.4").is_satisfied_by(&v("1.5")));
+
+        assert!(r("~> 5.0.0").is_satisfied_by(&v("5.0.1")));
+        assert!(!r("~> 5.0.0").is_satisfied_by(&v("5.1.0")));
+    }
+
+    #[test]
+    fn satisfied_by_operators() {
+        assert!(r("1.0").is_satisfied_by(&v("1.0.0")));
+        assert!(r("= 1.0").is_satisfied_by(&v("1.0")));
+        assert!(r("!= 1.0").is_satisfied_by(&v("1.1")));
+        assert!(r("> 1.0").is_satisfied_by(&v("1.0.1")));
+        assert!(!r("> 1.0").is_satisfied_by(&v("1.0")));
+        assert!(r("< 1.0").is_satisfied_by(&v("1.0.a")));
+        assert!(r(">= 1.0").is_satisfied_by(&v("1.0")));
+        assert!(r("<= 1.0").is_satisfied_by(&v("1.0")));
+        assert!(r(">= 2.0, < 2.2.1").is_satisfied_by(&v("2.1.9")));
+        assert!(!r(">= 2.0, < 2.2.1").is_satisfied_by(&v("2.2.1")));
+    }
+
+    #[test]
+    fn invalid_requirements() {
+        assert_eq!(
+            GemRequirement::from_str("~> 1.0 junk").unwrap_err(),
+            RequirementError::InvalidRequirement(String::from("~> 1.0 junk"))
+        );
+        assert_eq!(
+            GemRequirement::from_str(">= junk").unwrap_err(),
+            RequirementError::InvalidVersion(VersionError::InvalidVersion(String::from("junk")))
+        );
+    }
+
+    // Test helper methods
+    fn r(s: &str) -> GemRequirement {
+        s.parse().unwrap()
+    }
+
+    fn v(s: &str) -> GemVersion {
+        s.parse().unwrap()
+    }
+}
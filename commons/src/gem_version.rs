@@ -19,9 +19,11 @@ use std::str::FromStr; // needed for lookahead/behind
 /// let version = GemVersion::from_str("1.0.0").unwrap();
 /// assert!(version < GemVersion::from_str("2.0.0").unwrap());
 /// ```
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct GemVersion {
     segments: Vec<VersionSegment>,
+    /// Leading numeric segments as written, including trailing zeros, for `bump`
+    release: Vec<u32>,
 }
 
 impl fmt::Display for GemVersion {
@@ -39,6 +41,37 @@ impl fmt::Display for GemVersion {
     }
 }
 
+impl GemVersion {
+    /// The next significant release, used for the upper bound of `~>`
+    ///
+    /// Based on Ruby's `Gem::Version#bump`: prerelease segments are dropped, then the last
+    /// segment (if there is more than one) is dropped, then the new last segment is incremented.
+    ///
+    /// ```rust
+    /// use std::str::FromStr;
+    /// use commons::gem_version::GemVersion;
+    ///
+    /// let version = GemVersion::from_str("1.2.3").unwrap();
+    /// assert_eq!(version.bump(), GemVersion::from_str("1.3").unwrap());
+    /// ```
+    #[must_use]
+    pub fn bump(&self) -> Self {
+        let mut release = self.release.clone();
+        if release.len() > 1 {
+            release.pop();
+        }
+        match release.last_mut() {
+            Some(last) => *last += 1,
+            None => release.push(1),
+        }
+
+        GemVersion {
+            segments: release.iter().copied().map(VersionSegment::U32).collect(),
+            release,
+        }
+    }
+}
+
 impl FromStr for GemVersion {
     type Err = VersionError;
 
@@ -46,6 +79,7 @@ impl FromStr for GemVersion {
         if version_string.trim().is_empty() {
             Ok(GemVersion {
                 segments: vec![VersionSegment::U32(0)],
+                release: vec![0],
             })
         } else {
             let validation_regex = fancy_regex::Regex::new(
@@ -81,6 +115,13 @@ impl FromStr for GemVersion {
                         },
                     );
 
+                let release = segments_l
+                    .iter()
+                    .filter_map(|segment| match segment {
+                        VersionSegment::U32(i) => Some(*i),
+                        VersionSegment::String(_) => None,
+                    })
+                    .collect();
                 let is_zero_segment = |v: &VersionSegment| *v == VersionSegment::U32(0);
                 let segments_l = drop_right_while(segments_l, is_zero_segment);
                 let segments_r = drop_right_while(segments_r, is_zero_segment);
@@ -88,7 +129,7 @@ impl FromStr for GemVersion {
                 let mut segments = segments_l;
                 segments.extend(segments_r);
 
-                Ok(GemVersion { segments })
+                Ok(GemVersion { segments, release })
             } else {
                 Err(VersionError::InvalidVersion(String::from(version_string)))
             }
@@ -137,7 +178,7 @@ pub enum VersionError {
     InvalidVersion(String),
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq)]
 enum VersionSegment {
     String(String),
     U32(u32),
@@ -223,6 +264,16 @@ mod test {
         );
     }
 
+    #[test]
+    // https://github.com/rubygems/rubygems/blob/ecc8e895b69063562b9bf749b353948e051e4171/test/rubygems/test_gem_version.rb#L20-L34
+    fn bump() {
+        assert_eq!(v("5.2.4").bump(), v("5.3"));
+        assert_eq!(v("5.2.4.a").bump(), v("5.3"));
+        assert_eq!(v("5.2.4.a10").bump(), v("5.3"));
+        assert_eq!(v("5.0.0").bump(), v("5.1"));
+        assert_eq!(v("5").bump(), v("6"));
+    }
+
     // Test helper method
     fn v(s: &str) -> GemVersion {
         s.parse().unwrap()
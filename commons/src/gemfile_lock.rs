@@ -44,6 +44,15 @@ use std::fmt::Display;
 pub struct GemfileLock {
     pub bundler_version: BundlerVersion,
     pub ruby_version: RubyVersion,
+    /// The `remote:` of each `PATH` source, i.e. local gems declared
+    /// with `path:` in the Gemfile. Relative to the Gemfile's directory.
+    pub path_remotes: Vec<String>,
+    /// Every resolved spec from the `GEM`, `GIT`, and `PATH` sections
+    pub specs: Vec<LockedSpec>,
+    /// Names of gems listed in the `DEPENDENCIES` section (declared in the `Gemfile`)
+    pub dependencies: Vec<String>,
+    /// Values from the `PLATFORMS` section
+    pub platforms: Vec<String>,
 }
 
 impl GemfileLock {
@@ -66,7 +75,7 @@ impl GemfileLock {
     #[must_use]
     pub fn resolve_ruby(&self, default: &str) -> ResolvedRubyVersion {
         match &self.ruby_version {
-            RubyVersion::Explicit(version) => ResolvedRubyVersion(version.to_string()),
+            RubyVersion::Explicit(version) => ResolvedRubyVersion(version.clone()),
             RubyVersion::Default => ResolvedRubyVersion(default.to_string()),
         }
     }
@@ -74,7 +83,7 @@ impl GemfileLock {
     #[must_use]
     pub fn resolve_bundler(&self, default: &str) -> ResolvedBundlerVersion {
         match &self.bundler_version {
-            BundlerVersion::Explicit(version) => ResolvedBundlerVersion(version.to_string()),
+            BundlerVersion::Explicit(version) => ResolvedBundlerVersion(version.clone()),
             BundlerVersion::Default => ResolvedBundlerVersion(default.to_string()),
         }
     }
@@ -100,6 +109,43 @@ impl Display for ResolvedBundlerVersion {
     }
 }
 
+/// A single resolved gem from the `Gemfile.lock`
+///
+/// A lockfile may contain multiple specs with the same name and version
+/// but different platforms, i.e. `nokogiri (1.16.0)` and `nokogiri (1.16.0-x86_64-linux)`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct LockedSpec {
+    pub name: String,
+    pub version: String,
+    pub platform: Option<String>,
+    pub source: LockedSource,
+    /// Names of gems this spec depends on
+    pub dependencies: Vec<String>,
+}
+
+impl LockedSpec {
+    /// Name, version, and platform (when not `ruby`) as used by
+    /// rubygems for directory and gemspec names i.e. `nokogiri-1.16.0-x86_64-linux`
+    #[must_use]
+    pub fn full_name(&self) -> String {
+        match &self.platform {
+            Some(platform) => format!("{}-{}-{platform}", self.name, self.version),
+            None => format!("{}-{}", self.name, self.version),
+        }
+    }
+}
+
+/// Where a locked spec comes from
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum LockedSource {
+    /// `GEM` section, a gem server such as `https://rubygems.org/`
+    Rubygems { remote: String },
+    /// `GIT` section, a git repository at a specific revision
+    Git { remote: String, revision: String },
+    /// `PATH` section, a directory relative to the `Gemfile`
+    Path { remote: String },
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum RubyVersion {
     Explicit(String),
@@ -141,13 +187,121 @@ impl FromStr for GemfileLock {
             None => RubyVersion::Default,
         };
 
+        let Sections {
+            path_remotes,
+            specs,
+            dependencies,
+            platforms,
+        } = Sections::parse(string);
+
         Ok(Self {
             bundler_version,
             ruby_version,
+            path_remotes,
+            specs,
+            dependencies,
+            platforms,
         })
     }
 }
 
+/// Line based parser for the source, platform, and dependency sections of a lockfile
+///
+/// Sections start with an unindented header such as `GEM`. Source attributes are indented two
+/// spaces, specs four spaces, and the dependencies of a spec six spaces.
+#[derive(Debug, Default)]
+struct Sections {
+    path_remotes: Vec<String>,
+    specs: Vec<LockedSpec>,
+    dependencies: Vec<String>,
+    platforms: Vec<String>,
+}
+
+impl Sections {
+    fn parse(string: &str) -> Self {
+        let mut out = Sections::default();
+        let mut header = "";
+        let mut remote = None;
+        let mut revision = None;
+
+        for line in string.lines().map(str::trim_end) {
+            if line.is_empty() {
+                continue;
+            }
+            let indent = line.len() - line.trim_start().len();
+            let content = line.trim_start();
+
+            if indent == 0 {
+                header = content;
+                remote = None;
+                revision = None;
+                continue;
+            }
+
+            match (header, indent) {
+                ("GEM" | "GIT" | "PATH", 2) => {
+                    if let Some(value) = content.strip_prefix("remote: ") {
+                        if header == "PATH" {
+                            out.path_remotes.push(value.to_string());
+                        }
+                        remote.get_or_insert_with(|| value.to_string());
+                    } else if let Some(value) = content.strip_prefix("revision: ") {
+                        revision = Some(value.to_string());
+                    }
+                }
+                ("GEM" | "GIT" | "PATH", 4) => {
+                    let remote = remote.clone().unwrap_or_default();
+                    let source = match header {
+                        "GIT" => LockedSource::Git {
+                            remote,
+                            revision: revision.clone().unwrap_or_default(),
+                        },
+                        "PATH" => LockedSource::Path { remote },
+                        _ => LockedSource::Rubygems { remote },
+                    };
+                    let (name, version) = name_and_requirement(content);
+                    let version = version.unwrap_or_default();
+                    let (version, platform) = match version.split_once('-') {
+                        Some((version, platform)) => (version, Some(platform.to_string())),
+                        None => (version, None),
+                    };
+
+                    out.specs.push(LockedSpec {
+                        name: name.to_string(),
+                        version: version.to_string(),
+                        platform,
+                        source,
+                        dependencies: Vec::new(),
+                    });
+                }
+                ("GEM" | "GIT" | "PATH", 6) => {
+                    if let Some(spec) = out.specs.last_mut() {
+                        spec.dependencies
+                            .push(name_and_requirement(content).0.to_string());
+                    }
+                }
+                ("DEPENDENCIES", 2) => {
+                    let (name, _) = name_and_requirement(content);
+                    out.dependencies
+                        .push(name.trim_end_matches('!').to_string());
+                }
+                ("PLATFORMS", 2) => out.platforms.push(content.to_string()),
+                _ => {}
+            }
+        }
+
+        out
+    }
+}
+
+/// Splits `name (requirement)` into its parts
+fn name_and_requirement(content: &str) -> (&str, Option<&str>) {
+    match content.split_once(" (") {
+        Some((name, rest)) => (name, Some(rest.trim_end_matches(')'))),
+        None => (content, None),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -264,6 +418,127 @@ BUNDLED WITH
         assert_eq!(info.ruby_version, RubyVersion::Default);
     }
 
+    #[test]
+    fn test_path_remotes() {
+        let info = GemfileLock::from_str(
+            r"
+PATH
+  remote: .
+  specs:
+    my_app (0.1.0)
+
+PATH
+  remote: engines/billing
+  specs:
+    billing (1.0.0)
+
+GEM
+  remote: https://rubygems.org/
+  specs:
+    rake (13.0.6)
+",
+        )
+        .unwrap();
+
+        assert_eq!(
+            info.path_remotes,
+            vec![String::from("."), String::from("engines/billing")]
+        );
+        assert!(GemfileLock::from_str("").unwrap().path_remotes.is_empty());
+    }
+
+    #[test]
+    fn test_specs() {
+        let info = GemfileLock::from_str(
+            r"
+GIT
+  remote: https://github.com/rails/rails.git
+  revision: 9e9b2b1e0e4a5a9b7a6f1f0ff1e7e7d33c0fa0a1
+  branch: main
+  specs:
+    activesupport (7.2.0.alpha)
+      concurrent-ruby (~> 1.0, >= 1.0.2)
+
+PATH
+  remote: .
+  specs:
+    my_app (0.1.0)
+      rack
+
+GEM
+  remote: https://rubygems.org/
+  specs:
+    concurrent-ruby (1.2.2)
+    nokogiri (1.16.0)
+      racc (~> 1.4)
+    nokogiri (1.16.0-x86_64-linux)
+      racc (~> 1.4)
+    rack (3.0.8)
+    racc (1.7.3)
+
+PLATFORMS
+  ruby
+  x86_64-linux
+
+DEPENDENCIES
+  activesupport!
+  my_app!
+  nokogiri (>= 1.15)
+
+BUNDLED WITH
+   2.5.6
+",
+        )
+        .unwrap();
+
+        assert_eq!(
+            info.dependencies,
+            vec![
+                String::from("activesupport"),
+                String::from("my_app"),
+                String::from("nokogiri")
+            ]
+        );
+        assert_eq!(
+            info.platforms,
+            vec![String::from("ruby"), String::from("x86_64-linux")]
+        );
+        assert_eq!(info.specs.len(), 7);
+        assert_eq!(
+            info.specs[0],
+            LockedSpec {
+                name: String::from("activesupport"),
+                version: String::from("7.2.0.alpha"),
+                platform: None,
+                source: LockedSource::Git {
+                    remote: String::from("https://github.com/rails/rails.git"),
+                    revision: String::from("9e9b2b1e0e4a5a9b7a6f1f0ff1e7e7d33c0fa0a1"),
+                },
+                dependencies: vec![String::from("concurrent-ruby")],
+            }
+        );
+        assert_eq!(
+            info.specs[1].source,
+            LockedSource::Path {
+                remote: String::from(".")
+            }
+        );
+        assert_eq!(
+            info.specs[4],
+            LockedSpec {
+                name: String::from("nokogiri"),
+                version: String::from("1.16.0"),
+                platform: Some(String::from("x86_64-linux")),
+                source: LockedSource::Rubygems {
+                    remote: String::from("https://rubygems.org/"),
+                },
+                dependencies: vec![String::from("racc")],
+            }
+        );
+        assert_eq!(info.specs[4].full_name(), "nokogiri-1.16.0-x86_64-linux");
+        assert_eq!(info.specs[3].full_name(), "nokogiri-1.16.0");
+    }
+
     #[test]
     fn test_jruby() {
         let info = GemfileLock::from_str(
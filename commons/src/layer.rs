@@ -1 +1,6 @@
+pub mod clear_cache;
 pub mod diff_migrate;
+pub mod gc;
+pub mod provenance;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
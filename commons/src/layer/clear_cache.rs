@@ -0,0 +1,117 @@
+//! Force clearing layer caches
+//!
+//! A cache can end up in a bad state that cache invalidation logic does not catch, for example
+//! when a dependency was compiled against a system library that has since changed. Setting
+//! [`CLEAR_CACHE_ENV_KEY`] clears every layer, or only the named layers, on the next build:
+//!
+//! ```shell
+//! $ pack config env HEROKU_CLEAR_CACHE=all
+//! $ pack config env HEROKU_CLEAR_CACHE=gems,cache_public_assets
+//! ```
+//!
+//! Layers created with [`crate::layer::diff_migrate::DiffMigrateLayer`] and
+//! [`crate::cache::AppCache`] check the setting automatically and report the clear
+//! through [`crate::layer::diff_migrate::Meta::Message`].
+
+use libcnb::data::layer::LayerName;
+use libcnb::Env;
+
+/// Environment variable that lists the layers to clear, or `all`
+pub const CLEAR_CACHE_ENV_KEY: &str = "HEROKU_CLEAR_CACHE";
+
+/// Which layers to clear regardless of their metadata
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub enum ClearCache {
+    /// Keep caches according to their metadata
+    #[default]
+    None,
+    /// Clear every layer
+    All,
+    /// Clear layers with these names
+    Layers(Vec<String>),
+}
+
+impl ClearCache {
+    /// Reads [`CLEAR_CACHE_ENV_KEY`], `1`, `true`, and `all` clear every layer,
+    /// any other value is a comma separated list of layer names
+    #[must_use]
+    pub fn from_env(env: &Env) -> Self {
+        let Some(value) = env.get(CLEAR_CACHE_ENV_KEY) else {
+            return ClearCache::None;
+        };
+        let value = value.to_string_lossy();
+        match value.trim().to_lowercase().as_str() {
+            "" | "0" | "false" => ClearCache::None,
+            "1" | "true" | "all" => ClearCache::All,
+            _ => ClearCache::Layers(
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .map(String::from)
+                    .collect(),
+            ),
+        }
+    }
+
+    /// True when the layer should be cleared
+    #[must_use]
+    pub fn includes(&self, layer_name: &LayerName) -> bool {
+        match self {
+            ClearCache::None => false,
+            ClearCache::All => true,
+            ClearCache::Layers(names) => names.iter().any(|name| name == layer_name.as_str()),
+        }
+    }
+
+    /// A user readable reason for clearing the layer, `None` when the layer is kept
+    #[must_use]
+    pub fn message(&self, layer_name: &LayerName) -> Option<String> {
+        self.reason(layer_name)
+            .map(|reason| format!("Clearing cache due to {reason}"))
+    }
+
+    /// The setting that clears the layer, for example `HEROKU_CLEAR_CACHE=all`
+    #[must_use]
+    pub fn reason(&self, layer_name: &LayerName) -> Option<String> {
+        match self {
+            ClearCache::None => None,
+            ClearCache::All => Some(format!("{CLEAR_CACHE_ENV_KEY}=all")),
+            ClearCache::Layers(names) => self.includes(layer_name).then(|| {
+                format!(
+                    "{CLEAR_CACHE_ENV_KEY}={names} (includes `{layer_name}`)",
+                    names = names.join(",")
+                )
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libcnb::data::layer_name;
+
+    #[test]
+    fn test_from_env() {
+        let mut env = Env::new();
+        assert_eq!(ClearCache::from_env(&env), ClearCache::None);
+
+        env.insert(CLEAR_CACHE_ENV_KEY, "ALL");
+        assert_eq!(ClearCache::from_env(&env), ClearCache::All);
+
+        env.insert(CLEAR_CACHE_ENV_KEY, " gems, ,bundler ");
+        let clear = ClearCache::from_env(&env);
+        assert_eq!(
+            clear,
+            ClearCache::Layers(vec![String::from("gems"), String::from("bundler")])
+        );
+        assert!(clear.includes(&layer_name!("gems")));
+        assert!(!clear.includes(&layer_name!("binruby")));
+        assert_eq!(
+            clear.message(&layer_name!("gems")).as_deref(),
+            Some("Clearing cache due to HEROKU_CLEAR_CACHE=gems,bundler (includes `gems`)")
+        );
+        assert_eq!(clear.message(&layer_name!("binruby")), None);
+    }
+}
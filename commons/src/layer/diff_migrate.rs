@@ -40,9 +40,39 @@
 //! build output. If the cache is cleared for any reason, then a user readable message is returned. This message should
 //! be printed to the buildpack user so they can understand what caused the cache to clear.
 //!
+//! ## Keep and refresh ([`RefreshDiff`])
+//!
+//! Some changes don't need a rebuild, for example a new CA bundle or a patch version bump that only
+//! needs a re-link. Ignore those fields in `CacheDiff`, implement [`RefreshDiff`] for them, and use
+//! [`DiffMigrateLayer::cached_layer_refresh`]. When only refreshable fields changed, the layer is kept
+//! and [`Meta::Refresh`] hands back the old metadata and the differences. Run the incremental
+//! update, then write the new metadata with [`Refresh::write_metadata`].
+//!
+//! ## Provenance ([`crate::layer::provenance::Provenance`])
+//!
+//! [`DiffMigrateLayer`] records which buildpack version created a layer, when it was created and last
+//! reused, and its size when [`CACHE_EXPLAIN_ENV_KEY`] is set. A kept layer is returned as
+//! [`Meta::Cached`], which displays as `Using cache (created 12 days ago by 5.0.1)`, or with the size
+//! as `Using cache (created 12 days ago by 5.0.1, 84 MiB)`. Use [`Meta::data`] to read the old metadata.
+//!
+//! The provenance is stored in the layer metadata under a `provenance` key. Migrations receive it, so an
+//! older struct in a [`magic_migrate::TryMigrate`] chain can declare a `provenance: Option<Provenance>` field
+//! and branch on the buildpack version that wrote the metadata.
+//!
+//! ## Explain and dry run ([`explain`])
+//!
+//! When a cache clears unexpectedly (or doesn't clear when it should), set [`CACHE_EXPLAIN_ENV_KEY`]
+//! to `1` and print the [`Explanation`] returned by [`explain`] for each layer. It shows the old and
+//! new metadata, compares every field, lists the [`magic_migrate::TryMigrate`] chain that was
+//! attempted, and names the chosen action. Set it to `dry-run` to keep cache-only layers that would be
+//! cleared. Build and launch layers are still cleared, their stale contents would end up in the build
+//! or the image.
+//!
 #![doc = include_str!("fixtures/metadata_migration_example.md")]
 
 use crate::display::SentenceList;
+use crate::layer::clear_cache::ClearCache;
+use crate::layer::provenance::{self, Provenance, PROVENANCE_KEY};
 use cache_diff::CacheDiff;
 use fs_err::PathExt;
 use libcnb::build::BuildContext;
@@ -50,8 +80,12 @@ use libcnb::data::layer::LayerName;
 use libcnb::layer::{
     CachedLayerDefinition, InvalidMetadataAction, LayerError, LayerRef, RestoredLayerAction,
 };
+use libcnb::Platform;
 use magic_migrate::TryMigrate;
 use serde::ser::Serialize;
+use std::any::TypeId;
+use std::cell::RefCell;
+use std::collections::BTreeSet;
 use std::fmt::Debug;
 use std::path::PathBuf;
 
@@ -93,6 +127,8 @@ use bullet_stream as _;
 ///   When a `CacheDiff::diff` is empty, the layer is kept and the old data is returned. Otherwise,
 ///   the layer is deleted and the changes are returned.
 ///
+/// Layers named in [`crate::layer::clear_cache::CLEAR_CACHE_ENV_KEY`] are always cleared.
+///
 /// **TUTORIAL:** In the [`diff_migrate`] module docs
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct DiffMigrateLayer {
@@ -120,16 +156,140 @@ impl DiffMigrateLayer {
         B: libcnb::Buildpack,
         M: CacheDiff + TryMigrate + Serialize + Debug + Clone,
     {
+        self.cached_layer_with(layer_name, context, metadata, &|_, _| Vec::new())
+    }
+
+    /// Like [`DiffMigrateLayer::cached_layer`], but differences from [`RefreshDiff`] keep the layer
+    ///
+    /// When [`CacheDiff`] reports no differences but [`RefreshDiff`] does, the layer is kept and
+    /// returned as [`Meta::Refresh`] with the old metadata and the list of differences. The new
+    /// metadata is not written, run the incremental update and then call [`Refresh::write_metadata`].
+    /// If the update fails or is skipped, the next build is asked to refresh again.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if libcnb cannot read or write the metadata.
+    pub fn cached_layer_refresh<B, M>(
+        self,
+        layer_name: LayerName,
+        context: &BuildContext<B>,
+        metadata: &M,
+    ) -> libcnb::Result<LayerRef<B, Meta<M>, Meta<M>>, B::Error>
+    where
+        B: libcnb::Buildpack,
+        M: CacheDiff + RefreshDiff + TryMigrate + Serialize + Debug + Clone,
+    {
+        self.cached_layer_with(layer_name, context, metadata, &|now, old| {
+            now.refresh_diff(old)
+        })
+    }
+
+    fn cached_layer_with<B, M>(
+        self,
+        layer_name: LayerName,
+        context: &BuildContext<B>,
+        metadata: &M,
+        refresh_diff: &dyn Fn(&M, &M) -> Vec<String>,
+    ) -> libcnb::Result<LayerRef<B, Meta<M>, Meta<M>>, B::Error>
+    where
+        B: libcnb::Buildpack,
+        M: CacheDiff + TryMigrate + Serialize + Debug + Clone,
+    {
+        let clear = ClearCache::from_env(context.platform.env()).reason(&layer_name);
+        // Stale build or launch layers would change the build or ship in the image
+        let dry_run = !self.build
+            && !self.launch
+            && CacheMode::from_env(context.platform.env()) == CacheMode::DryRun;
+        // Only measured when explaining, walking a large layer on every build is slow
+        let measure = CacheMode::from_env(context.platform.env()) != CacheMode::Normal;
+        // Launch layer metadata is exported with the image, a reuse timestamp would change it
+        let launch = self.launch;
+        let now = provenance::now();
+        let kept = RefCell::new(None::<Kept>);
+        // Metadata is read as a table so the provenance can be separated from `M`
         let layer_ref = context.cached_layer(
             layer_name,
             CachedLayerDefinition {
                 build: self.build,
                 launch: self.launch,
-                invalid_metadata_action: &invalid_metadata_action,
-                restored_layer_action: &|old: &M, _| restored_layer_action(old, metadata),
+                invalid_metadata_action: &|invalid| match invalid_action_or_clear::<M, _>(
+                    invalid,
+                    clear.as_deref(),
+                ) {
+                    (InvalidMetadataAction::ReplaceMetadata(migrated), meta) => (
+                        InvalidMetadataAction::ReplaceMetadata(
+                            toml::Table::try_from(&migrated).unwrap_or_default(),
+                        ),
+                        meta,
+                    ),
+                    (InvalidMetadataAction::DeleteLayer, meta) => {
+                        (InvalidMetadataAction::DeleteLayer, meta)
+                    }
+                },
+                restored_layer_action: &|old: &toml::Table, path| match restored_table_action(
+                    old,
+                    metadata,
+                    clear.as_deref(),
+                ) {
+                    (Ok(data), stored) => {
+                        let provenance = stored.clone().map(|p| {
+                            let p = p.reused(now);
+                            if measure {
+                                p.measured(path)
+                            } else {
+                                p
+                            }
+                        });
+                        // Unknown for layers written before provenance was recorded, a new one
+                        // is only written when the layer is recreated
+                        let written = if launch { stored } else { provenance.clone() };
+                        let differences = refresh_diff(metadata, &data);
+                        if !differences.is_empty() {
+                            kept.replace(Some(Kept::Refresh));
+                            return (
+                                RestoredLayerAction::KeepLayer,
+                                Meta::Refresh(Refresh::new(data, differences, written)),
+                            );
+                        }
+                        kept.replace(Some(Kept::Provenance(written)));
+                        (
+                            RestoredLayerAction::KeepLayer,
+                            match provenance {
+                                Some(provenance) => {
+                                    Meta::Cached(Cached::new(data, provenance, now))
+                                }
+                                None => Meta::Data(data),
+                            },
+                        )
+                    }
+                    (Err(reason), _) if dry_run => {
+                        kept.replace(Some(Kept::DryRun(old.clone())));
+                        (
+                            RestoredLayerAction::KeepLayer,
+                            Meta::Message(reason.dry_run_message()),
+                        )
+                    }
+                    (Err(reason), _) => (
+                        RestoredLayerAction::DeleteLayer,
+                        Meta::Message(reason.message()),
+                    ),
+                },
             },
         )?;
-        layer_ref.write_metadata(metadata)?;
+        match kept.into_inner() {
+            // Keep the old metadata so the next build without dry run makes the same decision
+            Some(Kept::DryRun(old)) => layer_ref.write_metadata(old)?,
+            // The caller writes the metadata once the layer is refreshed
+            Some(Kept::Refresh) => {}
+            Some(Kept::Provenance(provenance)) => layer_ref.write_metadata(WithProvenance {
+                metadata,
+                provenance: provenance.as_ref(),
+            })?,
+            None => layer_ref.write_metadata(WithProvenance {
+                metadata,
+                provenance: Some(&Provenance::new(context, now)),
+            })?,
+        }
         Ok(layer_ref)
     }
 
@@ -185,6 +345,105 @@ impl DiffMigrateLayer {
     }
 }
 
+/// What happened to a kept layer
+enum Kept {
+    /// The layer was kept normally, `None` when it was written before provenance was recorded
+    Provenance(Option<Provenance>),
+    /// The layer would have been cleared, holds the old metadata
+    DryRun(toml::Table),
+    /// The layer needs a refresh before the new metadata is written
+    Refresh,
+}
+
+/// Metadata as written to disk
+#[derive(serde::Serialize)]
+struct WithProvenance<'a, M> {
+    #[serde(flatten)]
+    metadata: &'a M,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    provenance: Option<&'a Provenance>,
+}
+
+/// Why a layer is cleared, for example `change: value (hello to world)`
+struct ClearReason(String);
+
+impl ClearReason {
+    fn message(&self) -> String {
+        format!("Clearing cache due to {}", self.0)
+    }
+
+    fn dry_run_message(&self) -> String {
+        format!(
+            "Dry run, keeping cache that would be cleared due to {}",
+            self.0
+        )
+    }
+}
+
+/// Decides what to do with a restored layer from its metadata table, returns the old data
+/// when the layer is kept or why it's cleared
+///
+/// Metadata that is not a valid `M` goes through [`TryMigrate`]. The migration chain
+/// first receives the metadata including the provenance, so older structs can declare
+/// a `provenance: Option<Provenance>` field and branch on the buildpack version that wrote them.
+fn restored_table_action<M>(
+    old: &toml::Table,
+    now: &M,
+    clear: Option<&str>,
+) -> (Result<M, ClearReason>, Option<Provenance>)
+where
+    M: CacheDiff + TryMigrate + Clone,
+{
+    let mut table = old.clone();
+    let provenance = Provenance::remove_from(&mut table);
+    if let Some(reason) = clear {
+        return (Err(ClearReason(reason.to_string())), provenance);
+    }
+    let old_data = match toml::Value::Table(table.clone()).try_into::<M>() {
+        Ok(old_data) => old_data,
+        Err(_) => match migrate_with_provenance::<M>(old)
+            .map_or_else(|| migrate_invalid::<M, _>(&table), Ok)
+        {
+            Ok(migrated) => migrated,
+            Err(reason) => return (Err(reason), provenance),
+        },
+    };
+    match diff_reason(&old_data, now) {
+        Some(reason) => (Err(reason), provenance),
+        None => (Ok(old_data), provenance),
+    }
+}
+
+/// Migrates metadata that still contains the provenance, `None` when no struct in the chain accepts it
+fn migrate_with_provenance<M>(old: &toml::Table) -> Option<M>
+where
+    M: TryMigrate,
+{
+    if !old.contains_key(PROVENANCE_KEY) {
+        return None;
+    }
+    let toml = toml::to_string(old).ok()?;
+    M::try_from_str_migrations(&toml)?.ok()
+}
+
+/// Clears the layer when [`ClearCache`] includes it, otherwise uses [`invalid_metadata_action`]
+fn invalid_action_or_clear<M, S>(
+    invalid: &S,
+    clear: Option<&str>,
+) -> (InvalidMetadataAction<M>, Meta<M>)
+where
+    M: TryMigrate + Clone,
+    S: Serialize + Debug,
+{
+    match clear {
+        Some(reason) => (
+            InvalidMetadataAction::DeleteLayer,
+            Meta::Message(ClearReason(reason.to_string()).message()),
+        ),
+        None => invalid_metadata_action(invalid),
+    }
+}
+
 /// Represents when we want to move contents from one (or more) layer names
 ///
 pub struct LayerRename {
@@ -218,21 +477,26 @@ pub fn restored_layer_action<M>(old: &M, now: &M) -> (RestoredLayerAction, Meta<
 where
     M: CacheDiff + Clone,
 {
-    let diff = now.diff(old);
-    if diff.is_empty() {
-        (RestoredLayerAction::KeepLayer, Meta::Data(old.clone()))
-    } else {
-        (
+    match diff_reason(old, now) {
+        Some(reason) => (
             RestoredLayerAction::DeleteLayer,
-            Meta::Message(format!(
-                "Clearing cache due to {changes}: {differences}",
-                changes = if diff.len() > 1 { "changes" } else { "change" },
-                differences = SentenceList::new(&diff)
-            )),
-        )
+            Meta::Message(reason.message()),
+        ),
+        None => (RestoredLayerAction::KeepLayer, Meta::Data(old.clone())),
     }
 }
 
+fn diff_reason<M: CacheDiff>(old: &M, now: &M) -> Option<ClearReason> {
+    let diff = now.diff(old);
+    (!diff.is_empty()).then(|| {
+        ClearReason(format!(
+            "{changes}: {differences}",
+            changes = if diff.len() > 1 { "changes" } else { "change" },
+            differences = SentenceList::new(&diff)
+        ))
+    })
+}
+
 /// Standardizes formatting for invalid metadata behavior
 ///
 /// If the metadata can be migrated, it is replaced with the migrated version
@@ -243,36 +507,286 @@ where
     M: TryMigrate + Clone,
     S: Serialize + Debug,
 {
-    let invalid = toml::to_string(invalid);
-    match invalid {
-        Ok(toml) => match M::try_from_str_migrations(&toml) {
-            Some(Ok(migrated)) => (
-                InvalidMetadataAction::ReplaceMetadata(migrated.clone()),
-                Meta::Data(migrated),
-            ),
-            Some(Err(error)) => (
-                InvalidMetadataAction::DeleteLayer,
-                Meta::Message(format!(
-                    "Clearing cache due to metadata migration error: {error}"
-                )),
-            ),
-            None => (
-                InvalidMetadataAction::DeleteLayer,
-                Meta::Message(format!(
-                    "Clearing cache due to invalid metadata ({toml})",
-                    toml = toml.trim()
-                )),
-            ),
-        },
-        Err(error) => (
+    match migrate_invalid::<M, S>(invalid) {
+        Ok(migrated) => (
+            InvalidMetadataAction::ReplaceMetadata(migrated.clone()),
+            Meta::Data(migrated),
+        ),
+        Err(reason) => (
             InvalidMetadataAction::DeleteLayer,
-            Meta::Message(format!(
-                "Clearing cache due to invalid metadata serialization error: {error}"
-            )),
+            Meta::Message(reason.message()),
         ),
     }
 }
 
+fn migrate_invalid<M, S>(invalid: &S) -> Result<M, ClearReason>
+where
+    M: TryMigrate,
+    S: Serialize + Debug,
+{
+    let toml = toml::to_string(invalid)
+        .map_err(|error| ClearReason(format!("invalid metadata serialization error: {error}")))?;
+    match M::try_from_str_migrations(&toml) {
+        Some(Ok(migrated)) => Ok(migrated),
+        Some(Err(error)) => Err(ClearReason(format!("metadata migration error: {error}"))),
+        None => Err(ClearReason(format!(
+            "invalid metadata ({toml})",
+            toml = toml.trim()
+        ))),
+    }
+}
+
+/// Environment variable that turns on [`CacheMode::Explain`] or [`CacheMode::DryRun`]
+///
+/// Set to `1` (or `true`) to explain cache decisions, set to `dry-run` to also keep every
+/// cache-only layer that would be cleared.
+pub const CACHE_EXPLAIN_ENV_KEY: &str = "HEROKU_CACHE_EXPLAIN";
+
+/// How cache decisions are reported and applied
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum CacheMode {
+    /// Decisions are applied without any extra output
+    #[default]
+    Normal,
+    /// Decisions are applied and [`explain`] describes how they were made
+    Explain,
+    /// Like [`CacheMode::Explain`], but cache-only layers that would be cleared are kept
+    ///
+    /// Build and launch layers are still cleared, their stale contents would otherwise be used
+    /// by the build or exported in the image.
+    ///
+    /// The build uses the kept contents as-is and the old metadata is preserved so the next
+    /// build without dry run clears the layer as expected.
+    DryRun,
+}
+
+impl CacheMode {
+    /// Reads the mode from [`CACHE_EXPLAIN_ENV_KEY`], unknown values are ignored
+    #[must_use]
+    pub fn from_env(env: &libcnb::Env) -> Self {
+        let Some(value) = env.get(CACHE_EXPLAIN_ENV_KEY) else {
+            return CacheMode::Normal;
+        };
+        match value.to_string_lossy().trim().to_lowercase().as_str() {
+            "1" | "true" | "explain" => CacheMode::Explain,
+            "dry-run" | "dry_run" | "dryrun" => CacheMode::DryRun,
+            _ => CacheMode::Normal,
+        }
+    }
+}
+
+/// A description of the cache decision for a single layer, see [`explain`]
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Explanation {
+    pub layer_name: LayerName,
+    pub mode: CacheMode,
+    pub lines: Vec<String>,
+}
+
+impl std::fmt::Display for Explanation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Cache explain for layer `{name}`{dry_run}",
+            name = self.layer_name,
+            dry_run = if self.mode == CacheMode::DryRun {
+                " (dry run)"
+            } else {
+                ""
+            }
+        )?;
+        for line in &self.lines {
+            write!(f, "\n{line}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Describes how [`DiffMigrateLayer::cached_layer`] will treat a layer, without modifying it
+///
+/// Returns `None` unless [`CACHE_EXPLAIN_ENV_KEY`] is set in the platform environment. Must be
+/// called before [`DiffMigrateLayer::cached_layer`] as it reads the metadata from the prior build.
+///
+/// The explanation includes the old and new metadata, a field by field comparison, the result of
+/// [`CacheDiff`], the [`TryMigrate`] chain when old metadata is invalid, and the chosen
+/// [`RestoredLayerAction`] or [`InvalidMetadataAction`].
+///
+/// # Errors
+///
+/// Returns an error if the layer's TOML file exists but cannot be read.
+pub fn explain<B, M>(
+    layer_name: &LayerName,
+    context: &BuildContext<B>,
+    metadata: &M,
+) -> libcnb::Result<Option<Explanation>, B::Error>
+where
+    B: libcnb::Buildpack,
+    M: CacheDiff + TryMigrate + Serialize + Debug + Clone,
+{
+    let mode = CacheMode::from_env(context.platform.env());
+    if mode == CacheMode::Normal {
+        return Ok(None);
+    }
+    let layer_toml = context
+        .layers_dir
+        .join(layer_name.as_str())
+        .with_extension("toml");
+    let contents = match fs_err::read_to_string(&layer_toml) {
+        Ok(contents) => Some(contents),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => None,
+        Err(error) => return Err(LayerError::IoError(error).into()),
+    };
+
+    Ok(Some(Explanation {
+        layer_name: layer_name.clone(),
+        mode,
+        lines: explain_lines(
+            contents.as_deref(),
+            metadata,
+            mode,
+            ClearCache::from_env(context.platform.env())
+                .message(layer_name)
+                .as_deref(),
+        ),
+    }))
+}
+
+fn explain_lines<M>(
+    layer_toml: Option<&str>,
+    metadata: &M,
+    mode: CacheMode,
+    clear: Option<&str>,
+) -> Vec<String>
+where
+    M: CacheDiff + TryMigrate + Serialize + Debug + Clone,
+{
+    let now = toml::Table::try_from(metadata).unwrap_or_default();
+    let mut lines = vec![format!("New metadata: {}", inline_table(&now))];
+    let Some(layer_toml) = layer_toml else {
+        lines.push(String::from("No cached layer, action: create a new layer"));
+        return lines;
+    };
+    let mut old = toml::from_str::<toml::Table>(layer_toml)
+        .ok()
+        .and_then(|mut table| table.remove("metadata"))
+        .and_then(|value| value.as_table().cloned())
+        .unwrap_or_default();
+    let provenance = Provenance::remove_from(&mut old);
+    lines.push(format!("Old metadata: {}", inline_table(&old)));
+    lines.push(match provenance {
+        Some(provenance) => format!("Provenance: {}", provenance.summary(provenance::now())),
+        None => String::from("Provenance: not recorded"),
+    });
+    lines.extend(compare_fields(&old, &now));
+
+    let old_data = if let Ok(old_data) = toml::Value::Table(old.clone()).try_into::<M>() {
+        lines.push(format!(
+            "Migration: not needed, old metadata is a valid `{}`",
+            short_type_name::<M>()
+        ));
+        Some(old_data)
+    } else if let Some(message) = clear {
+        lines.push(format!("Migration: not attempted, {message}"));
+        None
+    } else {
+        let input = toml::to_string(&old).unwrap_or_default();
+        lines.push(format!(
+            "Migration chain (oldest first): {}",
+            migration_chain::<M>(&input).join(", ")
+        ));
+        match invalid_metadata_action::<M, _>(&old) {
+            (InvalidMetadataAction::ReplaceMetadata(migrated), _) => {
+                lines.push(String::from(
+                    "Migration: succeeded, action: InvalidMetadataAction::ReplaceMetadata",
+                ));
+                Some(migrated)
+            }
+            (InvalidMetadataAction::DeleteLayer, meta) => {
+                lines.push(format!("Migration: failed, {meta}"));
+                None
+            }
+        }
+    };
+    let action = match old_data {
+        None => "InvalidMetadataAction::DeleteLayer",
+        Some(_) if clear.is_some() => {
+            lines.push(format!(
+                "CacheDiff: not compared, {}",
+                clear.unwrap_or_default()
+            ));
+            "RestoredLayerAction::DeleteLayer"
+        }
+        Some(old_data) => {
+            let diff = metadata.diff(&old_data);
+            if diff.is_empty() {
+                lines.push(String::from("CacheDiff: no differences"));
+                "RestoredLayerAction::KeepLayer"
+            } else {
+                lines.push(format!("CacheDiff: {}", SentenceList::new(&diff)));
+                "RestoredLayerAction::DeleteLayer"
+            }
+        }
+    };
+    lines.push(format!("Action: {action}"));
+    if mode == CacheMode::DryRun && action.ends_with("DeleteLayer") {
+        lines.push(String::from(
+            "Dry run: kept when the layer is cache-only, build and launch layers are still cleared",
+        ));
+    }
+    lines
+}
+
+/// One line per metadata field, noting whether the value changed
+fn compare_fields(old: &toml::Table, now: &toml::Table) -> Vec<String> {
+    old.keys()
+        .chain(now.keys())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .map(|key| match (old.get(key), now.get(key)) {
+            (Some(old), Some(now)) if old == now => format!("Field `{key}`: {now} (same)"),
+            (Some(old), Some(now)) => format!("Field `{key}`: {old} to {now} (different)"),
+            (Some(old), None) => format!("Field `{key}`: {old} (removed)"),
+            (None, Some(now)) => format!("Field `{key}`: {now} (added)"),
+            (None, None) => unreachable!("key comes from one of the tables"),
+        })
+        .collect()
+}
+
+/// Every type in the [`TryMigrate`] chain of `M`, oldest first, noting which can read `input`
+pub(crate) fn migration_chain<M: TryMigrate>(input: &str) -> Vec<String> {
+    let mut chain = if TypeId::of::<M>() == TypeId::of::<M::TryFrom>() {
+        Vec::new()
+    } else {
+        migration_chain::<M::TryFrom>(input)
+    };
+    chain.push(format!(
+        "`{name}` ({matches})",
+        name = short_type_name::<M>(),
+        matches = if M::deserialize(M::deserializer(input)).is_ok() {
+            "matches"
+        } else {
+            "no match"
+        }
+    ));
+    chain
+}
+
+fn short_type_name<M>() -> &'static str {
+    let name = std::any::type_name::<M>();
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+fn inline_table(table: &toml::Table) -> String {
+    format!(
+        "{{{}}}",
+        table
+            .iter()
+            .map(|(key, value)| format!("{key} = {value}"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    )
+}
+
 /// Either contains (old) metadata or a message describing the state
 ///
 /// Why: The [`CachedLayerDefinition`] allows returning information about the cache state
@@ -295,6 +809,23 @@ where
 pub enum Meta<M> {
     Message(String),
     Data(M),
+    /// Old metadata of a kept layer along with where the layer came from
+    Cached(Cached<M>),
+    /// Old metadata of a kept layer that needs a refresh, see [`DiffMigrateLayer::cached_layer_refresh`]
+    Refresh(Refresh<M>),
+}
+
+impl<M> Meta<M> {
+    /// The old metadata when the cache is retained
+    #[must_use]
+    pub fn data(&self) -> Option<&M> {
+        match self {
+            Meta::Message(_) => None,
+            Meta::Data(data) => Some(data),
+            Meta::Cached(cached) => Some(&cached.data),
+            Meta::Refresh(refresh) => Some(&refresh.old),
+        }
+    }
 }
 
 impl<M> std::fmt::Display for Meta<M> {
@@ -308,6 +839,89 @@ impl<M> AsRef<str> for Meta<M> {
         match self {
             Meta::Message(s) => s.as_str(),
             Meta::Data(_) => "Using cache",
+            Meta::Cached(cached) => cached.message.as_str(),
+            Meta::Refresh(refresh) => refresh.message.as_str(),
+        }
+    }
+}
+
+/// Differences that can be applied to a kept layer instead of clearing it
+///
+/// Used by [`DiffMigrateLayer::cached_layer_refresh`]. Fields compared here should be ignored by
+/// [`CacheDiff`], for example with `#[cache_diff(ignore)]`, otherwise a change clears the layer.
+pub trait RefreshDiff {
+    /// Returns a user readable description of each refreshable difference, empty when nothing changed
+    fn refresh_diff(&self, old: &Self) -> Vec<String>;
+}
+
+/// Old metadata of a kept layer that needs an incremental update
+///
+/// Displays as `Refreshing cache due to change: <differences>`.
+pub struct Refresh<M> {
+    pub old: M,
+    pub differences: Vec<String>,
+    /// Written with the new metadata, `None` when the layer predates provenance
+    pub provenance: Option<Provenance>,
+    message: String,
+}
+
+impl<M> Refresh<M> {
+    fn new(old: M, differences: Vec<String>, provenance: Option<Provenance>) -> Self {
+        let message = format!(
+            "Refreshing cache due to {changes}: {differences}",
+            changes = if differences.len() > 1 {
+                "changes"
+            } else {
+                "change"
+            },
+            differences = SentenceList::new(&differences)
+        );
+        Self {
+            old,
+            differences,
+            provenance,
+            message,
+        }
+    }
+
+    /// Writes the new metadata once the layer is refreshed
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if libcnb cannot write the metadata.
+    pub fn write_metadata<B, MAC, RAC>(
+        &self,
+        layer_ref: &LayerRef<B, MAC, RAC>,
+        metadata: &M,
+    ) -> libcnb::Result<(), B::Error>
+    where
+        B: libcnb::Buildpack,
+        M: Serialize,
+    {
+        layer_ref.write_metadata(WithProvenance {
+            metadata,
+            provenance: self.provenance.as_ref(),
+        })
+    }
+}
+
+/// Old metadata of a layer kept by [`DiffMigrateLayer`] with its [`Provenance`]
+///
+/// Displays as `Using cache (created 12 days ago by 5.0.1)`.
+pub struct Cached<M> {
+    pub data: M,
+    pub provenance: Provenance,
+    message: String,
+}
+
+impl<M> Cached<M> {
+    /// Describes the provenance relative to `now`, in seconds since the Unix epoch
+    pub fn new(data: M, provenance: Provenance, now: u64) -> Self {
+        let message = format!("Using cache ({})", provenance.summary(now));
+        Self {
+            data,
+            provenance,
+            message,
         }
     }
 }
@@ -315,13 +929,13 @@ impl<M> AsRef<str> for Meta<M> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::layer::clear_cache::CLEAR_CACHE_ENV_KEY;
+    use crate::layer::testing::{platform_env, temp_build_context, FakeBuildpack};
     use cache_diff::CacheDiff;
     use core::panic;
     use libcnb::data::layer_name;
-    use libcnb::generic::{GenericMetadata, GenericPlatform};
     use libcnb::layer::{EmptyLayerCause, InvalidMetadataAction, LayerState, RestoredLayerAction};
     use magic_migrate::{migrate_toml_chain, try_migrate_deserializer_chain, Migrate, TryMigrate};
-    use std::convert::Infallible;
     /// Struct for asserting the behavior of `CacheBuddy`
     #[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
     #[serde(deny_unknown_fields)]
@@ -339,27 +953,6 @@ mod tests {
     }
     migrate_toml_chain! {TestMetadata}
 
-    struct FakeBuildpack;
-    impl libcnb::Buildpack for FakeBuildpack {
-        type Platform = GenericPlatform;
-        type Metadata = GenericMetadata;
-        type Error = Infallible;
-
-        fn detect(
-            &self,
-            _context: libcnb::detect::DetectContext<Self>,
-        ) -> libcnb::Result<libcnb::detect::DetectResult, Self::Error> {
-            todo!()
-        }
-
-        fn build(
-            &self,
-            _context: BuildContext<Self>,
-        ) -> libcnb::Result<libcnb::build::BuildResult, Self::Error> {
-            todo!()
-        }
-    }
-
     #[test]
     fn test_migrate_layer_name_works_if_prior_dir_does_not_exist() {
         let temp = tempfile::tempdir().unwrap();
@@ -498,7 +1091,14 @@ mod tests {
         let LayerState::Restored { cause } = &result.state else {
             panic!("Expected restored layer")
         };
-        assert_eq!(cause.as_ref(), "Using cache");
+        assert_eq!(
+            cause.as_ref(),
+            format!(
+                "Using cache (created just now by {})",
+                context.buildpack_descriptor.buildpack.version
+            )
+        );
+        assert_eq!(cause.data().map(|data| data.value.as_str()), Some("hello"));
 
         // Third write, change the data
         let result = DiffMigrateLayer {
@@ -583,6 +1183,16 @@ mod tests {
             }
         }
     }
+    impl CacheDiff for PersonV1 {
+        fn diff(&self, _: &Self) -> Vec<String> {
+            vec![]
+        }
+    }
+    impl CacheDiff for PersonV2 {
+        fn diff(&self, _: &Self) -> Vec<String> {
+            vec![]
+        }
+    }
     #[derive(Debug, Eq, PartialEq)]
     struct NotRichard {
         name: String,
@@ -631,51 +1241,487 @@ mod tests {
         // Unable to produce this error at will: "Clearing cache due to invalid metadata serialization error: {error}"
     }
 
-    /// Takes in a directory and returns a minimal build context for use in testing caching behavior
-    ///
-    /// # Panics
+    /// Structs for asserting migrations can read the provenance
     ///
-    /// - If a context cannot be created
-    fn temp_build_context<B: libcnb::Buildpack>(
-        from_dir: impl AsRef<std::path::Path>,
-        buildpack_toml_string: &str,
-    ) -> libcnb::build::BuildContext<B> {
-        let base_dir = from_dir.as_ref().to_path_buf();
-        let layers_dir = base_dir.join("layers");
-        let app_dir = base_dir.join("app_dir");
-        let platform_dir = base_dir.join("platform_dir");
-        let buildpack_dir = base_dir.join("buildpack_dir");
-        for dir in [&app_dir, &layers_dir, &buildpack_dir, &platform_dir] {
-            std::fs::create_dir_all(dir).unwrap();
-        }
-
-        let target = libcnb::Target {
-            os: String::new(),
-            arch: String::new(),
-            arch_variant: None,
-            distro_name: String::new(),
-            distro_version: String::new(),
+    /// In a module since `migrate_toml_chain!` conflicts with `TryMigrate` being in scope
+    mod written_by {
+        use crate::layer::provenance::Provenance;
+        use cache_diff::CacheDiff;
+        use magic_migrate::{migrate_toml_chain, Migrate};
+
+        #[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+        #[serde(deny_unknown_fields)]
+        pub(super) struct WrittenByV1 {
+            pub(super) value: String,
+            pub(super) provenance: Option<Provenance>,
+        }
+        #[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+        #[serde(deny_unknown_fields)]
+        pub(super) struct WrittenByV2 {
+            pub(super) value: String,
+            pub(super) written_by: String,
+        }
+        impl From<WrittenByV1> for WrittenByV2 {
+            fn from(value: WrittenByV1) -> Self {
+                WrittenByV2 {
+                    value: value.value,
+                    written_by: value
+                        .provenance
+                        .map(|provenance| provenance.buildpack_version)
+                        .unwrap_or_default(),
+                }
+            }
+        }
+        impl CacheDiff for WrittenByV1 {
+            fn diff(&self, _: &Self) -> Vec<String> {
+                vec![]
+            }
+        }
+        impl CacheDiff for WrittenByV2 {
+            fn diff(&self, _: &Self) -> Vec<String> {
+                vec![]
+            }
+        }
+        migrate_toml_chain! {WrittenByV1, WrittenByV2}
+    }
+    use written_by::{WrittenByV1, WrittenByV2};
+
+    #[test]
+    fn test_migration_reads_provenance() {
+        let temp = tempfile::tempdir().unwrap();
+        let context = temp_build_context::<FakeBuildpack>(
+            temp.path(),
+            include_str!("../../../buildpacks/ruby/buildpack.toml"),
+        );
+        let layer = DiffMigrateLayer {
+            build: true,
+            launch: true,
+        };
+        layer
+            .clone()
+            .cached_layer(
+                layer_name!("testing"),
+                &context,
+                &WrittenByV1 {
+                    value: "hello".to_string(),
+                    provenance: None,
+                },
+            )
+            .unwrap();
+
+        let result = layer
+            .cached_layer(
+                layer_name!("testing"),
+                &context,
+                &WrittenByV2 {
+                    value: "hello".to_string(),
+                    written_by: "unknown".to_string(),
+                },
+            )
+            .unwrap();
+        let LayerState::Restored {
+            cause: Meta::Cached(cached),
+        } = &result.state
+        else {
+            panic!("Expected restored layer with provenance")
+        };
+        assert_eq!(
+            cached.data.written_by,
+            context.buildpack_descriptor.buildpack.version.to_string()
+        );
+        assert!(cached.provenance.reused_at.is_some());
+    }
+
+    #[test]
+    fn test_provenance_written() {
+        let temp = tempfile::tempdir().unwrap();
+        let context = temp_build_context::<FakeBuildpack>(
+            temp.path(),
+            include_str!("../../../buildpacks/ruby/buildpack.toml"),
+        );
+        let hello = TestMetadata {
+            value: "hello".to_string(),
         };
-        let platform =
-            <<B as libcnb::Buildpack>::Platform as libcnb::Platform>::from_path(&platform_dir)
+        let written = |name: &str| {
+            let contents =
+                fs_err::read_to_string(context.layers_dir.join(format!("{name}.toml"))).unwrap();
+            toml::from_str::<toml::Table>(&contents).unwrap()["metadata"]
+                .get(PROVENANCE_KEY)
+                .cloned()
+                .map(|value| value.try_into::<Provenance>().unwrap())
+        };
+        let cached_layer = |name: &str, launch: bool| {
+            DiffMigrateLayer {
+                build: true,
+                launch,
+            }
+            .cached_layer(name.parse::<LayerName>().unwrap(), &context, &hello)
+            .unwrap()
+        };
+
+        // Launch layers are exported with the image, reuse must not change their metadata
+        cached_layer("launch_layer", true);
+        let created = written("launch_layer").unwrap();
+        cached_layer("launch_layer", true);
+        assert_eq!(written("launch_layer"), Some(created));
+
+        cached_layer("build_layer", false);
+        cached_layer("build_layer", false);
+        assert!(written("build_layer").unwrap().reused_at.is_some());
+
+        // Layers from before provenance was recorded are not stamped with the current build
+        fs_err::create_dir_all(context.layers_dir.join("old_layer")).unwrap();
+        fs_err::write(
+            context.layers_dir.join("old_layer.toml"),
+            "[types]\nbuild = true\nlaunch = true\ncache = true\n\n[metadata]\nvalue = \"hello\"\n",
+        )
+        .unwrap();
+        let result = cached_layer("old_layer", true);
+        assert!(matches!(
+            result.state,
+            LayerState::Restored {
+                cause: Meta::Data(_)
+            }
+        ));
+        assert_eq!(written("old_layer"), None);
+    }
+
+    /// Struct for asserting the behavior of `cached_layer_refresh`
+    #[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+    #[serde(deny_unknown_fields)]
+    struct RefreshMetadata {
+        minor: String,
+        patch: String,
+    }
+    impl CacheDiff for RefreshMetadata {
+        fn diff(&self, old: &Self) -> Vec<String> {
+            if self.minor == old.minor {
+                vec![]
+            } else {
+                vec![format!("minor ({} to {})", old.minor, self.minor)]
+            }
+        }
+    }
+    impl RefreshDiff for RefreshMetadata {
+        fn refresh_diff(&self, old: &Self) -> Vec<String> {
+            if self.patch == old.patch {
+                vec![]
+            } else {
+                vec![format!("patch ({} to {})", old.patch, self.patch)]
+            }
+        }
+    }
+    migrate_toml_chain! {RefreshMetadata}
+
+    #[test]
+    fn test_cached_layer_refresh() {
+        let temp = tempfile::tempdir().unwrap();
+        let context = temp_build_context::<FakeBuildpack>(
+            temp.path(),
+            include_str!("../../../buildpacks/ruby/buildpack.toml"),
+        );
+        let layer = DiffMigrateLayer {
+            build: true,
+            launch: true,
+        };
+        let metadata = |minor: &str, patch: &str| RefreshMetadata {
+            minor: minor.to_string(),
+            patch: patch.to_string(),
+        };
+        layer
+            .clone()
+            .cached_layer_refresh(layer_name!("testing"), &context, &metadata("3.3", "0"))
+            .unwrap();
+        fs_err::write(context.layers_dir.join("testing").join("ruby"), "compiled").unwrap();
+
+        // Only refreshable fields changed, the metadata is not written until the caller is done
+        for _ in 0..2 {
+            let result = layer
+                .clone()
+                .cached_layer_refresh(layer_name!("testing"), &context, &metadata("3.3", "1"))
                 .unwrap();
-        let buildpack_descriptor: libcnb::data::buildpack::ComponentBuildpackDescriptor<
-            <B as libcnb::Buildpack>::Metadata,
-        > = toml::from_str(buildpack_toml_string).unwrap();
-        let buildpack_plan = libcnb::data::buildpack_plan::BuildpackPlan {
-            entries: Vec::<libcnb::data::buildpack_plan::Entry>::new(),
+            let LayerState::Restored {
+                cause: Meta::Refresh(refresh),
+            } = &result.state
+            else {
+                panic!("Expected layer to be kept and refreshed")
+            };
+            assert_eq!(refresh.old.patch, "0");
+            assert_eq!(refresh.differences, vec!["patch (0 to 1)"]);
+            assert_eq!(
+                refresh.message,
+                "Refreshing cache due to change: patch (0 to 1)"
+            );
+            assert!(result.path().join("ruby").exists());
+        }
+
+        let result = layer
+            .clone()
+            .cached_layer_refresh(layer_name!("testing"), &context, &metadata("3.3", "1"))
+            .unwrap();
+        let LayerState::Restored {
+            cause: Meta::Refresh(refresh),
+        } = &result.state
+        else {
+            panic!("Expected layer to be kept and refreshed")
+        };
+        refresh
+            .write_metadata(&result, &metadata("3.3", "1"))
+            .unwrap();
+        let result = layer
+            .clone()
+            .cached_layer_refresh(layer_name!("testing"), &context, &metadata("3.3", "1"))
+            .unwrap();
+        assert!(matches!(
+            result.state,
+            LayerState::Restored {
+                cause: Meta::Cached(_)
+            }
+        ));
+
+        // Cache keys still clear the layer
+        let result = layer
+            .cached_layer_refresh(layer_name!("testing"), &context, &metadata("3.4", "0"))
+            .unwrap();
+        assert!(matches!(
+            result.state,
+            LayerState::Empty {
+                cause: EmptyLayerCause::RestoredLayerAction { .. }
+            }
+        ));
+    }
+
+    #[test]
+    fn test_clear_cache() {
+        let temp = tempfile::tempdir().unwrap();
+        let metadata = TestMetadata {
+            value: "hello".to_string(),
         };
-        let store = None;
-
-        libcnb::build::BuildContext {
-            layers_dir,
-            app_dir,
-            buildpack_dir,
-            target,
-            platform,
-            buildpack_plan,
-            buildpack_descriptor,
-            store,
+        let layer = DiffMigrateLayer {
+            build: true,
+            launch: true,
+        };
+        let context = temp_build_context::<FakeBuildpack>(
+            temp.path(),
+            include_str!("../../../buildpacks/ruby/buildpack.toml"),
+        );
+        for name in [layer_name!("gems"), layer_name!("bundler")] {
+            layer
+                .clone()
+                .cached_layer(name, &context, &metadata)
+                .unwrap();
         }
+
+        platform_env(temp.path(), CLEAR_CACHE_ENV_KEY, "gems");
+        let context = temp_build_context::<FakeBuildpack>(
+            temp.path(),
+            include_str!("../../../buildpacks/ruby/buildpack.toml"),
+        );
+        let result = layer
+            .clone()
+            .cached_layer(layer_name!("gems"), &context, &metadata)
+            .unwrap();
+        let LayerState::Empty {
+            cause: EmptyLayerCause::RestoredLayerAction { cause },
+        } = &result.state
+        else {
+            panic!("Expected cleared layer")
+        };
+        assert_eq!(
+            cause.as_ref(),
+            "Clearing cache due to HEROKU_CLEAR_CACHE=gems (includes `gems`)"
+        );
+        let result = layer
+            .clone()
+            .cached_layer(layer_name!("bundler"), &context, &metadata)
+            .unwrap();
+        assert!(matches!(result.state, LayerState::Restored { .. }));
+
+        platform_env(temp.path(), CLEAR_CACHE_ENV_KEY, "all");
+        let context = temp_build_context::<FakeBuildpack>(
+            temp.path(),
+            include_str!("../../../buildpacks/ruby/buildpack.toml"),
+        );
+        let result = layer
+            .cached_layer(layer_name!("bundler"), &context, &metadata)
+            .unwrap();
+        let LayerState::Empty {
+            cause: EmptyLayerCause::RestoredLayerAction { cause },
+        } = &result.state
+        else {
+            panic!("Expected cleared layer")
+        };
+        assert_eq!(
+            cause.as_ref(),
+            "Clearing cache due to HEROKU_CLEAR_CACHE=all"
+        );
+    }
+
+    #[test]
+    fn test_explain() {
+        let temp = tempfile::tempdir().unwrap();
+        platform_env(temp.path(), CACHE_EXPLAIN_ENV_KEY, "1");
+        let context = temp_build_context::<FakeBuildpack>(
+            temp.path(),
+            include_str!("../../../buildpacks/ruby/buildpack.toml"),
+        );
+        let hello = TestMetadata {
+            value: "hello".to_string(),
+        };
+        let world = TestMetadata {
+            value: "world".to_string(),
+        };
+
+        let explanation = explain(&layer_name!("testing"), &context, &hello)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            explanation.to_string(),
+            "Cache explain for layer `testing`\nNew metadata: {value = \"hello\"}\nNo cached layer, action: create a new layer"
+        );
+
+        DiffMigrateLayer {
+            build: true,
+            launch: true,
+        }
+        .cached_layer(layer_name!("testing"), &context, &hello)
+        .unwrap();
+
+        let explanation = explain(&layer_name!("testing"), &context, &world)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            explanation.lines,
+            vec![
+                "New metadata: {value = \"world\"}",
+                "Old metadata: {value = \"hello\"}",
+                &format!(
+                    "Provenance: created just now by {}",
+                    context.buildpack_descriptor.buildpack.version
+                ),
+                "Field `value`: \"hello\" to \"world\" (different)",
+                "Migration: not needed, old metadata is a valid `TestMetadata`",
+                "CacheDiff: value (hello to world)",
+                "Action: RestoredLayerAction::DeleteLayer",
+            ]
+        );
+
+        DiffMigrateLayer {
+            build: true,
+            launch: true,
+        }
+        .cached_layer(
+            layer_name!("person"),
+            &context,
+            &PersonV1 {
+                name: "Schneems".to_string(),
+            },
+        )
+        .unwrap();
+        let explanation = explain(
+            &layer_name!("person"),
+            &context,
+            &PersonV2 {
+                name: "Schneems".to_string(),
+                updated_at: "unknown".to_string(),
+            },
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            explanation.lines[3..],
+            [
+                "Field `name`: \"Schneems\" (same)",
+                "Field `updated_at`: \"unknown\" (added)",
+                "Migration chain (oldest first): `PersonV1` (matches), `PersonV2` (no match)",
+                "Migration: succeeded, action: InvalidMetadataAction::ReplaceMetadata",
+                "CacheDiff: no differences",
+                "Action: RestoredLayerAction::KeepLayer",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dry_run_keeps_layer() {
+        let temp = tempfile::tempdir().unwrap();
+        let context = temp_build_context::<FakeBuildpack>(
+            temp.path(),
+            include_str!("../../../buildpacks/ruby/buildpack.toml"),
+        );
+        let hello = TestMetadata {
+            value: "hello".to_string(),
+        };
+        let world = TestMetadata {
+            value: "world".to_string(),
+        };
+        DiffMigrateLayer {
+            build: false,
+            launch: false,
+        }
+        .cached_layer(layer_name!("testing"), &context, &hello)
+        .unwrap();
+
+        platform_env(temp.path(), CACHE_EXPLAIN_ENV_KEY, "dry-run");
+        let dry_run = temp_build_context::<FakeBuildpack>(
+            temp.path(),
+            include_str!("../../../buildpacks/ruby/buildpack.toml"),
+        );
+        let explanation = explain(&layer_name!("testing"), &dry_run, &world)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            explanation.lines.last().map(String::as_str),
+            Some(
+                "Dry run: kept when the layer is cache-only, build and launch layers are still cleared"
+            )
+        );
+
+        let result = DiffMigrateLayer {
+            build: false,
+            launch: false,
+        }
+        .cached_layer(layer_name!("testing"), &dry_run, &world)
+        .unwrap();
+        let LayerState::Restored { cause } = &result.state else {
+            panic!("Expected restored layer")
+        };
+        assert_eq!(
+            cause.as_ref(),
+            "Dry run, keeping cache that would be cleared due to change: value (hello to world)"
+        );
+
+        // Launch layers are cleared, a stale layer would be exported in the image
+        DiffMigrateLayer {
+            build: false,
+            launch: true,
+        }
+        .cached_layer(layer_name!("launch_testing"), &context, &hello)
+        .unwrap();
+        let result = DiffMigrateLayer {
+            build: false,
+            launch: true,
+        }
+        .cached_layer(layer_name!("launch_testing"), &dry_run, &world)
+        .unwrap();
+        assert!(matches!(
+            result.state,
+            LayerState::Empty {
+                cause: EmptyLayerCause::RestoredLayerAction { .. }
+            }
+        ));
+
+        // Old metadata is preserved, so a build without dry run still clears the layer
+        let result = DiffMigrateLayer {
+            build: false,
+            launch: false,
+        }
+        .cached_layer(layer_name!("testing"), &context, &world)
+        .unwrap();
+        assert!(matches!(
+            result.state,
+            LayerState::Empty {
+                cause: EmptyLayerCause::RestoredLayerAction { .. }
+            }
+        ));
     }
 }
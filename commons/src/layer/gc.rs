@@ -0,0 +1,149 @@
+//! Remove layers that the current buildpack version no longer uses
+//!
+//! Layers are restored from the prior build even if nothing in the current build
+//! asks for them. When a feature is removed, or an [`crate::cache::AppCache`] path is
+//! no longer cached, its layer stays on disk (and in the cache image) forever.
+//!
+//! Call [`remove_unlisted_layers`] at the end of a build with the name of every layer the
+//! buildpack knows about. Anything else in the layers directory is deleted:
+//!
+//! ```no_run
+//! use commons::layer::gc::remove_unlisted_layers;
+//! use libcnb::data::layer_name;
+//! # let layers_dir = std::path::PathBuf::new();
+//!
+//! let removed = remove_unlisted_layers(&layers_dir, &[layer_name!("gems")]).unwrap();
+//! for layer in removed {
+//!     println!("Removed {layer}");
+//! }
+//! ```
+use crate::layer::provenance::dir_size;
+use byte_unit::{Byte, UnitType};
+use libcnb::data::layer::LayerName;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::path::Path;
+
+/// Names of files in the layers directory that are written by libcnb or the buildpack and do not
+/// belong to a layer, such as `launch.toml` and `launch.sbom.cdx.json`
+const RESERVED_NAMES: [&str; 3] = ["launch", "build", "store"];
+
+/// A layer deleted by [`remove_unlisted_layers`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemovedLayer {
+    pub name: String,
+    pub bytes: u64,
+}
+
+impl Display for RemovedLayer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let size = Byte::from_u64(self.bytes).get_appropriate_unit(UnitType::Binary);
+        write!(f, "`{}` ({size:.0})", self.name)
+    }
+}
+
+/// Deletes every layer directory and layer file not named in `keep`
+///
+/// A layer consists of a `<name>` directory, a `<name>.toml` file, and optional
+/// `<name>.sbom.*` files. All of them are removed, even if only some of them exist.
+/// The `launch`, `build`, and `store` TOML and SBOM files are never touched.
+///
+/// Returns the removed layers sorted by name.
+///
+/// # Errors
+///
+/// - If the layers directory cannot be read
+/// - If a file or directory cannot be removed
+pub fn remove_unlisted_layers(
+    layers_dir: &Path,
+    keep: &[LayerName],
+) -> Result<Vec<RemovedLayer>, std::io::Error> {
+    let mut removed = BTreeMap::<String, u64>::new();
+    for entry in fs_err::read_dir(layers_dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let file_name = entry.file_name().to_string_lossy().to_string();
+
+        let name = if entry.file_type()?.is_dir() {
+            file_name
+        } else if let Some((name, _)) = file_name.split_once(".sbom.") {
+            name.to_string()
+        } else if let Some(name) = file_name.strip_suffix(".toml") {
+            name.to_string()
+        } else {
+            continue;
+        };
+
+        if RESERVED_NAMES.contains(&name.as_str())
+            || keep.iter().any(|layer| layer.as_str() == name)
+        {
+            continue;
+        }
+
+        let bytes = if path.is_dir() {
+            let bytes = dir_size(&path);
+            fs_err::remove_dir_all(&path)?;
+            bytes
+        } else {
+            let bytes = entry.metadata()?.len();
+            fs_err::remove_file(&path)?;
+            bytes
+        };
+        *removed.entry(name).or_default() += bytes;
+    }
+
+    Ok(removed
+        .into_iter()
+        .map(|(name, bytes)| RemovedLayer { name, bytes })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libcnb::data::layer_name;
+
+    #[test]
+    fn test_remove_unlisted_layers() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let layers_dir = tmpdir.path();
+        for name in ["gems", "venv"] {
+            fs_err::create_dir_all(layers_dir.join(name)).unwrap();
+            fs_err::write(layers_dir.join(format!("{name}.toml")), "").unwrap();
+        }
+        fs_err::write(layers_dir.join("venv").join("file"), "12345").unwrap();
+        fs_err::write(layers_dir.join("venv.sbom.cdx.json"), "{}").unwrap();
+        fs_err::write(layers_dir.join("orphan.toml"), "").unwrap();
+        for reserved in RESERVED_NAMES {
+            fs_err::write(layers_dir.join(format!("{reserved}.toml")), "").unwrap();
+        }
+        fs_err::write(layers_dir.join("launch.sbom.cdx.json"), "{}").unwrap();
+
+        let removed = remove_unlisted_layers(layers_dir, &[layer_name!("gems")]).unwrap();
+
+        assert_eq!(
+            vec![
+                RemovedLayer {
+                    name: String::from("orphan"),
+                    bytes: 0
+                },
+                RemovedLayer {
+                    name: String::from("venv"),
+                    bytes: 7
+                }
+            ],
+            removed
+        );
+        assert_eq!("`venv` (7 B)", removed[1].to_string());
+        assert!(layers_dir.join("gems").exists());
+        assert!(layers_dir.join("gems.toml").exists());
+        assert!(!layers_dir.join("venv").exists());
+        assert!(!layers_dir.join("venv.toml").exists());
+        assert!(!layers_dir.join("venv.sbom.cdx.json").exists());
+        assert!(!layers_dir.join("orphan.toml").exists());
+        for reserved in RESERVED_NAMES {
+            assert!(layers_dir.join(format!("{reserved}.toml")).exists());
+        }
+        assert!(layers_dir.join("launch.sbom.cdx.json").exists());
+    }
+}
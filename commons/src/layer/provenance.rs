@@ -0,0 +1,187 @@
+//! Where a cached layer came from
+//!
+//! [`crate::layer::diff_migrate::DiffMigrateLayer`] stores a [`Provenance`] under the
+//! [`PROVENANCE_KEY`] of the layer metadata, next to the buildpack's own metadata. It records the
+//! buildpack version that created the layer and when, so a restored layer can be described as
+//! "Using cache (created 12 days ago by 5.0.1)".
+//!
+//! Walking a large layer is slow, so the size is only measured when `HEROKU_CACHE_EXPLAIN` is set,
+//! see [`Provenance::measured`]. Then the description reads
+//! "Using cache (created 12 days ago by 5.0.1, 84 MiB)".
+//!
+//! Layers written before provenance was recorded have none until they're recreated. Launch layers
+//! keep the provenance from when they were created, their metadata is exported with the image and
+//! a reuse timestamp or size would make otherwise identical images differ.
+
+use byte_unit::{Byte, UnitType};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Key of the provenance table in the layer metadata
+pub const PROVENANCE_KEY: &str = "provenance";
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Provenance {
+    /// ID of the buildpack that created the layer
+    pub buildpack_id: String,
+    /// Version of the buildpack that created the layer
+    pub buildpack_version: String,
+    /// Seconds since the Unix epoch when the layer was created
+    pub created_at: u64,
+    /// Seconds since the Unix epoch when the layer was last restored and kept, not written for
+    /// launch layers
+    pub reused_at: Option<u64>,
+    /// Size of the layer on disk when it was last restored, `None` when not measured
+    pub bytes: Option<u64>,
+}
+
+impl Provenance {
+    /// Provenance for a layer created now by the buildpack in the context
+    #[must_use]
+    pub fn new<B: libcnb::Buildpack>(context: &libcnb::build::BuildContext<B>, now: u64) -> Self {
+        Self {
+            buildpack_id: context.buildpack_descriptor.buildpack.id.to_string(),
+            buildpack_version: context.buildpack_descriptor.buildpack.version.to_string(),
+            created_at: now,
+            reused_at: None,
+            bytes: None,
+        }
+    }
+
+    /// Records that the layer was kept, a size from an earlier build is dropped
+    #[must_use]
+    pub fn reused(self, now: u64) -> Self {
+        Self {
+            reused_at: Some(now),
+            bytes: None,
+            ..self
+        }
+    }
+
+    /// Records the size of the layer at `path`, walks every file in the layer
+    #[must_use]
+    pub fn measured(self, path: &Path) -> Self {
+        Self {
+            bytes: Some(dir_size(path)),
+            ..self
+        }
+    }
+
+    /// Removes and parses the provenance from layer metadata, `None` when missing or invalid
+    #[must_use]
+    pub fn remove_from(metadata: &mut toml::Table) -> Option<Self> {
+        metadata
+            .remove(PROVENANCE_KEY)
+            .and_then(|value| value.try_into().ok())
+    }
+
+    /// A short description such as `created 12 days ago by 5.0.1, 84 MiB`, without the size
+    /// when it wasn't measured
+    #[must_use]
+    pub fn summary(&self, now: u64) -> String {
+        let summary = format!(
+            "created {ago} by {version}",
+            ago = ago(now.saturating_sub(self.created_at)),
+            version = self.buildpack_version,
+        );
+        match self.bytes {
+            Some(bytes) => format!(
+                "{summary}, {size:.0}",
+                size = Byte::from_u64(bytes).get_appropriate_unit(UnitType::Binary)
+            ),
+            None => summary,
+        }
+    }
+}
+
+/// Seconds since the Unix epoch
+#[must_use]
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+fn ago(seconds: u64) -> String {
+    let (count, unit) = match seconds {
+        0..60 => return String::from("just now"),
+        60..3_600 => (seconds / 60, "minute"),
+        3_600..86_400 => (seconds / 3_600, "hour"),
+        _ => (seconds / 86_400, "day"),
+    };
+    format!(
+        "{count} {unit}{s} ago",
+        s = if count == 1 { "" } else { "s" }
+    )
+}
+
+/// Total size of the files in a directory, unreadable entries are skipped
+pub(crate) fn dir_size(path: &Path) -> u64 {
+    walkdir::WalkDir::new(path)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(std::fs::Metadata::is_file)
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        let provenance = Provenance {
+            buildpack_id: String::from("heroku/ruby"),
+            buildpack_version: String::from("5.0.1"),
+            created_at: 1_000_000,
+            reused_at: None,
+            bytes: Some(84 * 1024 * 1024),
+        };
+        assert_eq!(
+            provenance.summary(1_000_000 + 12 * 86_400 + 5),
+            "created 12 days ago by 5.0.1, 84 MiB"
+        );
+        assert_eq!(
+            provenance.summary(1_000_000 + 3_600),
+            "created 1 hour ago by 5.0.1, 84 MiB"
+        );
+        assert_eq!(
+            provenance.summary(1_000_000),
+            "created just now by 5.0.1, 84 MiB"
+        );
+        assert_eq!(
+            Provenance {
+                bytes: None,
+                ..provenance
+            }
+            .summary(1_000_000),
+            "created just now by 5.0.1"
+        );
+    }
+
+    #[test]
+    fn test_reused() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        fs_err::write(tmpdir.path().join("a"), "12345").unwrap();
+        fs_err::create_dir_all(tmpdir.path().join("nested")).unwrap();
+        fs_err::write(tmpdir.path().join("nested").join("b"), "123").unwrap();
+
+        let provenance = Provenance {
+            buildpack_id: String::from("heroku/ruby"),
+            buildpack_version: String::from("5.0.1"),
+            created_at: 1,
+            reused_at: None,
+            bytes: Some(1),
+        }
+        .reused(2);
+        assert_eq!(provenance.reused_at, Some(2));
+        assert_eq!(provenance.bytes, None);
+        assert_eq!(provenance.created_at, 1);
+        assert_eq!(provenance.measured(tmpdir.path()).bytes, Some(8));
+    }
+}
@@ -0,0 +1,413 @@
+//! Helpers for testing layer caching behavior
+//!
+//! ## Metadata fixtures
+//!
+//! Every change to a layer's metadata struct must keep old metadata readable, or the
+//! cache is cleared on the next deploy. Record the TOML that each released version of the
+//! buildpack wrote as a fixture, and check it against the current struct with
+//! [`MetadataFixtures`]:
+//!
+//! ```ignore
+//! use commons::layer::testing::MetadataFixtures;
+//!
+//! MetadataFixtures::<Metadata>::new()
+//!     .fixture("v1", r#"stack = "heroku-22""#)
+//!     .fixture("v2", r#"distro_name = "ubuntu""#)
+//!     .assert_all();
+//! ```
+//!
+//! When a field is added to the latest struct, add a new struct to the migration chain
+//! and a fixture for it. Don't edit old fixtures, they are what's on disk in the
+//! wild.
+
+use crate::layer::diff_migrate::migration_chain;
+use crate::layer::provenance::{Provenance, PROVENANCE_KEY};
+use cache_diff::CacheDiff;
+use libcnb::build::BuildContext;
+use libcnb::generic::{GenericMetadata, GenericPlatform};
+use magic_migrate::TryMigrate;
+use serde::Serialize;
+use std::any::type_name;
+use std::collections::BTreeSet;
+use std::convert::Infallible;
+use std::marker::PhantomData;
+
+/// Buildpack for creating a [`BuildContext`] in tests
+pub struct FakeBuildpack;
+impl libcnb::Buildpack for FakeBuildpack {
+    type Platform = GenericPlatform;
+    type Metadata = GenericMetadata;
+    type Error = Infallible;
+
+    fn detect(
+        &self,
+        _context: libcnb::detect::DetectContext<Self>,
+    ) -> libcnb::Result<libcnb::detect::DetectResult, Self::Error> {
+        libcnb::detect::DetectResultBuilder::fail().build()
+    }
+
+    fn build(
+        &self,
+        _context: BuildContext<Self>,
+    ) -> libcnb::Result<libcnb::build::BuildResult, Self::Error> {
+        libcnb::build::BuildResultBuilder::new().build()
+    }
+}
+
+/// Takes in a directory and returns a minimal build context for use in testing caching behavior
+///
+/// # Panics
+///
+/// - If a context cannot be created
+#[must_use]
+pub fn temp_build_context<B: libcnb::Buildpack>(
+    from_dir: impl AsRef<std::path::Path>,
+    buildpack_toml_string: &str,
+) -> libcnb::build::BuildContext<B> {
+    let base_dir = from_dir.as_ref().to_path_buf();
+    let layers_dir = base_dir.join("layers");
+    let app_dir = base_dir.join("app_dir");
+    let platform_dir = base_dir.join("platform_dir");
+    let buildpack_dir = base_dir.join("buildpack_dir");
+    for dir in [&app_dir, &layers_dir, &buildpack_dir, &platform_dir] {
+        std::fs::create_dir_all(dir).expect("create context dirs");
+    }
+
+    let target = libcnb::Target {
+        os: String::new(),
+        arch: String::new(),
+        arch_variant: None,
+        distro_name: String::new(),
+        distro_version: String::new(),
+    };
+    let platform =
+        <<B as libcnb::Buildpack>::Platform as libcnb::Platform>::from_path(&platform_dir)
+            .expect("read platform dir");
+    let buildpack_descriptor: libcnb::data::buildpack::ComponentBuildpackDescriptor<
+        <B as libcnb::Buildpack>::Metadata,
+    > = toml::from_str(buildpack_toml_string).expect("parse buildpack.toml");
+    let buildpack_plan = libcnb::data::buildpack_plan::BuildpackPlan {
+        entries: Vec::<libcnb::data::buildpack_plan::Entry>::new(),
+    };
+    let store = None;
+
+    libcnb::build::BuildContext {
+        layers_dir,
+        app_dir,
+        buildpack_dir,
+        target,
+        platform,
+        buildpack_plan,
+        buildpack_descriptor,
+        store,
+    }
+}
+
+/// Sets an env var in the platform dir used by `temp_build_context`
+///
+/// # Panics
+///
+/// - If the file cannot be written
+pub fn platform_env(from_dir: &std::path::Path, key: &str, value: &str) {
+    let env_dir = from_dir.join("platform_dir").join("env");
+    std::fs::create_dir_all(&env_dir).expect("create platform env dir");
+    std::fs::write(env_dir.join(key), value).expect("write platform env");
+}
+
+/// Historical metadata TOML checked against the current end of a migration chain
+///
+/// Fixtures are migrated the same way as a restored layer in
+/// [`crate::layer::diff_migrate::DiffMigrateLayer`]: a `provenance` key is ignored,
+/// metadata that deserializes as `M` is used as is, everything else goes through
+/// [`TryMigrate::try_from_str_migrations`].
+#[derive(Debug)]
+pub struct MetadataFixtures<M> {
+    fixtures: Vec<(String, String)>,
+    metadata: PhantomData<M>,
+}
+
+impl<M> Default for MetadataFixtures<M> {
+    fn default() -> Self {
+        Self {
+            fixtures: Vec::new(),
+            metadata: PhantomData,
+        }
+    }
+}
+
+impl<M> MetadataFixtures<M>
+where
+    M: TryMigrate + CacheDiff + Serialize,
+{
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds TOML written by a released version of the buildpack, oldest first
+    #[must_use]
+    pub fn fixture(mut self, name: impl Into<String>, toml: impl Into<String>) -> Self {
+        self.fixtures.push((name.into(), toml.into()));
+        self
+    }
+
+    /// Runs [`Self::assert_migrates`] and [`Self::assert_no_unmigrated_fields`]
+    ///
+    /// # Panics
+    ///
+    /// - If either assertion fails
+    pub fn assert_all(&self) {
+        let _ = self.assert_migrates();
+        self.assert_no_unmigrated_fields();
+    }
+
+    /// Asserts every fixture migrates to `M` and returns the results in fixture order
+    ///
+    /// # Panics
+    ///
+    /// - If there are no fixtures
+    /// - If a fixture is not valid TOML, no struct in the chain matches it, or the migration errors
+    #[must_use]
+    pub fn assert_migrates(&self) -> Vec<M> {
+        assert!(
+            !self.fixtures.is_empty(),
+            "No fixtures for `{}`",
+            type_name::<M>()
+        );
+        self.fixtures
+            .iter()
+            .map(|(name, toml)| {
+                migrate::<M>(toml).unwrap_or_else(|error| {
+                    panic!(
+                        "Fixture `{name}` did not migrate to `{type_name}`: {error}\n\
+                         Migration chain (oldest first): {chain}",
+                        type_name = type_name::<M>(),
+                        chain = migration_chain::<M>(toml).join(", ")
+                    )
+                })
+            })
+            .collect()
+    }
+
+    /// Asserts `now.diff(old)` renders `expected`, with ANSI colors removed, where `old` is
+    /// the named fixture after migration
+    ///
+    /// # Panics
+    ///
+    /// - If the fixture does not exist or does not migrate
+    /// - If the differences do not match
+    pub fn assert_diff(&self, name: &str, now: &M, expected: &[&str]) {
+        let (_, toml) = self
+            .fixtures
+            .iter()
+            .find(|(fixture, _)| fixture == name)
+            .unwrap_or_else(|| panic!("No fixture named `{name}`"));
+        let old = migrate::<M>(toml)
+            .unwrap_or_else(|error| panic!("Fixture `{name}` did not migrate: {error}"));
+
+        assert_eq!(
+            expected,
+            now.diff(&old)
+                .iter()
+                .map(bullet_stream::strip_ansi)
+                .collect::<Vec<_>>(),
+            "Unexpected differences between fixture `{name}` and the current metadata"
+        );
+    }
+
+    /// Asserts the fields of `M` are all present in the fixtures that deserialize as `M`
+    ///
+    /// A field added to the latest struct with `#[serde(default)]` lets old metadata
+    /// deserialize without a migration, so `CacheDiff` compares against a value that was
+    /// never on disk. That fixture is reported with the fields it's missing. At least one
+    /// fixture must match the current struct so its shape is pinned.
+    ///
+    /// Fields that don't serialize, such as an `Option` that is `None`, can't be checked.
+    ///
+    /// # Panics
+    ///
+    /// - If a fixture deserializes as `M` but is missing, or has extra, fields
+    /// - If no fixture deserializes as `M`
+    pub fn assert_no_unmigrated_fields(&self) {
+        let mut matched = false;
+        for (name, toml) in &self.fixtures {
+            let mut table = toml::from_str::<toml::Table>(toml)
+                .unwrap_or_else(|error| panic!("Fixture `{name}` is not valid TOML: {error}"));
+            table.remove(PROVENANCE_KEY);
+            let Ok(metadata) = toml::Value::Table(table.clone()).try_into::<M>() else {
+                continue;
+            };
+            matched = true;
+
+            let written = match toml::Value::try_from(&metadata) {
+                Ok(toml::Value::Table(written)) => written,
+                other => panic!("Could not serialize `{}`: {other:?}", type_name::<M>()),
+            };
+            let on_disk = key_paths(&table, "");
+            let written = key_paths(&written, "");
+            let missing = written.difference(&on_disk).collect::<Vec<_>>();
+            let extra = on_disk.difference(&written).collect::<Vec<_>>();
+            assert!(
+                missing.is_empty() && extra.is_empty(),
+                "Fixture `{name}` deserializes as `{type_name}` without a migration, \
+                 but its fields differ (missing: {missing:?}, extra: {extra:?}). \
+                 Add a new struct to the migration chain instead of changing `{type_name}`",
+                type_name = type_name::<M>()
+            );
+        }
+        assert!(
+            matched,
+            "No fixture deserializes as `{}`, add one for the current metadata",
+            type_name::<M>()
+        );
+    }
+}
+
+/// Migrates in the same order as a restored `DiffMigrateLayer`
+fn migrate<M: TryMigrate>(toml: &str) -> Result<M, String> {
+    let mut table = toml::from_str::<toml::Table>(toml).map_err(|error| error.to_string())?;
+    let provenance = Provenance::remove_from(&mut table);
+    if let Ok(metadata) = toml::Value::Table(table.clone()).try_into::<M>() {
+        return Ok(metadata);
+    }
+    if provenance.is_some() {
+        if let Some(Ok(metadata)) = M::try_from_str_migrations(toml) {
+            return Ok(metadata);
+        }
+    }
+    let stripped = toml::to_string(&table).map_err(|error| error.to_string())?;
+    match M::try_from_str_migrations(&stripped) {
+        Some(Ok(metadata)) => Ok(metadata),
+        Some(Err(error)) => Err(error.to_string()),
+        None => Err(String::from("no struct in the migration chain matches")),
+    }
+}
+
+/// Dotted paths of every key, including keys of nested tables
+fn key_paths(table: &toml::Table, prefix: &str) -> BTreeSet<String> {
+    let mut paths = BTreeSet::new();
+    for (key, value) in table {
+        let path = format!("{prefix}{key}");
+        if let toml::Value::Table(inner) = value {
+            paths.extend(key_paths(inner, &format!("{path}.")));
+        }
+        paths.insert(path);
+    }
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use person::PersonV2;
+    use unmigrated::Unmigrated;
+
+    mod person {
+        use cache_diff::CacheDiff;
+        use magic_migrate::{migrate_toml_chain, Migrate};
+        use serde::{Deserialize, Serialize};
+
+        #[derive(Debug, Deserialize, Serialize)]
+        #[serde(deny_unknown_fields)]
+        pub(super) struct PersonV1 {
+            pub(super) name: String,
+        }
+
+        #[derive(Debug, Deserialize, Serialize, CacheDiff)]
+        #[serde(deny_unknown_fields)]
+        pub(super) struct PersonV2 {
+            pub(super) name: String,
+            #[cache_diff(rename = "Job title")]
+            pub(super) title: String,
+        }
+
+        impl From<PersonV1> for PersonV2 {
+            fn from(value: PersonV1) -> Self {
+                Self {
+                    name: value.name,
+                    title: String::from("Unknown"),
+                }
+            }
+        }
+        migrate_toml_chain! {PersonV1, PersonV2}
+    }
+
+    mod unmigrated {
+        use cache_diff::CacheDiff;
+        use magic_migrate::Migrate;
+        use serde::{Deserialize, Serialize};
+
+        /// `title` was added without a new struct in the chain
+        #[derive(Debug, Deserialize, Serialize, CacheDiff)]
+        pub(super) struct Unmigrated {
+            name: String,
+            #[serde(default)]
+            title: String,
+        }
+
+        impl Migrate for Unmigrated {
+            type From = Self;
+
+            fn deserializer<'de>(input: &str) -> impl serde::de::Deserializer<'de> {
+                toml::Deserializer::new(input)
+            }
+        }
+    }
+
+    fn fixtures() -> MetadataFixtures<PersonV2> {
+        MetadataFixtures::<PersonV2>::new()
+            .fixture("v1", r#"name = "schneems""#)
+            .fixture(
+                "v2",
+                r#"
+                name = "schneems"
+                title = "Developer"
+
+                [provenance]
+                buildpack_id = "heroku/ruby"
+                buildpack_version = "5.0.1"
+                created_at = 0
+                bytes = 0
+                "#,
+            )
+    }
+
+    #[test]
+    fn test_metadata_fixtures() {
+        let fixtures = fixtures();
+        fixtures.assert_all();
+
+        let migrated = fixtures.assert_migrates();
+        assert_eq!("Unknown", migrated[0].title);
+
+        let now = PersonV2 {
+            name: String::from("schneems"),
+            title: String::from("Developer"),
+        };
+        fixtures.assert_diff("v1", &now, &["Job title (`Unknown` to `Developer`)"]);
+        fixtures.assert_diff("v2", &now, &[]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Fixture `v1` did not migrate")]
+    fn test_metadata_fixtures_unknown_shape() {
+        MetadataFixtures::<PersonV2>::new()
+            .fixture("v1", r#"full_name = "schneems""#)
+            .assert_all();
+    }
+
+    #[test]
+    #[should_panic(expected = r#"missing: ["title"]"#)]
+    fn test_metadata_fixtures_unmigrated_field() {
+        MetadataFixtures::<Unmigrated>::new()
+            .fixture("v1", r#"name = "schneems""#)
+            .fixture(
+                "v2",
+                r#"
+                name = "schneems"
+                title = "Developer"
+                "#,
+            )
+            .assert_all();
+    }
+}
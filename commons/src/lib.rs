@@ -1,5 +1,6 @@
 pub mod cache;
 pub mod display;
+pub mod gem_requirement;
 pub mod gem_version;
 pub mod gemfile_lock;
 pub mod layer;
@@ -3,12 +3,17 @@ use serde::{Deserialize, Serialize};
 use sha2::Digest;
 use std::collections::HashMap;
 use std::fmt::Display;
+use std::os::unix::ffi::OsStrExt;
 use std::path::{Path, PathBuf};
 
 use crate::display::SentenceList;
 
 const PLATFORM_ENV_VAR: &str = "user configured environment variables";
 
+/// Recorded in place of a SHA when a tracked path is not on disk. Not a valid
+/// hex digest, so it can never collide with the digest of real contents.
+const ABSENT: &str = "absent";
+
 /// Store digest data in a Layer's metadata and compare them later
 ///
 /// Store this struct as a field in the last value of your Layer's metadata.
@@ -172,6 +177,30 @@ impl MetadataDigest {
         })
     }
 
+    /// Create new from the platform env and a list of tracked paths
+    ///
+    /// Unlike [`MetadataDigest::new_env_files`] this can track directories,
+    /// glob patterns, and files that might not exist. See [`TrackedPath`]
+    /// for details.
+    ///
+    /// # Errors
+    ///
+    /// Errors if a tracked file or directory exists but cannot be read from disk
+    /// or if a glob pattern is invalid.
+    pub fn new_env_paths(
+        platform: &impl Platform,
+        paths: &[TrackedPath],
+    ) -> Result<Self, DigestError> {
+        let env = PlatformEnvDigest::new(platform);
+        let mut files = PathsDigest::default();
+        files.add_tracked(paths)?;
+
+        Ok(MetadataDigest {
+            platform_env: Some(env),
+            files: Some(files),
+        })
+    }
+
     /// Show difference between old and current metadata digest
     ///
     /// If no differences: None, Otherwise Some(Changed)
@@ -237,10 +266,56 @@ impl MetadataDigest {
     }
 }
 
+/// A path, or set of paths, whose contents are tracked by a [`MetadataDigest`]
+///
+/// All variants are recorded by a single key in the digest, the path (or pattern)
+/// given, so the same value must be used between builds to be compared.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TrackedPath {
+    /// A single file that must exist on disk
+    File(PathBuf),
+
+    /// A single file that may not exist such as `.bundle/config`
+    ///
+    /// When absent, that state is recorded so adding or removing the file
+    /// is detected as a change.
+    OptionalFile(PathBuf),
+
+    /// All files in a directory, recursively, such as `vendor/cache`
+    ///
+    /// File names relative to the directory and their contents are
+    /// tracked. A missing directory is recorded as absent.
+    Dir(PathBuf),
+
+    /// All files matching a glob pattern such as `/workspace/vendor/cache/**`
+    ///
+    /// Patterns should be absolute, relative patterns are resolved against the
+    /// current working directory. A pattern that matches no files is tracked
+    /// as empty.
+    Glob(String),
+}
+
+impl TrackedPath {
+    fn key(&self) -> PathBuf {
+        match self {
+            TrackedPath::File(path) | TrackedPath::OptionalFile(path) | TrackedPath::Dir(path) => {
+                path.clone()
+            }
+            TrackedPath::Glob(pattern) => PathBuf::from(pattern),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, Hash, PartialEq, Eq)]
 #[serde(deny_unknown_fields)]
 struct ShaString(String);
 
+impl ShaString {
+    fn absent() -> Self {
+        ShaString(String::from(ABSENT))
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, Hash, PartialEq, Eq)]
 #[serde(deny_unknown_fields)]
 struct PlatformEnvDigest(ShaString);
@@ -411,11 +486,33 @@ impl PathsDigest {
 
     fn add_paths(&mut self, paths: &[&Path]) -> Result<&mut Self, DigestError> {
         for path in paths {
-            let contents = fs_err::read_to_string(path)
-                .map_err(|error| DigestError::CannotReadFile(path.to_path_buf(), error))?;
+            self.0.insert(path.to_path_buf(), sha_from_file(path)?);
+        }
+
+        Ok(self)
+    }
 
-            self.0
-                .insert(path.to_path_buf(), sha_from_string(&contents));
+    fn add_tracked(&mut self, paths: &[TrackedPath]) -> Result<&mut Self, DigestError> {
+        for tracked in paths {
+            let sha = match tracked {
+                TrackedPath::File(path) => sha_from_file(path)?,
+                TrackedPath::OptionalFile(path) => {
+                    if exists(path)? {
+                        sha_from_file(path)?
+                    } else {
+                        ShaString::absent()
+                    }
+                }
+                TrackedPath::Dir(path) => {
+                    if exists(path)? {
+                        sha_from_dir(path)?
+                    } else {
+                        ShaString::absent()
+                    }
+                }
+                TrackedPath::Glob(pattern) => sha_from_glob(pattern)?,
+            };
+            self.0.insert(tracked.key(), sha);
         }
 
         Ok(self)
@@ -426,6 +523,9 @@ impl PathsDigest {
 pub enum DigestError {
     #[error("Attempted to read file for digest but cannot: {1}")]
     CannotReadFile(PathBuf, std::io::Error),
+
+    #[error("Invalid glob pattern {0} for digest: {1}")]
+    InvalidGlob(String, glob::PatternError),
 }
 
 fn sha_from_env(env: &Env) -> ShaString {
@@ -441,6 +541,82 @@ fn sha_from_string(str: &str) -> ShaString {
     ShaString(format!("{:x}", hasher.finalize()))
 }
 
+/// Streams the contents of a file through the hasher
+///
+/// Binary safe, and the whole file is never held in memory. For UTF-8 files the
+/// result is identical to [`sha_from_string`] on the contents.
+fn sha_from_file(path: &Path) -> Result<ShaString, DigestError> {
+    let mut hasher = sha2::Sha256::new();
+    fs_err::File::open(path)
+        .and_then(|mut file| std::io::copy(&mut file, &mut hasher))
+        .map_err(|error| DigestError::CannotReadFile(path.to_path_buf(), error))?;
+
+    Ok(ShaString(format!("{:x}", hasher.finalize())))
+}
+
+/// Hashes the names (relative to the directory) and contents of all files in a directory
+///
+/// Symlinks are not followed, the link target is hashed instead.
+fn sha_from_dir(dir: &Path) -> Result<ShaString, DigestError> {
+    let mut hasher = sha2::Sha256::new();
+    for entry in walkdir::WalkDir::new(dir).sort_by_file_name() {
+        let entry = entry.map_err(|error| {
+            let path = error.path().unwrap_or(dir).to_path_buf();
+            DigestError::CannotReadFile(path, error.into())
+        })?;
+        let relative = entry
+            .path()
+            .strip_prefix(dir)
+            .expect("Walkdir path should return path with prefix of called root");
+
+        if entry.file_type().is_symlink() {
+            let target = fs_err::read_link(entry.path())
+                .map_err(|error| DigestError::CannotReadFile(entry.path().to_path_buf(), error))?;
+            hash_entry(&mut hasher, relative, &format!("-> {}", target.display()));
+        } else if entry.file_type().is_file() {
+            let ShaString(sha) = sha_from_file(entry.path())?;
+            hash_entry(&mut hasher, relative, &sha);
+        }
+    }
+
+    Ok(ShaString(format!("{:x}", hasher.finalize())))
+}
+
+/// Hashes the names and contents of all files matching a glob pattern
+fn sha_from_glob(pattern: &str) -> Result<ShaString, DigestError> {
+    let mut hasher = sha2::Sha256::new();
+    let mut paths = glob::glob(pattern)
+        .map_err(|error| DigestError::InvalidGlob(pattern.to_string(), error))?
+        .map(|entry| {
+            entry.map_err(|error| {
+                DigestError::CannotReadFile(error.path().to_path_buf(), error.into_error())
+            })
+        })
+        .collect::<Result<Vec<PathBuf>, DigestError>>()?;
+    paths.sort();
+
+    for path in paths.iter().filter(|path| path.is_file()) {
+        let ShaString(sha) = sha_from_file(path)?;
+        hash_entry(&mut hasher, path, &sha);
+    }
+
+    Ok(ShaString(format!("{:x}", hasher.finalize())))
+}
+
+/// Records a path and its contents digest so renames are detected
+/// along with content changes
+fn hash_entry(hasher: &mut sha2::Sha256, path: &Path, contents: &str) {
+    hasher.update(path.as_os_str().as_bytes());
+    hasher.update([0]);
+    hasher.update(contents);
+    hasher.update([b'\n']);
+}
+
+fn exists(path: &Path) -> Result<bool, DigestError> {
+    fs_err::PathExt::fs_err_try_exists(path)
+        .map_err(|error| DigestError::CannotReadFile(path.to_path_buf(), error))
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -558,4 +734,133 @@ mod test {
             format!("{}", one.changed(&two).unwrap())
         );
     }
+
+    #[test]
+    fn metadata_digest_binary_file() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let file = tempdir.path().join("nokogiri.gem");
+        fs_err::write(&file, [0xff, 0xfe, 0x00, 0x01]).unwrap();
+        let context = FakeContext::default();
+
+        let one = MetadataDigest::new_env_files(&context.platform, &[&file]).unwrap();
+        fs_err::write(&file, [0xff, 0xfe, 0x00, 0x02]).unwrap();
+        let two = MetadataDigest::new_env_files(&context.platform, &[&file]).unwrap();
+
+        assert_eq!(
+            one.changed(&two).unwrap().files.unwrap(),
+            PathChange::ChangedFiles(vec![file])
+        );
+    }
+
+    #[test]
+    fn metadata_digest_file_sha_matches_string_sha() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let gemfile = tempdir.path().join("Gemfile");
+        fs_err::write(&gemfile, "iamagemfile").unwrap();
+
+        assert_eq!(
+            sha_from_string("iamagemfile"),
+            sha_from_file(&gemfile).unwrap()
+        );
+    }
+
+    #[test]
+    fn metadata_digest_optional_file() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let config = tempdir.path().join(".bundle").join("config");
+        let context = FakeContext::default();
+        let tracked = [TrackedPath::OptionalFile(config.clone())];
+
+        let absent = MetadataDigest::new_env_paths(&context.platform, &tracked).unwrap();
+        assert_eq!(
+            absent.files.as_ref().unwrap().0.get(&config),
+            Some(&ShaString::absent())
+        );
+        assert_eq!(
+            absent.changed(&MetadataDigest::new_env_paths(&context.platform, &tracked).unwrap()),
+            None
+        );
+
+        fs_err::create_dir_all(config.parent().unwrap()).unwrap();
+        fs_err::write(&config, "BUNDLE_FROZEN: \"true\"").unwrap();
+        let present = MetadataDigest::new_env_paths(&context.platform, &tracked).unwrap();
+
+        assert_eq!(
+            present.changed(&absent).unwrap().files.unwrap(),
+            PathChange::ChangedFiles(vec![config])
+        );
+    }
+
+    #[test]
+    fn metadata_digest_dir() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let cache = tempdir.path().join("vendor").join("cache");
+        let context = FakeContext::default();
+        let tracked = [TrackedPath::Dir(cache.clone())];
+
+        let absent = MetadataDigest::new_env_paths(&context.platform, &tracked).unwrap();
+
+        fs_err::create_dir_all(&cache).unwrap();
+        let empty = MetadataDigest::new_env_paths(&context.platform, &tracked).unwrap();
+        assert!(empty.changed(&absent).is_some());
+
+        fs_err::write(cache.join("rake-13.0.6.gem"), "rake").unwrap();
+        let one = MetadataDigest::new_env_paths(&context.platform, &tracked).unwrap();
+        assert!(one.changed(&empty).is_some());
+        assert_eq!(
+            one.changed(&MetadataDigest::new_env_paths(&context.platform, &tracked).unwrap()),
+            None
+        );
+
+        // Same contents, different name
+        fs_err::rename(cache.join("rake-13.0.6.gem"), cache.join("rake-13.0.7.gem")).unwrap();
+        let two = MetadataDigest::new_env_paths(&context.platform, &tracked).unwrap();
+        assert_eq!(
+            two.changed(&one).unwrap().files.unwrap(),
+            PathChange::ChangedFiles(vec![cache.clone()])
+        );
+
+        // Nested files are tracked
+        fs_err::create_dir_all(cache.join("nested")).unwrap();
+        fs_err::write(cache.join("nested").join("file"), "contents").unwrap();
+        let three = MetadataDigest::new_env_paths(&context.platform, &tracked).unwrap();
+        assert!(three.changed(&two).is_some());
+    }
+
+    #[test]
+    fn metadata_digest_glob() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let dir = tempdir.path();
+        let pattern = format!("{}/*.gemspec", dir.display());
+        let context = FakeContext::default();
+        let tracked = [TrackedPath::Glob(pattern.clone())];
+
+        let none = MetadataDigest::new_env_paths(&context.platform, &tracked).unwrap();
+
+        fs_err::write(dir.join("ignored.rb"), "puts 'hi'").unwrap();
+        let ignored = MetadataDigest::new_env_paths(&context.platform, &tracked).unwrap();
+        assert_eq!(ignored.changed(&none), None);
+
+        fs_err::write(dir.join("my_gem.gemspec"), "Gem::Specification.new").unwrap();
+        let one = MetadataDigest::new_env_paths(&context.platform, &tracked).unwrap();
+        assert_eq!(
+            one.changed(&none).unwrap().files.unwrap(),
+            PathChange::ChangedFiles(vec![PathBuf::from(&pattern)])
+        );
+        assert_eq!(
+            one.checked_list(),
+            vec![pattern, String::from(PLATFORM_ENV_VAR)]
+        );
+    }
+
+    #[test]
+    fn metadata_digest_invalid_glob() {
+        let context = FakeContext::default();
+        let result = MetadataDigest::new_env_paths(
+            &context.platform,
+            &[TrackedPath::Glob(String::from("***"))],
+        );
+
+        assert!(matches!(result, Err(DigestError::InvalidGlob(_, _))));
+    }
 }